@@ -332,6 +332,24 @@ begin
   puts "end"
   puts "```\n"
 
+  # Example: Sync to Amazon S3 or an S3-compatible store
+  puts "Example: Sync to Amazon S3 (or MinIO, with AWS_ENDPOINT_URL set at startup)"
+  puts "```ruby"
+  puts "begin"
+  puts "  replica.sync_to_aws("
+  puts "    region: 'us-east-1',"
+  puts "    bucket: 'my-taskwarrior-bucket',"
+  puts "    access_key_id: 'your-access-key-id',"
+  puts "    secret_access_key: 'your-secret-access-key',"
+  puts "    encryption_secret: 'your-encryption-secret',"
+  puts "    avoid_snapshots: false"
+  puts "  )"
+  puts "  puts '✓ Successfully synced to S3'"
+  puts "rescue Taskchampion::Error => e"
+  puts "  puts '✗ S3 sync failed: #{e.message}'"
+  puts "end"
+  puts "```\n"
+
   # ========================================
   # 5. SNAPSHOT MANAGEMENT
   # ========================================
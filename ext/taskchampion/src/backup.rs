@@ -0,0 +1,72 @@
+use rusqlite::{Connection, OpenFlags};
+use std::path::Path;
+
+/// The file TaskChampion keeps its database in, inside the replica directory
+pub const DB_FILE: &str = "taskchampion.sqlite3";
+
+/// Tables every TaskChampion database has, used to reject other files
+const REQUIRED_TABLES: &[&str] = &["operations", "sync_meta", "tasks", "working_set"];
+
+/// Write a consistent, compacted copy of the SQLite database at `source` to
+/// `destination` with `VACUUM INTO`. SQLite reads from a single snapshot, so
+/// writes made by other connections while the copy runs are either wholly
+/// included or left out.
+pub fn vacuum_into(source: &Path, destination: &Path) -> Result<(), String> {
+    if destination.exists() {
+        return Err(format!("Backup destination {} already exists", destination.display()));
+    }
+    let destination = destination
+        .to_str()
+        .ok_or_else(|| format!("Backup destination {} is not valid UTF-8", destination.display()))?;
+
+    let con = Connection::open_with_flags(source, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Cannot open {}: {}", source.display(), e))?;
+    con.execute("VACUUM INTO ?1", [destination])
+        .map_err(|e| format!("Backup failed: {}", e))?;
+    Ok(())
+}
+
+/// Restore the backup at `backup` as the database of a new replica in
+/// `taskdb_dir`, which must not already contain one
+pub fn restore(backup: &Path, taskdb_dir: &Path) -> Result<(), String> {
+    if !backup.is_file() {
+        return Err(format!("Backup {} does not exist", backup.display()));
+    }
+    check_backup(backup)?;
+
+    std::fs::create_dir_all(taskdb_dir)
+        .map_err(|e| format!("Cannot create directory {}: {}", taskdb_dir.display(), e))?;
+    let db_file = taskdb_dir.join(DB_FILE);
+    if db_file.exists() {
+        return Err(format!("{} already contains a task database", taskdb_dir.display()));
+    }
+    vacuum_into(backup, &db_file)
+}
+
+/// Check `path` is an intact TaskChampion database
+fn check_backup(path: &Path) -> Result<(), String> {
+    let invalid = |detail: String| format!("{} is not a valid TaskChampion backup: {}", path.display(), detail);
+
+    let con = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| invalid(e.to_string()))?;
+    let integrity: String = con
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(|e| invalid(e.to_string()))?;
+    if integrity != "ok" {
+        return Err(invalid(integrity));
+    }
+
+    for table in REQUIRED_TABLES {
+        let found: bool = con
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+                [table],
+                |row| row.get(0),
+            )
+            .map_err(|e| invalid(e.to_string()))?;
+        if !found {
+            return Err(invalid(format!("missing table '{}'", table)));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,353 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use taskchampion::{DependencyMap as TCDependencyMap, Operation as TCOperation, Replica as TCReplica, Uuid};
+
+/// The dependency map as an adjacency list. TaskChampion only answers
+/// questions about one task at a time, so the graph is built by asking about
+/// every task in the working set, which is where the map's edges come from.
+pub struct Graph {
+    /// Each task's dependencies, sorted. Dependencies outside the working
+    /// set are present with no dependencies of their own.
+    dependencies: BTreeMap<Uuid, Vec<Uuid>>,
+}
+
+impl Graph {
+    pub fn new(depmap: &TCDependencyMap, tasks: impl IntoIterator<Item = Uuid>) -> Self {
+        let mut dependencies: BTreeMap<Uuid, Vec<Uuid>> = BTreeMap::new();
+        for uuid in tasks {
+            let mut deps: Vec<Uuid> = depmap.dependencies(uuid).collect();
+            deps.sort();
+            deps.dedup();
+            for dep in &deps {
+                dependencies.entry(*dep).or_default();
+            }
+            dependencies.insert(uuid, deps);
+        }
+        Graph { dependencies }
+    }
+
+    fn dependencies_of(&self, uuid: Uuid) -> &[Uuid] {
+        self.dependencies.get(&uuid).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Tasks that depend on another task or are depended on
+    pub fn connected_tasks(&self) -> BTreeSet<Uuid> {
+        let mut tasks = BTreeSet::new();
+        for (uuid, deps) in &self.dependencies {
+            if !deps.is_empty() {
+                tasks.insert(*uuid);
+                tasks.extend(deps.iter().copied());
+            }
+        }
+        tasks
+    }
+
+    /// Depth-first post-order from each of `roots` in turn, so every task
+    /// comes after its dependencies. Returns the tasks of a cycle, in
+    /// dependency order and starting and ending with the same task, if one
+    /// is reachable.
+    fn post_order(&self, roots: impl IntoIterator<Item = Uuid>) -> Result<Vec<Uuid>, Vec<Uuid>> {
+        let mut order = Vec::new();
+        let mut done = HashSet::new();
+        let mut on_path: HashSet<Uuid> = HashSet::new();
+
+        for root in roots {
+            if done.contains(&root) {
+                continue;
+            }
+            // (task, index of the next dependency to visit); iterative so
+            // long chains cannot overflow the stack
+            let mut path: Vec<(Uuid, usize)> = vec![(root, 0)];
+            on_path.insert(root);
+
+            while let Some((uuid, next)) = path.last_mut() {
+                let uuid = *uuid;
+                match self.dependencies_of(uuid).get(*next) {
+                    Some(&dep) => {
+                        *next += 1;
+                        if on_path.contains(&dep) {
+                            let start = path.iter().position(|(u, _)| *u == dep).unwrap_or(0);
+                            let mut cycle: Vec<Uuid> = path[start..].iter().map(|(u, _)| *u).collect();
+                            cycle.push(dep);
+                            return Err(cycle);
+                        }
+                        if !done.contains(&dep) {
+                            on_path.insert(dep);
+                            path.push((dep, 0));
+                        }
+                    }
+                    None => {
+                        path.pop();
+                        on_path.remove(&uuid);
+                        done.insert(uuid);
+                        order.push(uuid);
+                    }
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    /// `uuids` ordered so each task comes after everything it depends on,
+    /// including through tasks not in `uuids`, otherwise keeping the given
+    /// order
+    pub fn topological_order(&self, uuids: &[Uuid]) -> Result<Vec<Uuid>, Vec<Uuid>> {
+        // Any cycle reachable from `uuids` makes an order impossible
+        self.post_order(uuids.iter().copied())?;
+
+        let mut seen = HashSet::new();
+        let wanted: Vec<Uuid> = uuids.iter().copied().filter(|uuid| seen.insert(*uuid)).collect();
+        let mut waiting_on: Vec<HashSet<Uuid>> = wanted
+            .iter()
+            .map(|uuid| {
+                self.transitive_dependencies(*uuid)
+                    .into_iter()
+                    .filter(|dep| seen.contains(dep))
+                    .collect()
+            })
+            .collect();
+
+        // Repeatedly take the earliest task with nothing left to wait for
+        let mut remaining: Vec<usize> = (0..wanted.len()).collect();
+        let mut order = Vec::with_capacity(wanted.len());
+        while let Some(position) = remaining.iter().position(|i| waiting_on[*i].is_empty()) {
+            let uuid = wanted[remaining.remove(position)];
+            for i in &remaining {
+                waiting_on[*i].remove(&uuid);
+            }
+            order.push(uuid);
+        }
+        Ok(order)
+    }
+
+    /// Groups of tasks that depend on each other in a circle (strongly
+    /// connected components with more than one task, or a task depending on
+    /// itself), each sorted, found with Tarjan's algorithm
+    pub fn cycles(&self) -> Vec<Vec<Uuid>> {
+        let mut index_of: HashMap<Uuid, usize> = HashMap::new();
+        let mut low_link: HashMap<Uuid, usize> = HashMap::new();
+        let mut stack: Vec<Uuid> = Vec::new();
+        let mut on_stack: HashSet<Uuid> = HashSet::new();
+        let mut cycles = Vec::new();
+
+        for &root in self.dependencies.keys() {
+            if index_of.contains_key(&root) {
+                continue;
+            }
+            let mut path: Vec<(Uuid, usize)> = vec![(root, 0)];
+            index_of.insert(root, index_of.len());
+            low_link.insert(root, index_of[&root]);
+            stack.push(root);
+            on_stack.insert(root);
+
+            while let Some((uuid, next)) = path.last_mut() {
+                let uuid = *uuid;
+                if let Some(&dep) = self.dependencies_of(uuid).get(*next) {
+                    *next += 1;
+                    if !index_of.contains_key(&dep) {
+                        index_of.insert(dep, index_of.len());
+                        low_link.insert(dep, index_of[&dep]);
+                        stack.push(dep);
+                        on_stack.insert(dep);
+                        path.push((dep, 0));
+                    } else if on_stack.contains(&dep) {
+                        let low = low_link[&uuid].min(index_of[&dep]);
+                        low_link.insert(uuid, low);
+                    }
+                    continue;
+                }
+
+                path.pop();
+                if let Some((parent, _)) = path.last() {
+                    let low = low_link[parent].min(low_link[&uuid]);
+                    low_link.insert(*parent, low);
+                }
+                if low_link[&uuid] == index_of[&uuid] {
+                    let mut component = Vec::new();
+                    while let Some(member) = stack.pop() {
+                        on_stack.remove(&member);
+                        component.push(member);
+                        if member == uuid {
+                            break;
+                        }
+                    }
+                    if component.len() > 1 || self.dependencies_of(uuid).contains(&uuid) {
+                        component.sort();
+                        cycles.push(component);
+                    }
+                }
+            }
+        }
+
+        cycles.sort();
+        cycles
+    }
+
+    /// Everything `uuid` depends on, directly or indirectly, nearest first
+    pub fn transitive_dependencies(&self, uuid: Uuid) -> Vec<Uuid> {
+        let mut seen = HashSet::from([uuid]);
+        let mut queue = VecDeque::from([uuid]);
+        let mut found = Vec::new();
+        while let Some(current) = queue.pop_front() {
+            for &dep in self.dependencies_of(current) {
+                if seen.insert(dep) {
+                    found.push(dep);
+                    queue.push_back(dep);
+                }
+            }
+        }
+        found
+    }
+
+    /// The chain of dependencies taking longest to work through, given each
+    /// task's duration in seconds, in the order the tasks must be done, with
+    /// its total duration. Ties go to the lowest UUIDs.
+    pub fn critical_path(&self, durations: &HashMap<Uuid, u64>) -> Result<(Vec<Uuid>, u64), Vec<Uuid>> {
+        let order = self.post_order(self.dependencies.keys().copied())?;
+
+        // Longest total duration of a chain ending at each task, and the
+        // dependency it continues from
+        let mut finish: HashMap<Uuid, u64> = HashMap::new();
+        let mut previous: HashMap<Uuid, Uuid> = HashMap::new();
+        for &uuid in &order {
+            let mut longest = 0;
+            for &dep in self.dependencies_of(uuid) {
+                if finish[&dep] > longest || !previous.contains_key(&uuid) {
+                    longest = finish[&dep];
+                    previous.insert(uuid, dep);
+                }
+            }
+            let duration = durations.get(&uuid).copied().unwrap_or(0);
+            finish.insert(uuid, longest.saturating_add(duration));
+        }
+
+        let Some((&last, &total)) = finish
+            .iter()
+            .max_by(|(a_uuid, a), (b_uuid, b)| a.cmp(b).then(b_uuid.cmp(a_uuid)))
+        else {
+            return Ok((Vec::new(), 0));
+        };
+        let mut path = vec![last];
+        while let Some(&dep) = previous.get(path.last().unwrap_or(&last)) {
+            path.push(dep);
+        }
+        path.reverse();
+        Ok((path, total))
+    }
+
+    /// A Graphviz digraph of the tasks with dependencies, with an edge from
+    /// each task to each task it depends on. Tasks are labelled from
+    /// `labels`, falling back to their UUIDs.
+    pub fn to_dot(&self, labels: &HashMap<Uuid, String>) -> String {
+        let mut dot = String::from("digraph dependencies {\n");
+        for uuid in self.connected_tasks() {
+            let label = labels.get(&uuid).cloned().unwrap_or_else(|| uuid.to_string());
+            dot.push_str(&format!("  \"{}\" [label=\"{}\"];\n", uuid, escape(&label)));
+        }
+        for (uuid, deps) in &self.dependencies {
+            for dep in deps {
+                dot.push_str(&format!("  \"{}\" -> \"{}\";\n", uuid, dep));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// A dependency cycle that committing `operations` would create, starting
+/// and ending with the task given the new dependency. Checked against the
+/// stored tasks rather than the map a `Task` was loaded with, so edges added
+/// since, or earlier in the same operations, are seen.
+pub fn new_cycle(tc_replica: &mut TCReplica, operations: &[TCOperation]) -> Result<Option<Vec<Uuid>>, taskchampion::Error> {
+    let dependency = |property: &str| property.strip_prefix("dep_").and_then(|dep| Uuid::parse_str(dep).ok());
+    let added: Vec<(Uuid, Uuid)> = operations
+        .iter()
+        .filter_map(|operation| match operation {
+            TCOperation::Update { uuid, property, value: Some(_), .. } => dependency(property).map(|dep| (*uuid, dep)),
+            _ => None,
+        })
+        .collect();
+    if added.is_empty() {
+        return Ok(None);
+    }
+
+    // Dependencies of the tasks the operations touch, once they are applied
+    let mut changed: HashMap<Uuid, HashSet<Uuid>> = HashMap::new();
+    for operation in operations {
+        match operation {
+            TCOperation::Create { uuid } => {
+                changed.entry(*uuid).or_default();
+            }
+            TCOperation::Delete { uuid, .. } => {
+                changed.insert(*uuid, HashSet::new());
+            }
+            TCOperation::Update { uuid, property, value, .. } => {
+                let Some(dep) = dependency(property) else {
+                    continue;
+                };
+                if !changed.contains_key(uuid) {
+                    let stored = tc_replica
+                        .get_task_data(*uuid)?
+                        .map(|data| data.properties().filter_map(|p| dependency(p)).collect())
+                        .unwrap_or_default();
+                    changed.insert(*uuid, stored);
+                }
+                let deps = changed.entry(*uuid).or_default();
+                match value {
+                    Some(_) => deps.insert(dep),
+                    None => deps.remove(&dep),
+                };
+            }
+            _ => {}
+        }
+    }
+
+    let depmap = tc_replica.dependency_map(true)?;
+    let dependencies_of = |uuid: Uuid| -> Vec<Uuid> {
+        match changed.get(&uuid) {
+            Some(deps) => deps.iter().copied().collect(),
+            None => depmap.dependencies(uuid).collect(),
+        }
+    };
+
+    for (task, dep) in added {
+        // Later operations may have removed the dependency again
+        if !changed.get(&task).is_some_and(|deps| deps.contains(&dep)) {
+            continue;
+        }
+        let mut previous: HashMap<Uuid, Uuid> = HashMap::new();
+        let mut queue = VecDeque::from([dep]);
+        while let Some(current) = queue.pop_front() {
+            if current == task {
+                let mut path = vec![current];
+                while let Some(prev) = previous.get(path.last().unwrap_or(&task)) {
+                    path.push(*prev);
+                }
+                path.push(task);
+                path.reverse();
+                return Ok(Some(path));
+            }
+            for next in dependencies_of(current) {
+                if next != dep && !previous.contains_key(&next) {
+                    previous.insert(next, current);
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Escape text for a double-quoted DOT string
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
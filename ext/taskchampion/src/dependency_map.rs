@@ -1,22 +1,57 @@
 use magnus::{
-    class, method, prelude::*, Error, IntoValue, RArray, RModule,
+    class, gc::Marker, method, prelude::*, scan_args::{get_kwargs, scan_args}, typed_data::Obj, value::Opaque,
+    DataTypeFunctions, Error, IntoValue, RArray, RHash, RModule, Ruby, Symbol, TryConvert, TypedData, Value,
 };
+use std::collections::HashMap;
 use std::sync::Arc;
-use taskchampion::DependencyMap as TCDependencyMap;
+use taskchampion::{DependencyMap as TCDependencyMap, Uuid};
 
+use crate::dependency_graph::Graph;
+use crate::error::validation_error;
+use crate::recurrence;
+use crate::replica::Replica;
 use crate::thread_check::ThreadBound;
 use crate::util::{uuid2tc, vec_to_ruby};
 
-#[magnus::wrap(class = "Taskchampion::DependencyMap", free_immediately)]
-pub struct DependencyMap(ThreadBound<Arc<TCDependencyMap>>);
+/// Dependencies between the replica's pending tasks. Graph analysis runs
+/// over an adjacency list built when the map is read; task properties
+/// needed for it (durations, labels) are read through the replica.
+#[derive(TypedData)]
+#[magnus(class = "Taskchampion::DependencyMap", free_immediately, mark)]
+pub struct DependencyMap {
+    map: ThreadBound<Arc<TCDependencyMap>>,
+    graph: Graph,
+    replica: Opaque<Obj<Replica>>,
+}
+
+impl DataTypeFunctions for DependencyMap {
+    fn mark(&self, marker: &Marker) {
+        marker.mark(self.replica);
+    }
+}
+
+/// A ValidationError describing `cycle`, as returned by `Graph`
+fn cycle_error(cycle: Vec<Uuid>) -> Error {
+    let path: Vec<String> = cycle.iter().map(Uuid::to_string).collect();
+    Error::new(validation_error(), format!("Dependency cycle: {}", path.join(" -> ")))
+}
+
+fn uuids_to_ruby(uuids: Vec<Uuid>) -> Result<RArray, Error> {
+    vec_to_ruby(uuids, |uuid| Ok(uuid.to_string().into_value()))
+}
 
 impl DependencyMap {
-    pub fn from_tc_dependency_map(tc_dependency_map: Arc<TCDependencyMap>) -> Self {
-        DependencyMap(ThreadBound::new(tc_dependency_map))
+    pub fn new(tc_dependency_map: Arc<TCDependencyMap>, tasks: Vec<Uuid>, replica: Obj<Replica>) -> Self {
+        let graph = Graph::new(&tc_dependency_map, tasks);
+        DependencyMap {
+            map: ThreadBound::new(tc_dependency_map),
+            graph,
+            replica: replica.into(),
+        }
     }
 
     fn dependencies(&self, uuid: String) -> Result<RArray, Error> {
-        let dep_map = self.0.get()?;
+        let dep_map = self.map.get()?;
         let tc_uuid = uuid2tc(&uuid)?;
 
         let deps: Vec<String> = dep_map
@@ -28,7 +63,7 @@ impl DependencyMap {
     }
 
     fn dependents(&self, uuid: String) -> Result<RArray, Error> {
-        let dep_map = self.0.get()?;
+        let dep_map = self.map.get()?;
         let tc_uuid = uuid2tc(&uuid)?;
 
         let deps: Vec<String> = dep_map
@@ -40,7 +75,7 @@ impl DependencyMap {
     }
 
     fn has_dependency(&self, uuid: String) -> Result<bool, Error> {
-        let dep_map = self.0.get()?;
+        let dep_map = self.map.get()?;
         let tc_uuid = uuid2tc(&uuid)?;
 
         // Check if this UUID has any dependencies
@@ -48,6 +83,63 @@ impl DependencyMap {
         Ok(result)
     }
 
+    /// The given UUIDs reordered so each task comes after the tasks it
+    /// depends on, directly or not
+    fn topological_order(&self, uuids: Vec<String>) -> Result<RArray, Error> {
+        let tc_uuids = uuids.iter().map(|uuid| uuid2tc(uuid)).collect::<Result<Vec<_>, _>>()?;
+
+        let ordered = self.graph.topological_order(&tc_uuids).map_err(cycle_error)?;
+        uuids_to_ruby(ordered)
+    }
+
+    fn cycles(&self) -> Result<RArray, Error> {
+        vec_to_ruby(self.graph.cycles(), |cycle| Ok(uuids_to_ruby(cycle)?.into_value()))
+    }
+
+    fn transitive_dependencies(&self, uuid: String) -> Result<RArray, Error> {
+        let tc_uuid = uuid2tc(&uuid)?;
+        uuids_to_ruby(self.graph.transitive_dependencies(tc_uuid))
+    }
+
+    /// The longest chain of dependencies, weighting each task by the
+    /// duration in its `duration_uda` property
+    fn critical_path(ruby: &Ruby, rb_self: &Self, args: &[Value]) -> Result<RHash, Error> {
+        let args = scan_args::<(), (), (), (), RHash, ()>(args)?;
+        let kwargs = get_kwargs::<_, (Value,), (), ()>(args.keywords, &["duration_uda"], &[])?;
+        let (uda,) = kwargs.required;
+        let uda = match Symbol::from_value(uda) {
+            Some(symbol) => symbol.name()?.into_owned(),
+            None => String::try_convert(uda)?,
+        };
+
+        let tasks: Vec<Uuid> = rb_self.graph.connected_tasks().into_iter().collect();
+        let values = ruby.get_inner(rb_self.replica).task_values(&tasks, &uda)?;
+        let mut durations = HashMap::new();
+        for (uuid, value) in values {
+            let seconds = recurrence::parse_duration(&value).ok_or_else(|| {
+                Error::new(
+                    validation_error(),
+                    format!("Task {} has an invalid {} duration '{}'", uuid, uda, value),
+                )
+            })?;
+            durations.insert(uuid, seconds);
+        }
+
+        let (path, duration) = rb_self.graph.critical_path(&durations).map_err(cycle_error)?;
+        let hash = RHash::new();
+        hash.aset(Symbol::new("path"), uuids_to_ruby(path)?)?;
+        hash.aset(Symbol::new("duration"), duration)?;
+        Ok(hash)
+    }
+
+    /// Graphviz source for the tasks with dependencies, labelled with their
+    /// descriptions
+    fn to_dot(ruby: &Ruby, rb_self: &Self) -> Result<String, Error> {
+        let tasks: Vec<Uuid> = rb_self.graph.connected_tasks().into_iter().collect();
+        let labels = ruby.get_inner(rb_self.replica).task_values(&tasks, "description")?;
+        Ok(rb_self.graph.to_dot(&labels))
+    }
+
     fn inspect(&self) -> Result<String, Error> {
         Ok("#<Taskchampion::DependencyMap>".to_string())
     }
@@ -59,6 +151,11 @@ pub fn init(module: &RModule) -> Result<(), Error> {
     class.define_method("dependencies", method!(DependencyMap::dependencies, 1))?;
     class.define_method("dependents", method!(DependencyMap::dependents, 1))?;
     class.define_method("has_dependency?", method!(DependencyMap::has_dependency, 1))?;
+    class.define_method("topological_order", method!(DependencyMap::topological_order, 1))?;
+    class.define_method("cycles", method!(DependencyMap::cycles, 0))?;
+    class.define_method("transitive_dependencies", method!(DependencyMap::transitive_dependencies, 1))?;
+    class.define_method("critical_path", method!(DependencyMap::critical_path, -1))?;
+    class.define_method("to_dot", method!(DependencyMap::to_dot, 0))?;
     class.define_method("inspect", method!(DependencyMap::inspect, 0))?;
 
     Ok(())
@@ -1,4 +1,4 @@
-use magnus::{exception, prelude::*, Error, RModule};
+use magnus::{exception, method, prelude::*, Error, ExceptionClass, RArray, RModule, RObject, Symbol, Value};
 
 pub fn init_errors(module: &RModule) -> Result<(), Error> {
     let error_class = module.define_error("Error", exception::standard_error())?;
@@ -6,8 +6,15 @@ pub fn init_errors(module: &RModule) -> Result<(), Error> {
     module.define_error("StorageError", error_class)?;
     module.define_error("ValidationError", error_class)?;
     module.define_error("ConfigError", error_class)?;
+    module.define_error("HookError", error_class)?;
     let sync_error_class = module.define_error("SyncError", error_class)?;
     module.define_error("OutOfSyncError", sync_error_class)?;
+
+    error_class.define_method("kind", method!(error_kind, 0))?;
+    error_class.define_method("causes", method!(error_causes, 0))?;
+    error_class.define_method("retryable?", method!(error_retryable, 0))?;
+    sync_error_class.define_method("http_status", method!(sync_error_http_status, 0))?;
+    sync_error_class.define_method("url", method!(sync_error_url, 0))?;
     Ok(())
 }
 
@@ -43,6 +50,22 @@ pub fn sync_error() -> magnus::ExceptionClass {
         .expect("SyncError class not initialized")
 }
 
+pub fn config_error() -> magnus::ExceptionClass {
+    let ruby = magnus::Ruby::get().expect("Ruby not available");
+    let module = ruby.class_object().const_get::<_, RModule>("Taskchampion")
+        .expect("Taskchampion module not found");
+    module.const_get::<_, magnus::ExceptionClass>("ConfigError")
+        .expect("ConfigError class not initialized")
+}
+
+pub fn hook_error() -> magnus::ExceptionClass {
+    let ruby = magnus::Ruby::get().expect("Ruby not available");
+    let module = ruby.class_object().const_get::<_, RModule>("Taskchampion")
+        .expect("Taskchampion module not found");
+    module.const_get::<_, magnus::ExceptionClass>("HookError")
+        .expect("HookError class not initialized")
+}
+
 pub fn out_of_sync_error() -> magnus::ExceptionClass {
     let ruby = magnus::Ruby::get().expect("Ruby not available");
     let module = ruby.class_object().const_get::<_, RModule>("Taskchampion")
@@ -52,16 +75,201 @@ pub fn out_of_sync_error() -> magnus::ExceptionClass {
 }
 
 pub fn map_taskchampion_error(error: taskchampion::Error) -> Error {
-    match error {
-        taskchampion::Error::Database(msg) => Error::new(storage_error(), msg),
-        taskchampion::Error::Server(msg)   => Error::new(sync_error(), msg),
-        taskchampion::Error::OutOfSync     => Error::new(out_of_sync_error(),
-                                                 "Local replica is out of sync with the server"),
-        taskchampion::Error::Usage(msg)    => Error::new(validation_error(), msg),
+    let details = ErrorDetails::new(&error);
+    let message = match &error {
+        taskchampion::Error::Database(msg)
+        | taskchampion::Error::Server(msg)
+        | taskchampion::Error::Usage(msg) => msg.clone(),
         // anyhow's alternate format renders the full cause chain
         // ("context: cause: root cause"), not just the top context line.
-        taskchampion::Error::Other(err)    => Error::new(storage_error(),
-                                                 format!("{err:#}")),
-        _                                  => Error::new(storage_error(), error.to_string()),
+        taskchampion::Error::Other(err) => format!("{err:#}"),
+        _ => error.to_string(),
+    };
+    details.raise(error_class(&error), message)
+}
+
+/// The Ruby class for `error`. Replica::sync wraps server failures in
+/// context, so a TaskChampion error found in the cause chain decides the
+/// class of an `Other`.
+fn error_class(error: &taskchampion::Error) -> ExceptionClass {
+    match error {
+        taskchampion::Error::Database(_) => storage_error(),
+        taskchampion::Error::Server(_) => sync_error(),
+        taskchampion::Error::OutOfSync => out_of_sync_error(),
+        taskchampion::Error::Usage(_) => validation_error(),
+        taskchampion::Error::Other(err) => match err.chain().find_map(|e| e.downcast_ref::<taskchampion::Error>()) {
+            Some(inner) => error_class(inner),
+            None => storage_error(),
+        },
+        _ => storage_error(),
+    }
+}
+
+/// What a `taskchampion::Error` tells us about a failure beyond its message,
+/// exposed on the Ruby exception so callers don't have to parse messages
+pub struct ErrorDetails {
+    /// The `taskchampion::Error` variant, in snake case
+    kind: &'static str,
+    /// The error and its sources, outermost first
+    causes: Vec<String>,
+    /// Whether the same call might succeed if tried again
+    retryable: bool,
+    http_status: Option<u16>,
+    url: Option<String>,
+}
+
+impl ErrorDetails {
+    pub fn new(error: &taskchampion::Error) -> Self {
+        let details = |kind, cause: &str| ErrorDetails {
+            kind,
+            causes: vec![cause.to_string()],
+            retryable: false,
+            http_status: None,
+            url: None,
+        };
+
+        match error {
+            taskchampion::Error::Server(msg) => {
+                // TaskChampion flattens HTTP failures into a message, either
+                // "<url> responded with <status> <reason>" or ureq's
+                // "<url>: <transport failure>: <detail>"
+                if let Some((url, status)) = msg.rsplit_once(" responded with ") {
+                    let http_status = status.split_whitespace().next().and_then(|s| s.parse::<u16>().ok());
+                    ErrorDetails {
+                        retryable: http_status.is_some_and(|s| s == 408 || s == 429 || s >= 500),
+                        http_status,
+                        url: Some(url.to_string()),
+                        ..details("server", msg)
+                    }
+                } else {
+                    let (url, failure) = match msg.split_once(": ") {
+                        Some((url, rest)) if url.starts_with("http://") || url.starts_with("https://") => {
+                            (Some(url.to_string()), rest)
+                        }
+                        _ => (None, msg.as_str()),
+                    };
+                    ErrorDetails {
+                        retryable: TRANSIENT_TRANSPORT_FAILURES.iter().any(|f| failure.starts_with(f)),
+                        url,
+                        ..details("server", msg)
+                    }
+                }
+            }
+            taskchampion::Error::Database(msg) => details("database", msg),
+            taskchampion::Error::OutOfSync => details("out_of_sync", &error.to_string()),
+            taskchampion::Error::Usage(msg) => details("usage", msg),
+            taskchampion::Error::Other(err) => {
+                let mut causes: Vec<String> = err.chain().map(|cause| cause.to_string()).collect();
+                // Wrappers such as rusqlite's often repeat their source's message
+                causes.dedup();
+                let transient = err.chain().any(is_transient);
+                match err.chain().find_map(|e| e.downcast_ref::<taskchampion::Error>()) {
+                    Some(inner) => {
+                        let inner = ErrorDetails::new(inner);
+                        ErrorDetails { causes, retryable: inner.retryable || transient, ..inner }
+                    }
+                    None => ErrorDetails { causes, retryable: transient, ..details("other", "") },
+                }
+            }
+            _ => details("other", &error.to_string()),
+        }
     }
+
+    /// Build an exception of `class` carrying these details
+    fn raise(self, class: ExceptionClass, message: String) -> Error {
+        let exception = match class.new_instance((message.as_str(),)) {
+            Ok(exception) => exception,
+            Err(e) => return e,
+        };
+        let Some(object) = RObject::from_value(exception.as_value()) else {
+            return Error::from(exception);
+        };
+
+        let set = || -> Result<(), Error> {
+            object.ivar_set("@kind", Symbol::new(self.kind))?;
+            object.ivar_set("@causes", RArray::from_vec(self.causes))?;
+            object.ivar_set("@retryable", self.retryable)?;
+            object.ivar_set("@http_status", self.http_status)?;
+            object.ivar_set("@url", self.url)?;
+            Ok(())
+        };
+        match set() {
+            Ok(()) => Error::from(exception),
+            Err(e) => e,
+        }
+    }
+}
+
+/// ureq transport failures that may clear up on their own
+const TRANSIENT_TRANSPORT_FAILURES: &[&str] =
+    &["Dns Failed", "Connection Failed", "Network Error", "Proxy failed to connect"];
+
+/// Whether `error` is a busy database or an interrupted or timed-out
+/// connection rather than something that will fail the same way again
+fn is_transient(error: &(dyn std::error::Error + 'static)) -> bool {
+    use std::io::ErrorKind;
+
+    if let Some(rusqlite::Error::SqliteFailure(e, _)) = error.downcast_ref::<rusqlite::Error>() {
+        return matches!(e.code, rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked);
+    }
+    if let Some(e) = error.downcast_ref::<std::io::Error>() {
+        return matches!(
+            e.kind(),
+            ErrorKind::TimedOut
+                | ErrorKind::Interrupted
+                | ErrorKind::WouldBlock
+                | ErrorKind::ConnectionRefused
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+        );
+    }
+    false
+}
+
+/// `Error#kind`, falling back to one implied by the class for errors raised
+/// by the extension itself rather than by TaskChampion
+fn error_kind(rb_self: RObject) -> Result<Symbol, Error> {
+    if let Some(kind) = rb_self.ivar_get::<_, Option<Symbol>>("@kind")? {
+        return Ok(kind);
+    }
+    let kind = if rb_self.is_kind_of(out_of_sync_error()) {
+        "out_of_sync"
+    } else if rb_self.is_kind_of(sync_error()) {
+        "server"
+    } else if rb_self.is_kind_of(storage_error()) {
+        "database"
+    } else if rb_self.is_kind_of(validation_error()) {
+        "usage"
+    } else if rb_self.is_kind_of(thread_error()) {
+        "thread"
+    } else if rb_self.is_kind_of(config_error()) {
+        "config"
+    } else if rb_self.is_kind_of(hook_error()) {
+        "hook"
+    } else {
+        "other"
+    };
+    Ok(Symbol::new(kind))
+}
+
+fn error_causes(rb_self: RObject) -> Result<RArray, Error> {
+    match rb_self.ivar_get::<_, Option<RArray>>("@causes")? {
+        Some(causes) => Ok(causes),
+        None => {
+            let message: Value = rb_self.funcall("message", ())?;
+            Ok(RArray::from_slice(&[message]))
+        }
+    }
+}
+
+fn error_retryable(rb_self: RObject) -> Result<bool, Error> {
+    Ok(rb_self.ivar_get::<_, Option<bool>>("@retryable")?.unwrap_or(false))
+}
+
+fn sync_error_http_status(rb_self: RObject) -> Result<Option<u16>, Error> {
+    rb_self.ivar_get("@http_status")
+}
+
+fn sync_error_url(rb_self: RObject) -> Result<Option<String>, Error> {
+    rb_self.ivar_get("@url")
 }
@@ -0,0 +1,503 @@
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, TimeZone, Utc};
+use magnus::Error;
+use taskchampion::{DependencyMap as TCDependencyMap, Tag as TCTag, TaskData as TCTaskData};
+
+use crate::error::validation_error;
+
+/// Properties holding Unix timestamps, compared as dates rather than strings
+const DATE_ATTRIBUTES: &[&str] = &[
+    "due", "wait", "entry", "modified", "start", "end", "scheduled", "until",
+];
+
+/// A parsed Taskwarrior-style filter expression, such as
+/// `project:work +urgent due.before:eom status:pending`.
+///
+/// Terms are implicitly joined with `and`; `or`, `not` and parentheses are
+/// also supported, with `not` binding tightest and `or` loosest.
+pub struct Filter {
+    expr: Option<Expr>,
+}
+
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Attribute {
+        name: String,
+        modifier: Modifier,
+        value: String,
+        date: Option<DateOperand>,
+    },
+    Tag {
+        tag: TCTag,
+        name: String,
+        present: bool,
+    },
+    Word(String),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Modifier {
+    Default,
+    Is,
+    Isnt,
+    Has,
+    Hasnt,
+    StartsWith,
+    EndsWith,
+    Before,
+    After,
+    None,
+    Any,
+}
+
+impl Modifier {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "is" | "equals" => Modifier::Is,
+            "isnt" | "not" => Modifier::Isnt,
+            "has" | "contains" => Modifier::Has,
+            "hasnt" => Modifier::Hasnt,
+            "startswith" | "left" => Modifier::StartsWith,
+            "endswith" | "right" => Modifier::EndsWith,
+            "before" | "under" | "below" => Modifier::Before,
+            "after" | "over" | "above" => Modifier::After,
+            "none" => Modifier::None,
+            "any" => Modifier::Any,
+            _ => return None,
+        })
+    }
+}
+
+/// A date operand. Day-granularity values (`today`, `2024-01-31`) cover the
+/// whole local day for equality; `before`/`after` compare against `start`.
+#[derive(Clone, Copy)]
+struct DateOperand {
+    start: DateTime<Utc>,
+    end: Option<DateTime<Utc>>,
+}
+
+#[derive(PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Term(String),
+}
+
+/// State needed to evaluate synthetic tags that depend on other tasks
+pub struct FilterContext<'a> {
+    pub depmap: Option<&'a TCDependencyMap>,
+    pub now: DateTime<Utc>,
+}
+
+impl Filter {
+    pub fn parse(input: &str) -> Result<Self, Error> {
+        let tokens = tokenize(input)?;
+        let now = Local::now();
+        let mut parser = Parser { tokens, pos: 0, now };
+        if parser.tokens.is_empty() {
+            return Ok(Filter { expr: None });
+        }
+        let expr = parser.parse_or()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(filter_error("Unexpected ')' in filter"));
+        }
+        Ok(Filter { expr: Some(expr) })
+    }
+
+    /// True if only pending tasks can match, so the working set suffices
+    pub fn pending_only(&self) -> bool {
+        self.expr.as_ref().is_some_and(Expr::pending_only)
+    }
+
+    /// True if evaluating needs the replica's dependency map
+    pub fn needs_dependency_map(&self) -> bool {
+        self.expr.as_ref().is_some_and(Expr::needs_dependency_map)
+    }
+
+    pub fn matches(&self, task: &TCTaskData, ctx: &FilterContext) -> bool {
+        self.expr.as_ref().is_none_or(|expr| expr.matches(task, ctx))
+    }
+}
+
+impl Expr {
+    fn pending_only(&self) -> bool {
+        match self {
+            Expr::And(a, b) => a.pending_only() || b.pending_only(),
+            Expr::Attribute { name, modifier, value, .. } => {
+                name == "status"
+                    && matches!(modifier, Modifier::Default | Modifier::Is)
+                    && value == "pending"
+            }
+            Expr::Tag { name, present, .. } => *present && name == "PENDING",
+            _ => false,
+        }
+    }
+
+    fn needs_dependency_map(&self) -> bool {
+        match self {
+            Expr::And(a, b) | Expr::Or(a, b) => a.needs_dependency_map() || b.needs_dependency_map(),
+            Expr::Not(a) => a.needs_dependency_map(),
+            Expr::Tag { name, .. } => matches!(name.as_str(), "BLOCKED" | "UNBLOCKED" | "BLOCKING"),
+            _ => false,
+        }
+    }
+
+    fn matches(&self, task: &TCTaskData, ctx: &FilterContext) -> bool {
+        match self {
+            Expr::And(a, b) => a.matches(task, ctx) && b.matches(task, ctx),
+            Expr::Or(a, b) => a.matches(task, ctx) || b.matches(task, ctx),
+            Expr::Not(a) => !a.matches(task, ctx),
+            Expr::Tag { tag, name, present } => has_tag(task, tag, name, ctx) == *present,
+            Expr::Word(word) => {
+                task.get("description").is_some_and(|d| d.contains(word.as_str()))
+                    || task
+                        .iter()
+                        .any(|(k, v)| k.starts_with("annotation_") && v.contains(word.as_str()))
+            }
+            Expr::Attribute { name, modifier, value, date } => {
+                match date {
+                    Some(date) => match_date(attribute_timestamp(task, name), *modifier, date),
+                    None => match_string(name, attribute_value(task, name).as_deref(), *modifier, value),
+                }
+            }
+        }
+    }
+}
+
+pub fn has_tag(task: &TCTaskData, tag: &TCTag, name: &str, ctx: &FilterContext) -> bool {
+    if tag.is_user() {
+        return task.has(format!("tag_{}", name));
+    }
+    let status = task.get("status");
+    let uuid = task.get_uuid();
+    match name {
+        "PENDING" => status == Some("pending"),
+        "COMPLETED" => status == Some("completed"),
+        "DELETED" => status == Some("deleted"),
+        "ACTIVE" => task.has("start"),
+        "WAITING" => attribute_timestamp(task, "wait").is_some_and(|wait| wait > ctx.now),
+        "BLOCKED" => ctx.depmap.is_some_and(|dm| dm.dependencies(uuid).next().is_some()),
+        "UNBLOCKED" => ctx.depmap.is_none_or(|dm| dm.dependencies(uuid).next().is_none()),
+        "BLOCKING" => ctx.depmap.is_some_and(|dm| dm.dependents(uuid).next().is_some()),
+        _ => false,
+    }
+}
+
+fn attribute_value(task: &TCTaskData, name: &str) -> Option<String> {
+    match name {
+        "uuid" => Some(task.get_uuid().to_string()),
+        _ => task.get(name).map(str::to_string),
+    }
+}
+
+fn attribute_timestamp(task: &TCTaskData, name: &str) -> Option<DateTime<Utc>> {
+    task.get(name)
+        .and_then(|s| s.parse::<i64>().ok())
+        .and_then(|secs| DateTime::from_timestamp(secs, 0))
+}
+
+fn match_date(actual: Option<DateTime<Utc>>, modifier: Modifier, operand: &DateOperand) -> bool {
+    let equal = |ts: DateTime<Utc>| match operand.end {
+        Some(end) => ts >= operand.start && ts < end,
+        None => ts == operand.start,
+    };
+    match (modifier, actual) {
+        (Modifier::None, actual) => actual.is_none(),
+        (Modifier::Any, actual) => actual.is_some(),
+        (Modifier::Before, Some(ts)) => ts < operand.start,
+        (Modifier::After, Some(ts)) => ts > operand.start,
+        (Modifier::Isnt, Some(ts)) => !equal(ts),
+        (Modifier::Isnt, None) => true,
+        (_, Some(ts)) => equal(ts),
+        (_, None) => false,
+    }
+}
+
+fn priority_rank(priority: &str) -> Option<u8> {
+    match priority {
+        "" => Some(0),
+        "L" => Some(1),
+        "M" => Some(2),
+        "H" => Some(3),
+        _ => None,
+    }
+}
+
+fn match_string(name: &str, actual: Option<&str>, modifier: Modifier, value: &str) -> bool {
+    let present = actual.is_some_and(|a| !a.is_empty());
+    let actual = actual.unwrap_or("");
+    match modifier {
+        Modifier::None => !present,
+        Modifier::Any => present,
+        Modifier::Default | Modifier::Is if value.is_empty() => !present,
+        Modifier::Isnt if value.is_empty() => present,
+        // Projects are hierarchical: project:work also matches work.reports
+        Modifier::Default if name == "project" => {
+            actual == value || actual.strip_prefix(value).is_some_and(|rest| rest.starts_with('.'))
+        }
+        Modifier::Default if name == "description" => actual.contains(value),
+        Modifier::Default | Modifier::Is => actual == value,
+        Modifier::Isnt => actual != value,
+        Modifier::Has => actual.contains(value),
+        Modifier::Hasnt => !actual.contains(value),
+        Modifier::StartsWith => present && actual.starts_with(value),
+        Modifier::EndsWith => present && actual.ends_with(value),
+        Modifier::Before | Modifier::After => {
+            if !present && name != "priority" {
+                return false;
+            }
+            let ordering = if name == "priority" {
+                match (priority_rank(actual), priority_rank(value)) {
+                    (Some(a), Some(b)) => a.cmp(&b),
+                    _ => actual.cmp(value),
+                }
+            } else {
+                match (actual.parse::<f64>(), value.parse::<f64>()) {
+                    (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal),
+                    _ => actual.cmp(value),
+                }
+            };
+            if modifier == Modifier::Before {
+                ordering.is_lt()
+            } else {
+                ordering.is_gt()
+            }
+        }
+    }
+}
+
+fn filter_error(message: impl Into<String>) -> Error {
+    Error::new(validation_error(), message.into())
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    // Quoted terms are never treated as operators
+    let mut quoted = false;
+    let mut quote: Option<char> = None;
+
+    fn finish(tokens: &mut Vec<Token>, current: &mut String, quoted: &mut bool) {
+        if current.is_empty() && !*quoted {
+            return;
+        }
+        let term = std::mem::take(current);
+        let token = if *quoted {
+            Token::Term(term)
+        } else {
+            match term.as_str() {
+                "and" => Token::And,
+                "or" => Token::Or,
+                "not" => Token::Not,
+                _ => Token::Term(term),
+            }
+        };
+        tokens.push(token);
+        *quoted = false;
+    }
+
+    for c in input.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => current.push(c),
+            None => match c {
+                '"' | '\'' => {
+                    quote = Some(c);
+                    quoted = true;
+                }
+                '(' | ')' => {
+                    finish(&mut tokens, &mut current, &mut quoted);
+                    tokens.push(if c == '(' { Token::LParen } else { Token::RParen });
+                }
+                c if c.is_whitespace() => finish(&mut tokens, &mut current, &mut quoted),
+                c => current.push(c),
+            },
+        }
+    }
+    if quote.is_some() {
+        return Err(filter_error("Unterminated quote in filter"));
+    }
+    finish(&mut tokens, &mut current, &mut quoted);
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    now: DateTime<Local>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            let rhs = self.parse_and()?;
+            expr = Expr::Or(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.parse_unary()?;
+        loop {
+            match self.peek() {
+                Some(Token::And) => self.pos += 1,
+                // Adjacent terms are implicitly and-ed
+                Some(Token::LParen) | Some(Token::Not) | Some(Token::Term(_)) => {}
+                _ => break,
+            }
+            let rhs = self.parse_unary()?;
+            expr = Expr::And(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, Error> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, Error> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        match token {
+            Some(Token::LParen) => {
+                let expr = self.parse_or()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err(filter_error("Missing ')' in filter"));
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            Some(Token::Term(term)) => {
+                let term = term.clone();
+                self.parse_term(&term)
+            }
+            Some(Token::RParen) => Err(filter_error("Unexpected ')' in filter")),
+            Some(Token::And) | Some(Token::Or) | Some(Token::Not) | None => {
+                Err(filter_error("Filter has an operator without an operand"))
+            }
+        }
+    }
+
+    fn parse_term(&self, term: &str) -> Result<Expr, Error> {
+        if let Some(rest) = term.strip_prefix('+').or_else(|| term.strip_prefix('-')) {
+            if !rest.is_empty() {
+                let tag: TCTag = rest
+                    .parse()
+                    .map_err(|_| filter_error(format!("Invalid tag in filter: '{}'", rest)))?;
+                return Ok(Expr::Tag {
+                    tag,
+                    name: rest.to_string(),
+                    present: term.starts_with('+'),
+                });
+            }
+        }
+
+        if let Some((key, value)) = term.split_once(':') {
+            let is_name = |s: &str| {
+                s.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                    && s.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+            };
+            if is_name(key) {
+                // UDA names may contain dots, so only a known trailing modifier is split off
+                let (name, modifier) = key
+                    .rsplit_once('.')
+                    .and_then(|(name, m)| Modifier::from_name(m).map(|m| (name, m)))
+                    .unwrap_or((key, Modifier::Default));
+                let date = if DATE_ATTRIBUTES.contains(&name)
+                    && !value.is_empty()
+                    && !matches!(modifier, Modifier::None | Modifier::Any)
+                {
+                    Some(self.parse_date(value).ok_or_else(|| {
+                        filter_error(format!("Invalid date '{}' for attribute '{}'", value, name))
+                    })?)
+                } else {
+                    None
+                };
+                return Ok(Expr::Attribute {
+                    name: name.to_string(),
+                    modifier,
+                    value: value.to_string(),
+                    date,
+                });
+            }
+        }
+
+        Ok(Expr::Word(term.to_string()))
+    }
+
+    /// Parse a named date (`now`, `today`, `eom`, ...), an ISO 8601 date or
+    /// datetime, or a Taskwarrior compact datetime (`20240131T120000Z`).
+    ///
+    /// Named "end" dates (`eod`, `eow`, `eom`, `eoy`) are the first instant of
+    /// the following period, so `due.before:eom` includes the whole month.
+    /// Weeks start on Sunday, as with Taskwarrior's default `weekstart`.
+    fn parse_date(&self, value: &str) -> Option<DateOperand> {
+        let today = self.now.date_naive();
+        let day = |date: NaiveDate| DateOperand {
+            start: local_midnight(date),
+            end: Some(local_midnight(date + Duration::days(1))),
+        };
+        let instant = |date: NaiveDate| DateOperand { start: local_midnight(date), end: None };
+        let week_start = today - Duration::days(today.weekday().num_days_from_sunday() as i64);
+        let month_start = today.with_day(1)?;
+        let next_month = if today.month() == 12 {
+            NaiveDate::from_ymd_opt(today.year() + 1, 1, 1)?
+        } else {
+            NaiveDate::from_ymd_opt(today.year(), today.month() + 1, 1)?
+        };
+        let year_start = NaiveDate::from_ymd_opt(today.year(), 1, 1)?;
+
+        let named = match value {
+            "now" => Some(DateOperand { start: self.now.with_timezone(&Utc), end: None }),
+            "today" | "sod" => Some(day(today)),
+            "yesterday" => Some(day(today - Duration::days(1))),
+            "tomorrow" => Some(day(today + Duration::days(1))),
+            "eod" => Some(instant(today + Duration::days(1))),
+            "sow" => Some(instant(week_start)),
+            "eow" => Some(instant(week_start + Duration::days(7))),
+            "som" => Some(instant(month_start)),
+            "eom" => Some(instant(next_month)),
+            "soy" => Some(instant(year_start)),
+            "eoy" => Some(instant(NaiveDate::from_ymd_opt(today.year() + 1, 1, 1)?)),
+            _ => None,
+        };
+        if named.is_some() {
+            return named;
+        }
+
+        if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+            return Some(day(date));
+        }
+        if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+            return Some(DateOperand { start: dt.with_timezone(&Utc), end: None });
+        }
+        if let Ok(dt) = chrono::NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ") {
+            return Some(DateOperand { start: dt.and_utc(), end: None });
+        }
+        None
+    }
+}
+
+fn local_midnight(date: NaiveDate) -> DateTime<Utc> {
+    let naive = date.and_hms_opt(0, 0, 0).expect("midnight is always valid");
+    // DST transitions can skip midnight; fall back to treating it as UTC
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|dt| dt.with_timezone(&Utc))
+        .unwrap_or_else(|| naive.and_utc())
+}
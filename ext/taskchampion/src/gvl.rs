@@ -0,0 +1,87 @@
+use magnus::{Error, Ruby};
+use std::cell::RefCell;
+use std::ffi::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+struct BlockingCall<F, R> {
+    func: Option<F>,
+    result: Option<std::thread::Result<R>>,
+}
+
+thread_local! {
+    /// The interrupt flag of the `without_gvl` call running on this thread
+    static INTERRUPT: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+unsafe extern "C" fn call_blocking<F, R>(data: *mut c_void) -> *mut c_void
+where
+    F: FnOnce() -> R,
+{
+    let call = &mut *(data as *mut BlockingCall<F, R>);
+    if let Some(func) = call.func.take() {
+        // Panics must not unwind into Ruby's C frames; resume them once the GVL is back
+        call.result = Some(panic::catch_unwind(AssertUnwindSafe(func)));
+    }
+    std::ptr::null_mut()
+}
+
+/// Ruby's unblocking function: called from another thread, possibly more
+/// than once, when the blocked thread is interrupted
+unsafe extern "C" fn interrupt(data: *mut c_void) {
+    let flag = &*(data as *const AtomicBool);
+    flag.store(true, Ordering::SeqCst);
+}
+
+/// Whether the thread running the current `without_gvl` call has been
+/// interrupted (`Thread#kill`, `Thread#raise`, `Timeout`, signals) since it
+/// started. Work that can take a long time checks this between steps and
+/// gives up early.
+pub fn interrupted() -> bool {
+    INTERRUPT.with(|flag| flag.borrow().as_ref().is_some_and(|flag| flag.load(Ordering::SeqCst)))
+}
+
+/// Run `func` with Ruby's GVL released so other Ruby threads keep running
+/// during TaskChampion's network and disk I/O.
+///
+/// `func` must not touch any Ruby object or call the Ruby API, which includes
+/// building a `magnus::Error`; return the `taskchampion::Error` and map it
+/// afterwards.
+///
+/// TaskChampion calls cannot be safely abandoned part way through, so an
+/// interrupt only sets the flag read by `interrupted`: syncs stop before
+/// their next server request and hooks are killed, while other work runs to
+/// the end. The interrupt itself is left pending and handled by Ruby once
+/// the calling method returns, after callers have released their borrows.
+/// If one is already pending, `func` runs without releasing the GVL.
+pub fn without_gvl<F, R>(func: F) -> Result<R, Error>
+where
+    F: FnOnce() -> R,
+{
+    Ruby::get().map_err(|e| Error::new(magnus::exception::runtime_error(), e.to_string()))?;
+    let mut call = BlockingCall { func: Some(func), result: None };
+    let flag = Arc::new(AtomicBool::new(false));
+    let previous = INTERRUPT.with(|current| current.replace(Some(flag.clone())));
+
+    // The `2` variant returns without calling `func` if an interrupt is
+    // pending, rather than raising (longjmp-ing) over our Rust frames
+    unsafe {
+        rb_sys::rb_thread_call_without_gvl2(
+            Some(call_blocking::<F, R>),
+            &mut call as *mut BlockingCall<F, R> as *mut c_void,
+            Some(interrupt),
+            Arc::as_ptr(&flag) as *mut c_void,
+        );
+        if call.result.is_none() {
+            call_blocking::<F, R>(&mut call as *mut BlockingCall<F, R> as *mut c_void);
+        }
+    }
+    INTERRUPT.with(|current| *current.borrow_mut() = previous);
+
+    match call.result {
+        Some(Ok(value)) => Ok(value),
+        Some(Err(panic)) => panic::resume_unwind(panic),
+        None => Err(Error::new(magnus::exception::runtime_error(), "Blocking call did not run")),
+    }
+}
@@ -0,0 +1,236 @@
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use taskchampion::{Operation as TCOperation, TaskData as TCTaskData, Uuid};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Create,
+    Update,
+    Delete,
+}
+
+impl ChangeKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ChangeKind::Create => "create",
+            ChangeKind::Update => "update",
+            ChangeKind::Delete => "delete",
+        }
+    }
+}
+
+/// Record a change to `property` in `changes`, keeping the value before the
+/// first change and after the last
+fn record(changes: &mut Vec<(String, Option<String>, Option<String>)>, property: &str, old_value: Option<String>, value: Option<String>) {
+    match changes.iter_mut().find(|(p, _, _)| p == property) {
+        Some(change) => change.2 = value,
+        None => changes.push((property.to_string(), old_value, value)),
+    }
+}
+
+/// One step in a task's history: a group of operations made within the same
+/// second, which is how Taskwarrior commands and `Task` setters (each of
+/// which also bumps `modified`) show up in the operation log.
+pub struct HistoryEntry {
+    pub timestamp: DateTime<Utc>,
+    pub kind: ChangeKind,
+    /// Changed properties in the order first changed, with the value before
+    /// the first change and after the last
+    pub changes: Vec<(String, Option<String>, Option<String>)>,
+    /// The task's properties once this entry is applied; empty after a delete
+    pub properties: HashMap<String, String>,
+}
+
+impl HistoryEntry {
+    fn new(timestamp: DateTime<Utc>, kind: ChangeKind) -> Self {
+        HistoryEntry {
+            timestamp,
+            kind,
+            changes: Vec::new(),
+            properties: HashMap::new(),
+        }
+    }
+
+    fn record(&mut self, property: &str, old_value: Option<String>, value: Option<String>) {
+        record(&mut self.changes, property, old_value, value);
+    }
+}
+
+fn same_second(a: DateTime<Utc>, b: DateTime<Utc>) -> bool {
+    a.timestamp() == b.timestamp()
+}
+
+/// Fold a task's operations, as returned by `Replica::get_task_operations`,
+/// into a timeline of entries, oldest first.
+///
+/// `Create` and `Delete` operations carry no timestamp. A create takes the
+/// timestamp of the update that follows it (TaskChampion sets `entry` and
+/// `modified` straight away) and a delete that of the update before it.
+pub fn task_history(operations: &[TCOperation]) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = Vec::new();
+    let mut properties: HashMap<String, String> = HashMap::new();
+    // A create whose timestamp is not yet known
+    let mut pending_create = false;
+
+    for operation in operations {
+        match operation {
+            TCOperation::Create { .. } => {
+                properties.clear();
+                pending_create = true;
+            }
+            TCOperation::Delete { .. } => {
+                // Record an outstanding create before the delete
+                if pending_create {
+                    let timestamp = entries.last().map(|e| e.timestamp).unwrap_or(DateTime::UNIX_EPOCH);
+                    entries.push(HistoryEntry::new(timestamp, ChangeKind::Create));
+                    pending_create = false;
+                }
+                let timestamp = entries.last().map(|e| e.timestamp).unwrap_or(DateTime::UNIX_EPOCH);
+                let mut entry = HistoryEntry::new(timestamp, ChangeKind::Delete);
+                let mut removed: Vec<(String, String)> = properties.drain().collect();
+                removed.sort();
+                for (property, value) in removed {
+                    entry.record(&property, Some(value), None);
+                }
+                entries.push(entry);
+            }
+            TCOperation::Update {
+                property,
+                old_value,
+                value,
+                timestamp,
+                ..
+            } => {
+                let continues_last = !pending_create
+                    && entries
+                        .last()
+                        .is_some_and(|e| e.kind != ChangeKind::Delete && same_second(e.timestamp, *timestamp));
+                if !continues_last {
+                    let kind = if pending_create { ChangeKind::Create } else { ChangeKind::Update };
+                    entries.push(HistoryEntry::new(*timestamp, kind));
+                    pending_create = false;
+                }
+
+                match value {
+                    Some(v) => properties.insert(property.clone(), v.clone()),
+                    None => properties.remove(property),
+                };
+                // Every entry was pushed above, so there is a last one
+                if let Some(entry) = entries.last_mut() {
+                    entry.record(property, old_value.clone(), value.clone());
+                    entry.properties = properties.clone();
+                }
+            }
+            TCOperation::UndoPoint => {}
+        }
+    }
+
+    if pending_create {
+        let timestamp = entries.last().map(|e| e.timestamp).unwrap_or(DateTime::UNIX_EPOCH);
+        entries.push(HistoryEntry::new(timestamp, ChangeKind::Create));
+    }
+
+    entries
+}
+
+/// The task's properties as of `time`, or `None` if it did not exist then.
+pub fn task_at(operations: &[TCOperation], time: DateTime<Utc>) -> Option<HashMap<String, String>> {
+    task_history(operations)
+        .into_iter()
+        .take_while(|entry| entry.timestamp <= time)
+        .last()
+        .filter(|entry| entry.kind != ChangeKind::Delete)
+        .map(|entry| entry.properties)
+}
+
+/// The net effect of a commit or sync on one task
+pub struct TaskChange {
+    pub uuid: Uuid,
+    pub kind: ChangeKind,
+    /// Changed properties with the value before and after
+    pub changes: Vec<(String, Option<String>, Option<String>)>,
+}
+
+/// Summarise operations about to be committed, one entry per task in order
+/// of first appearance. Changes reverted within the operations are left
+/// out, as are tasks created and deleted within them.
+pub fn summarize_operations(operations: &[TCOperation]) -> Vec<TaskChange> {
+    let mut summary: Vec<TaskChange> = Vec::new();
+    let mut index: HashMap<Uuid, usize> = HashMap::new();
+    let mut created: HashSet<Uuid> = HashSet::new();
+
+    for operation in operations {
+        let uuid = match operation {
+            TCOperation::Create { uuid } | TCOperation::Delete { uuid, .. } | TCOperation::Update { uuid, .. } => *uuid,
+            TCOperation::UndoPoint => continue,
+        };
+        let i = *index.entry(uuid).or_insert_with(|| {
+            summary.push(TaskChange { uuid, kind: ChangeKind::Update, changes: Vec::new() });
+            summary.len() - 1
+        });
+        let change = &mut summary[i];
+
+        match operation {
+            TCOperation::Create { .. } => {
+                if change.kind == ChangeKind::Delete {
+                    change.kind = ChangeKind::Update;
+                } else {
+                    change.kind = ChangeKind::Create;
+                    created.insert(uuid);
+                }
+            }
+            TCOperation::Delete { old_task, .. } => {
+                let mut removed: Vec<(&String, &String)> = old_task.iter().collect();
+                removed.sort();
+                for (property, value) in removed {
+                    record(&mut change.changes, property, Some(value.clone()), None);
+                }
+                change.kind = ChangeKind::Delete;
+            }
+            TCOperation::Update { property, old_value, value, .. } => {
+                record(&mut change.changes, property, old_value.clone(), value.clone());
+            }
+            TCOperation::UndoPoint => {}
+        }
+    }
+
+    summary
+        .into_iter()
+        .filter(|change| !(change.kind == ChangeKind::Delete && created.contains(&change.uuid)))
+        .filter_map(|mut change| {
+            change.changes.retain(|(_, old_value, value)| old_value != value);
+            (change.kind != ChangeKind::Update || !change.changes.is_empty()).then_some(change)
+        })
+        .collect()
+}
+
+/// Summarise the difference between two states of the same tasks, in UUID
+/// order, with each task's changed properties sorted
+pub fn summarize_states(before: &HashMap<Uuid, TCTaskData>, after: &HashMap<Uuid, TCTaskData>) -> Vec<TaskChange> {
+    let uuids: BTreeSet<&Uuid> = before.keys().chain(after.keys()).collect();
+    uuids
+        .into_iter()
+        .filter_map(|uuid| {
+            let (old, new) = (before.get(uuid), after.get(uuid));
+            let kind = match (old, new) {
+                (None, Some(_)) => ChangeKind::Create,
+                (Some(_), None) => ChangeKind::Delete,
+                _ => ChangeKind::Update,
+            };
+            let properties: BTreeSet<&String> = old
+                .into_iter()
+                .chain(new)
+                .flat_map(|data| data.properties())
+                .collect();
+            let changes: Vec<(String, Option<String>, Option<String>)> = properties
+                .into_iter()
+                .map(|property| {
+                    let value = |data: Option<&TCTaskData>| data.and_then(|d| d.get(property)).map(str::to_string);
+                    (property.clone(), value(old), value(new))
+                })
+                .filter(|(_, old_value, value)| old_value != value)
+                .collect();
+            (kind != ChangeKind::Update || !changes.is_empty()).then_some(TaskChange { uuid: *uuid, kind, changes })
+        })
+        .collect()
+}
@@ -0,0 +1,327 @@
+use chrono::Utc;
+use magnus::Error;
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use taskchampion::{Operation as TCOperation, Replica as TCReplica, TaskData as TCTaskData, Uuid};
+
+use crate::error::hook_error;
+use crate::gvl;
+use crate::search;
+use crate::taskwarrior;
+
+/// How long a hook may run before it is killed, unless
+/// `enable_taskwarrior_hooks` is given a `timeout:`
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often a running hook is checked for exit, timeout or interrupt
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The hook directory enabled on a replica and the time each hook may take
+#[derive(Clone)]
+pub struct HookConfig {
+    pub dir: PathBuf,
+    pub timeout: Duration,
+}
+
+/// The Taskwarrior hook events run when operations are committed
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    Add,
+    Modify,
+}
+
+impl HookEvent {
+    fn command(self) -> &'static str {
+        match self {
+            HookEvent::Add => "add",
+            HookEvent::Modify => "modify",
+        }
+    }
+
+    /// Hook scripts for this event are named with this prefix, such as
+    /// `on-add-validate.py`
+    fn prefix(self) -> &'static str {
+        match self {
+            HookEvent::Add => "on-add",
+            HookEvent::Modify => "on-modify",
+        }
+    }
+}
+
+/// A task added or modified by a commit, as its hooks see it
+pub struct HookInput {
+    pub uuid: Uuid,
+    /// The task before the commit, as a `task export` JSON line
+    pub original: Option<String>,
+    /// The task once the commit is applied, as a JSON line
+    pub modified: String,
+    /// The task's properties once the commit is applied
+    pub properties: HashMap<String, String>,
+}
+
+pub enum HookError {
+    TaskChampion(taskchampion::Error),
+    /// A hook rejected the change, could not be run or gave bad output
+    Hook(String),
+}
+
+impl From<taskchampion::Error> for HookError {
+    fn from(error: taskchampion::Error) -> Self {
+        HookError::TaskChampion(error)
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// The executables in `dir` for `event`, in name order as Taskwarrior runs
+/// them. Other files are ignored.
+fn find_hooks(dir: &Path, event: HookEvent) -> Result<Vec<PathBuf>, HookError> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| HookError::Hook(format!("Could not read hook directory {}: {}", dir.display(), e)))?;
+    let mut hooks: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(event.prefix()))
+        .map(|entry| entry.path())
+        .filter(|path| is_executable(path))
+        .collect();
+    hooks.sort();
+    Ok(hooks)
+}
+
+/// Apply `operations` to `task`'s properties, returning `None` if they
+/// leave it deleted
+fn apply(
+    uuid: Uuid,
+    mut task: Option<HashMap<String, String>>,
+    operations: &[TCOperation],
+) -> Option<HashMap<String, String>> {
+    for operation in operations {
+        match operation {
+            TCOperation::Create { uuid: u } if *u == uuid => {
+                task.get_or_insert_with(HashMap::new);
+            }
+            TCOperation::Delete { uuid: u, .. } if *u == uuid => task = None,
+            TCOperation::Update { uuid: u, property, value, .. } if *u == uuid => {
+                if let Some(task) = task.as_mut() {
+                    match value {
+                        Some(value) => task.insert(property.clone(), value.clone()),
+                        None => task.remove(property),
+                    };
+                }
+            }
+            _ => {}
+        }
+    }
+    task
+}
+
+fn to_json_line(uuid: Uuid, properties: &HashMap<String, String>, id: usize) -> String {
+    // Built outside the replica; the operations are discarded
+    let mut ops = Vec::new();
+    let mut data = TCTaskData::create(uuid, &mut ops);
+    for (key, value) in properties {
+        data.update(key, Some(value.clone()), &mut ops);
+    }
+    taskwarrior::task_to_json(&data, id).to_string()
+}
+
+/// Run one hook, returning the task line it printed. The hook is killed if
+/// it runs longer than `timeout` or the calling Ruby thread is interrupted.
+fn run_hook(hook: &Path, args: &[String], input: &str, uuid: Uuid, timeout: Duration) -> Result<String, HookError> {
+    let name = hook.file_name().unwrap_or(hook.as_os_str()).to_string_lossy();
+    let could_not_run = |e: std::io::Error| HookError::Hook(format!("Could not run hook {}: {}", name, e));
+    let mut child = Command::new(hook)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(could_not_run)?;
+    let deadline = Instant::now() + timeout;
+
+    // The pipes are served from their own threads so a hook that stops
+    // reading or writing cannot block the wait below
+    if let Some(mut stdin) = child.stdin.take() {
+        let input = input.to_string();
+        thread::spawn(move || {
+            // A hook that exits without reading its input closes the pipe
+            // early; its exit status still decides the outcome
+            let _ = stdin.write_all(input.as_bytes());
+        });
+    }
+    let (sender, receiver) = mpsc::channel();
+    if let Some(mut stdout) = child.stdout.take() {
+        thread::spawn(move || {
+            let mut output = Vec::new();
+            let _ = stdout.read_to_end(&mut output);
+            let _ = sender.send(output);
+        });
+    }
+
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(could_not_run)? {
+            break status;
+        }
+        let stopped = if gvl::interrupted() {
+            Some("was interrupted".to_string())
+        } else if Instant::now() >= deadline {
+            Some(format!("timed out after {}s", timeout.as_secs_f64()))
+        } else {
+            None
+        };
+        if let Some(reason) = stopped {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(HookError::Hook(format!("Hook {} {} on task {}", name, reason, uuid)));
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    // Processes the hook started may still hold its output open, so this
+    // waits for the rest of the time allowed, but at least a moment
+    let remaining = deadline.saturating_duration_since(Instant::now()).max(POLL_INTERVAL * 10);
+    let output = receiver
+        .recv_timeout(remaining)
+        .map_err(|_| HookError::Hook(format!("Hook {} left its output open on task {}", name, uuid)))?;
+
+    // As in Taskwarrior, lines that are JSON objects are tasks and the rest
+    // is feedback for the user
+    let stdout = String::from_utf8_lossy(&output);
+    let (tasks, feedback): (Vec<&str>, Vec<&str>) = stdout
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .partition(|line| line.starts_with('{'));
+
+    if !status.success() {
+        let mut message = format!("Hook {} rejected task {}", name, uuid);
+        if !feedback.is_empty() {
+            message.push_str(": ");
+            message.push_str(&feedback.join("\n"));
+        }
+        return Err(HookError::Hook(message));
+    }
+    match tasks.as_slice() {
+        [task] => Ok(task.to_string()),
+        _ => Err(HookError::Hook(format!(
+            "Hook {} must print exactly one JSON task for task {}, printed {}",
+            name,
+            uuid,
+            tasks.len()
+        ))),
+    }
+}
+
+/// Run the on-add and on-modify hooks in `dir` on the tasks `operations`
+/// add or modify, before they are committed. Each task's hooks run in turn,
+/// each given the task as the previous one left it. Returns the tasks
+/// alongside the JSON line the last hook printed.
+pub fn run_hooks(
+    replica: &mut TCReplica,
+    config: &HookConfig,
+    data_dir: Option<&Path>,
+    operations: &[TCOperation],
+) -> Result<Vec<(HookInput, String)>, HookError> {
+    let on_add = find_hooks(&config.dir, HookEvent::Add)?;
+    let on_modify = find_hooks(&config.dir, HookEvent::Modify)?;
+    if on_add.is_empty() && on_modify.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let working_set = replica.working_set()?;
+    let mut results = Vec::new();
+    for uuid in search::touched_uuids(operations) {
+        let stored: Option<HashMap<String, String>> = replica
+            .get_task_data(uuid)?
+            .map(|data| data.iter().map(|(k, v)| (k.clone(), v.clone())).collect());
+        // Tasks deleted outright are not added or modified
+        let Some(properties) = apply(uuid, stored.clone(), operations) else {
+            continue;
+        };
+        if stored.as_ref() == Some(&properties) {
+            continue;
+        }
+
+        let id = working_set.by_uuid(uuid).unwrap_or(0);
+        let (event, hooks) = match &stored {
+            None => (HookEvent::Add, &on_add),
+            Some(_) => (HookEvent::Modify, &on_modify),
+        };
+        let input = HookInput {
+            uuid,
+            original: stored.map(|stored| to_json_line(uuid, &stored, id)),
+            modified: to_json_line(uuid, &properties, id),
+            properties,
+        };
+
+        let mut args = vec!["api:2".to_string(), format!("command:{}", event.command())];
+        if let Some(data_dir) = data_dir {
+            args.push(format!("data:{}", data_dir.display()));
+        }
+        let mut task = input.modified.clone();
+        for hook in hooks {
+            let stdin = match &input.original {
+                Some(original) => format!("{}\n{}\n", original, task),
+                None => format!("{}\n", task),
+            };
+            task = run_hook(hook, &args, &stdin, uuid, config.timeout)?;
+        }
+        results.push((input, task));
+    }
+    Ok(results)
+}
+
+/// Operations making the changes hooks made to the tasks in `results`
+pub fn hook_operations(results: &[(HookInput, String)]) -> Result<Vec<TCOperation>, Error> {
+    let now = Utc::now();
+    let mut operations = Vec::new();
+
+    for (input, output) in results {
+        if *output == input.modified {
+            continue;
+        }
+        let parse = |line: &str| {
+            serde_json::from_str::<JsonValue>(line).map_err(|e| {
+                Error::new(hook_error(), format!("Hook output for task {} is not valid JSON: {}", input.uuid, e))
+            })
+        };
+        let (uuid, properties) = taskwarrior::json_to_task(&parse(output)?)?;
+        if uuid != input.uuid {
+            return Err(Error::new(
+                hook_error(),
+                format!("Hooks cannot change the UUID of task {}", input.uuid),
+            ));
+        }
+
+        // Compare with the task as the hooks received it, rather than its
+        // stored properties, so values the JSON form does not carry are kept
+        let (_, sent) = taskwarrior::json_to_task(&parse(&input.modified)?)?;
+        let keys: BTreeSet<&String> = sent.keys().chain(properties.keys()).collect();
+        for key in keys {
+            let value = properties.get(key);
+            if sent.get(key) != value {
+                operations.push(TCOperation::Update {
+                    uuid,
+                    property: key.clone(),
+                    old_value: input.properties.get(key).cloned(),
+                    value: value.cloned(),
+                    timestamp: now,
+                });
+            }
+        }
+    }
+    Ok(operations)
+}
@@ -0,0 +1,550 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+use magnus::Error;
+use std::collections::{BTreeSet, HashMap};
+use taskchampion::{Operation as TCOperation, Operations as TCOperations, Replica as TCReplica, TaskData as TCTaskData, Uuid};
+
+use crate::error::validation_error;
+
+/// Task property holding the UID of a to-do imported from another client
+/// when it is not a UUID, so the task keeps that UID when exported
+pub const UID_PROPERTY: &str = "ical.uid";
+
+const ICAL_DATE_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Content lines longer than this many octets are folded
+const MAX_LINE_OCTETS: usize = 75;
+
+fn import_error(message: impl Into<String>) -> Error {
+    Error::new(validation_error(), message.into())
+}
+
+fn format_date(value: &str) -> Option<String> {
+    let secs = value.parse::<i64>().ok()?;
+    DateTime::from_timestamp(secs, 0).map(|dt| dt.format(ICAL_DATE_FORMAT).to_string())
+}
+
+/// Escape a TEXT value
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Append a content line, folding it as RFC 5545 requires
+fn push_line(out: &mut String, line: &str) {
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            out.push_str("\r\n ");
+            octets = 1;
+        }
+        out.push(c);
+        octets += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn push_todo(out: &mut String, task: &TCTaskData, uids: &HashMap<Uuid, String>, now: DateTime<Utc>) {
+    let uid = |uuid: Uuid| uids.get(&uuid).cloned().unwrap_or_else(|| uuid.to_string());
+
+    push_line(out, "BEGIN:VTODO");
+    push_line(out, &format!("UID:{}", escape(&uid(task.get_uuid()))));
+    push_line(out, &format!("DTSTAMP:{}", now.format(ICAL_DATE_FORMAT)));
+    for (property, name) in [("entry", "CREATED"), ("modified", "LAST-MODIFIED"), ("due", "DUE")] {
+        if let Some(date) = task.get(property).and_then(format_date) {
+            push_line(out, &format!("{}:{}", name, date));
+        }
+    }
+    if let Some(description) = task.get("description") {
+        push_line(out, &format!("SUMMARY:{}", escape(description)));
+    }
+
+    let status = task.get("status");
+    let ical_status = match status {
+        Some("completed") => "COMPLETED",
+        Some("deleted") => "CANCELLED",
+        _ => "NEEDS-ACTION",
+    };
+    push_line(out, &format!("STATUS:{}", ical_status));
+    if status == Some("completed") {
+        if let Some(end) = task.get("end").and_then(format_date) {
+            push_line(out, &format!("COMPLETED:{}", end));
+        }
+    }
+
+    // RFC 5545 ranks 1 to 4 as high, 5 as medium and 6 to 9 as low
+    let priority = match task.get("priority") {
+        Some("H") => Some(1),
+        Some("M") => Some(5),
+        Some("L") => Some(9),
+        _ => None,
+    };
+    if let Some(priority) = priority {
+        push_line(out, &format!("PRIORITY:{}", priority));
+    }
+
+    let mut tags = Vec::new();
+    let mut annotations = Vec::new();
+    let mut depends = Vec::new();
+    for (key, value) in task.iter() {
+        if let Some(tag) = key.strip_prefix("tag_") {
+            tags.push(escape(tag));
+        } else if let Some(entry) = key.strip_prefix("annotation_") {
+            annotations.push((entry.parse::<i64>().unwrap_or(0), value.as_str()));
+        } else if let Some(dep) = key.strip_prefix("dep_").and_then(|dep| Uuid::parse_str(dep).ok()) {
+            depends.push(uid(dep));
+        }
+    }
+    tags.sort();
+    annotations.sort();
+    depends.sort();
+
+    if !tags.is_empty() {
+        push_line(out, &format!("CATEGORIES:{}", tags.join(",")));
+    }
+    if !annotations.is_empty() {
+        let lines: Vec<&str> = annotations.into_iter().map(|(_, description)| description).collect();
+        push_line(out, &format!("DESCRIPTION:{}", escape(&lines.join("\n"))));
+    }
+    for dep in depends {
+        push_line(out, &format!("RELATED-TO;RELTYPE=DEPENDS-ON:{}", escape(&dep)));
+    }
+    push_line(out, "END:VTODO");
+}
+
+/// Convert tasks into an iCalendar document with one VTODO each
+pub fn tasks_to_ical(tasks: &[TCTaskData], now: DateTime<Utc>) -> String {
+    // Dependencies on imported tasks refer to them by their original UID
+    let uids: HashMap<Uuid, String> = tasks
+        .iter()
+        .filter_map(|task| task.get(UID_PROPERTY).map(|uid| (task.get_uuid(), uid.to_string())))
+        .collect();
+
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, "PRODID:-//taskchampion-rb//NONSGML taskchampion-rb//EN");
+    for task in tasks {
+        push_todo(&mut out, task, &uids, now);
+    }
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+/// A content line: its name, parameters and unparsed value
+struct ContentLine {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl ContentLine {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+}
+
+/// Join folded lines back together
+fn unfold(input: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in input.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continued), Some(last)) => last.push_str(continued),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// Split a content line at its first `:` and the `;`s before it, ignoring
+/// those in quoted parameter values
+fn parse_line(line: &str) -> Option<ContentLine> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut chars = line.char_indices();
+    let value = loop {
+        let (i, c) = chars.next()?;
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => parts.push(std::mem::take(&mut current)),
+            ':' if !quoted => {
+                parts.push(std::mem::take(&mut current));
+                break line[i + 1..].to_string();
+            }
+            c => current.push(c),
+        }
+    };
+
+    let mut parts = parts.into_iter();
+    let name = parts.next()?.trim().to_ascii_uppercase();
+    let params = parts
+        .filter_map(|part| {
+            part.split_once('=')
+                .map(|(key, value)| (key.trim().to_ascii_uppercase(), value.to_string()))
+        })
+        .collect();
+    Some(ContentLine { name, params, value })
+}
+
+/// Unescape a TEXT value, splitting it at unescaped commas if `list`
+fn text_items(value: &str, list: bool) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n' | 'N') => current.push('\n'),
+                Some(escaped) => current.push(escaped),
+                None => {}
+            },
+            ',' if list => items.push(std::mem::take(&mut current)),
+            c => current.push(c),
+        }
+    }
+    items.push(current);
+    items
+}
+
+fn unescape(value: &str) -> String {
+    text_items(value, false).concat()
+}
+
+/// Parse a DATE-TIME or DATE value into a Unix timestamp string. Times
+/// ending in `Z` are UTC, times with a `TZID` are in that IANA time zone and
+/// floating times are in the local time zone; dates are midnight UTC.
+fn parse_date(line: &ContentLine) -> Result<String, Error> {
+    let value = line.value.trim();
+    let invalid = || import_error(format!("Invalid {} '{}'", line.name, value));
+
+    let Ok(naive) = NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), "%Y%m%dT%H%M%S") else {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").map_err(|_| invalid())?;
+        return Ok(date.and_time(NaiveTime::MIN).and_utc().timestamp().to_string());
+    };
+    let timestamp = match line.param("TZID") {
+        _ if value.ends_with('Z') => Some(naive.and_utc().timestamp()),
+        Some(tzid) => {
+            // Globally unique TZIDs are marked with a leading `/`
+            let tz: Tz = tzid.trim_start_matches('/').parse().map_err(|_| {
+                import_error(format!("Unknown TZID '{}' in {}; use an IANA time zone name or UTC", tzid, line.name))
+            })?;
+            local_timestamp(&tz, naive)
+        }
+        None => local_timestamp(&Local, naive),
+    };
+    timestamp.map(|t| t.to_string()).ok_or_else(invalid)
+}
+
+/// The timestamp of `naive` in `tz`. Times repeated when clocks go back are
+/// taken at their first occurrence, and times skipped when they go forward
+/// are shifted by the hour skipped, which keeps the offset from before it.
+fn local_timestamp<T: TimeZone>(tz: &T, naive: NaiveDateTime) -> Option<i64> {
+    tz.from_local_datetime(&naive)
+        .earliest()
+        .or_else(|| tz.from_local_datetime(&(naive + TimeDelta::hours(1))).earliest())
+        .map(|time| time.timestamp())
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TodoStatus {
+    Open,
+    Completed,
+    Cancelled,
+}
+
+/// The parts of a VTODO that map to task properties
+pub struct Todo {
+    pub uid: String,
+    pub summary: Option<String>,
+    pub created: Option<String>,
+    pub due: Option<String>,
+    pub status: Option<TodoStatus>,
+    pub completed: Option<String>,
+    pub priority: Option<&'static str>,
+    pub categories: Vec<String>,
+    /// Lines of the DESCRIPTION, each an annotation
+    pub annotations: Vec<String>,
+    /// UIDs of the to-dos this one depends on
+    pub depends: Vec<String>,
+}
+
+fn todo_from_lines(lines: &[ContentLine]) -> Result<Todo, Error> {
+    let mut todo = Todo {
+        uid: String::new(),
+        summary: None,
+        created: None,
+        due: None,
+        status: None,
+        completed: None,
+        priority: None,
+        categories: Vec::new(),
+        annotations: Vec::new(),
+        depends: Vec::new(),
+    };
+
+    for line in lines {
+        match line.name.as_str() {
+            "UID" => todo.uid = unescape(&line.value).trim().to_string(),
+            "SUMMARY" => todo.summary = Some(unescape(&line.value)),
+            "CREATED" => todo.created = Some(parse_date(line)?),
+            "DUE" => todo.due = Some(parse_date(line)?),
+            "COMPLETED" => todo.completed = Some(parse_date(line)?),
+            "STATUS" => {
+                todo.status = match line.value.trim().to_ascii_uppercase().as_str() {
+                    "NEEDS-ACTION" | "IN-PROCESS" => Some(TodoStatus::Open),
+                    "COMPLETED" => Some(TodoStatus::Completed),
+                    "CANCELLED" => Some(TodoStatus::Cancelled),
+                    other => return Err(import_error(format!("Invalid STATUS '{}'", other))),
+                }
+            }
+            "PRIORITY" => {
+                todo.priority = match line.value.trim().parse::<u8>() {
+                    Ok(0) => None,
+                    Ok(1..=4) => Some("H"),
+                    Ok(5) => Some("M"),
+                    Ok(6..=9) => Some("L"),
+                    _ => return Err(import_error(format!("Invalid PRIORITY '{}'", line.value))),
+                }
+            }
+            "CATEGORIES" => {
+                // Tags cannot contain whitespace
+                let categories = text_items(&line.value, true).into_iter().map(|category| {
+                    category.split_whitespace().collect::<Vec<_>>().join("_")
+                });
+                todo.categories.extend(categories.filter(|category| !category.is_empty()));
+            }
+            "DESCRIPTION" => {
+                todo.annotations = unescape(&line.value)
+                    .lines()
+                    .map(str::trim)
+                    .filter(|annotation| !annotation.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            }
+            "RELATED-TO" => {
+                let reltype = line.param("RELTYPE").map(str::to_ascii_uppercase);
+                if reltype.as_deref() == Some("DEPENDS-ON") {
+                    todo.depends.push(unescape(&line.value).trim().to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if todo.uid.is_empty() {
+        return Err(import_error("Each VTODO needs a UID"));
+    }
+    Ok(todo)
+}
+
+/// Parse the VTODOs in an iCalendar document. Other components, and those
+/// nested in a VTODO such as VALARMs, are skipped.
+pub fn parse_todos(input: &str) -> Result<Vec<Todo>, Error> {
+    let lines: Vec<ContentLine> = unfold(input)
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| parse_line(line).ok_or_else(|| import_error(format!("Invalid iCalendar line '{}'", line))))
+        .collect::<Result<_, _>>()?;
+    if !lines.first().is_some_and(|line| line.name == "BEGIN" && line.value.eq_ignore_ascii_case("VCALENDAR")) {
+        return Err(import_error("Expected an iCalendar document starting with BEGIN:VCALENDAR"));
+    }
+
+    let mut todos = Vec::new();
+    // Lines of the VTODO being read, and how deeply nested inside it we are
+    let mut current: Option<(Vec<ContentLine>, usize)> = None;
+    for line in lines {
+        let component = line.value.trim().to_ascii_uppercase();
+        match (&mut current, line.name.as_str()) {
+            (None, "BEGIN") if component == "VTODO" => current = Some((Vec::new(), 0)),
+            (Some((_, depth)), "BEGIN") => *depth += 1,
+            (Some((_, depth)), "END") if *depth > 0 => *depth -= 1,
+            (Some(_), "END") => {
+                if component != "VTODO" {
+                    return Err(import_error(format!("Unexpected END:{} in a VTODO", component)));
+                }
+                if let Some((todo_lines, _)) = current.take() {
+                    todos.push(todo_from_lines(&todo_lines)?);
+                }
+            }
+            (Some((todo_lines, 0)), _) => todo_lines.push(line),
+            _ => {}
+        }
+    }
+    if current.is_some() {
+        return Err(import_error("Unterminated VTODO"));
+    }
+    Ok(todos)
+}
+
+/// The property changes that make `existing` (empty for a new task) match
+/// `todo`, in property order
+fn todo_changes(
+    todo: &Todo,
+    existing: &HashMap<String, String>,
+    uuids: &HashMap<String, Uuid>,
+    now: DateTime<Utc>,
+) -> Vec<(String, Option<String>)> {
+    let mut wanted: HashMap<String, Option<String>> = HashMap::new();
+    let is_new = existing.is_empty();
+
+    if Uuid::parse_str(&todo.uid).is_err() {
+        wanted.insert(UID_PROPERTY.into(), Some(todo.uid.clone()));
+    }
+    if is_new {
+        wanted.insert("entry".into(), Some(todo.created.clone().unwrap_or_else(|| now.timestamp().to_string())));
+    }
+    wanted.insert("description".into(), todo.summary.clone());
+    wanted.insert("due".into(), todo.due.clone());
+    wanted.insert("priority".into(), todo.priority.map(str::to_string));
+
+    let status = existing.get("status").map(String::as_str);
+    let end = || {
+        // Keep the end of a task that was already closed the same way
+        let already = matches!(
+            (todo.status, status),
+            (Some(TodoStatus::Completed), Some("completed")) | (Some(TodoStatus::Cancelled), Some("deleted"))
+        );
+        let kept = already.then(|| existing.get("end").cloned()).flatten();
+        Some(todo.completed.clone().or(kept).unwrap_or_else(|| now.timestamp().to_string()))
+    };
+    match todo.status {
+        None if is_new => {
+            wanted.insert("status".into(), Some("pending".into()));
+        }
+        None => {}
+        Some(TodoStatus::Open) => {
+            if !matches!(status, Some("pending" | "recurring")) {
+                wanted.insert("status".into(), Some("pending".into()));
+                wanted.insert("end".into(), None);
+            }
+        }
+        Some(TodoStatus::Completed) => {
+            wanted.insert("status".into(), Some("completed".into()));
+            wanted.insert("end".into(), end());
+        }
+        Some(TodoStatus::Cancelled) => {
+            wanted.insert("status".into(), Some("deleted".into()));
+            wanted.insert("end".into(), end());
+        }
+    }
+
+    // Tags and dependencies are replaced as a whole
+    for key in existing.keys().filter(|key| key.starts_with("tag_") || key.starts_with("dep_")) {
+        wanted.insert(key.clone(), None);
+    }
+    for category in &todo.categories {
+        wanted.insert(format!("tag_{}", category), Some(String::new()));
+    }
+    for uid in &todo.depends {
+        // Dependencies on to-dos that are not tasks are dropped
+        if let Some(uuid) = uuids.get(uid).copied().or_else(|| Uuid::parse_str(uid).ok()) {
+            wanted.insert(format!("dep_{}", uuid), Some(String::new()));
+        }
+    }
+
+    // Annotations that are still listed keep their timestamps
+    let mut remaining: Vec<&String> = todo.annotations.iter().collect();
+    for (key, description) in existing.iter().filter(|(key, _)| key.starts_with("annotation_")) {
+        match remaining.iter().position(|annotation| *annotation == description) {
+            Some(i) => {
+                remaining.remove(i);
+            }
+            None => {
+                wanted.insert(key.clone(), None);
+            }
+        }
+    }
+    let mut entry = now.timestamp();
+    for annotation in remaining {
+        while existing.contains_key(&format!("annotation_{}", entry)) {
+            entry += 1;
+        }
+        wanted.insert(format!("annotation_{}", entry), Some(annotation.clone()));
+        entry += 1;
+    }
+
+    let mut changes: Vec<(String, Option<String>)> = wanted
+        .into_iter()
+        .filter(|(key, value)| existing.get(key) != value.as_ref())
+        .collect();
+    changes.sort();
+    if !changes.is_empty() {
+        changes.push(("modified".into(), Some(now.timestamp().to_string())));
+    }
+    changes
+}
+
+/// Build the operations creating or updating a task for each of `todos`,
+/// matched by UID: a UID that is a UUID names the task, and any other UID is
+/// looked up in the tasks' `ical.uid`. Returns the operations, for the
+/// caller to commit, and the number of tasks created, updated and left
+/// unchanged.
+pub fn import_todos(
+    replica: &mut TCReplica,
+    todos: &[Todo],
+    now: DateTime<Utc>,
+) -> Result<(TCOperations, usize, usize, usize), taskchampion::Error> {
+    let mut tasks = replica.all_task_data()?;
+    let mut uuids: HashMap<String, Uuid> = tasks
+        .values()
+        .filter_map(|task| task.get(UID_PROPERTY).map(|uid| (uid.to_string(), task.get_uuid())))
+        .collect();
+    // Find every to-do's task first, so dependencies on later ones resolve
+    for todo in todos {
+        if !uuids.contains_key(&todo.uid) {
+            let uuid = Uuid::parse_str(&todo.uid).unwrap_or_else(|_| Uuid::new_v4());
+            uuids.insert(todo.uid.clone(), uuid);
+        }
+    }
+
+    let (mut created, mut updated, mut skipped) = (0usize, 0usize, 0usize);
+    let mut ops = TCOperations::new();
+    ops.push(TCOperation::UndoPoint);
+    // Tasks imported so far, so a to-do that appears twice counts once
+    let mut seen = BTreeSet::new();
+
+    for todo in todos {
+        let uuid = uuids[&todo.uid];
+        let first = seen.insert(uuid);
+        let existing: HashMap<String, String> = tasks
+            .get(&uuid)
+            .map(|task| task.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+            .unwrap_or_default();
+        let changes = todo_changes(todo, &existing, &uuids, now);
+
+        let mut task = match tasks.remove(&uuid) {
+            Some(task) => {
+                if first {
+                    if changes.is_empty() {
+                        skipped += 1;
+                    } else {
+                        updated += 1;
+                    }
+                }
+                task
+            }
+            None => {
+                created += 1;
+                TCTaskData::create(uuid, &mut ops)
+            }
+        };
+        for (key, value) in changes {
+            task.update(key, value, &mut ops);
+        }
+        tasks.insert(uuid, task);
+    }
+
+    Ok((ops, created, updated, skipped))
+}
@@ -1,6 +1,12 @@
 use magnus::{Error, Ruby};
 
+mod backup;
 mod error;
+mod filter;
+mod gvl;
+mod history;
+mod hooks;
+mod ical;
 mod thread_check;
 mod util;
 mod access_mode;
@@ -9,11 +15,22 @@ mod tag;
 mod annotation;
 mod task;
 mod task_data;
+mod task_snapshot;
+mod taskwarrior;
 mod operation;
+mod operation_json;
 mod operations;
 mod replica;
+mod search;
+mod shared_replica;
+mod transaction;
 mod working_set;
+mod sync_result;
 mod dependency_map;
+mod dependency_graph;
+mod uda_schema;
+mod urgency;
+mod recurrence;
 
 use error::init_errors;
 
@@ -23,6 +40,7 @@ fn init(ruby: &Ruby) -> Result<(), Error> {
 
     // Initialize error classes
     init_errors(&module)?;
+    util::init_time_class(&module)?;
 
     // Initialize constants
     access_mode::init(&module)?;
@@ -33,11 +51,17 @@ fn init(ruby: &Ruby) -> Result<(), Error> {
     annotation::init(&module)?;
     task::init(&module)?;
     task_data::init(&module)?;
+    task_snapshot::init(&module)?;
     operation::init(&module)?;
     operations::init(&module)?;
     working_set::init(&module)?;
     dependency_map::init(&module)?;
+    uda_schema::init(&module)?;
+    urgency::init(&module)?;
+    sync_result::init(&module)?;
     replica::init(&module)?;
+    shared_replica::init(&module)?;
+    transaction::init(&module)?;
 
     Ok(())
 }
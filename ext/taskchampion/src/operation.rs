@@ -1,8 +1,11 @@
 use magnus::{
     class, function, method, prelude::*, Error, IntoValue, RHash, RModule, Value,
 };
+use serde_json::Value as JsonValue;
 use taskchampion::Operation as TCOperation;
 
+use crate::error::validation_error;
+use crate::operation_json;
 use crate::util::{datetime_to_ruby, ruby_to_datetime, ruby_to_hashmap, uuid2tc};
 
 #[magnus::wrap(class = "Taskchampion::Operation", free_immediately)]
@@ -44,6 +47,19 @@ impl Operation {
         Operation(TCOperation::UndoPoint)
     }
 
+    fn from_json(json: String) -> Result<Self, Error> {
+        let value: JsonValue = serde_json::from_str(&json)
+            .map_err(|e| Error::new(validation_error(), format!("Invalid JSON: {}", e)))?;
+        operation_json::from_json(&value)
+            .map(Operation)
+            .map_err(|e| Error::new(validation_error(), e))
+    }
+
+    // Arguments from JSON.generate are accepted and ignored
+    fn to_json(&self, _args: &[Value]) -> String {
+        operation_json::to_json(&self.0).to_string()
+    }
+
     // Type checking methods
     fn create_op(&self) -> bool {
         matches!(self.0, TCOperation::Create { .. })
@@ -212,6 +228,7 @@ pub fn init(module: &RModule) -> Result<(), Error> {
     class.define_singleton_method("delete", function!(Operation::delete, 2))?;
     class.define_singleton_method("update", function!(Operation::update, 5))?;
     class.define_singleton_method("undo_point", function!(Operation::undo_point, 0))?;
+    class.define_singleton_method("from_json", function!(Operation::from_json, 1))?;
 
     // Type checking methods
     class.define_method("create?", method!(Operation::create_op, 0))?;
@@ -228,6 +245,7 @@ pub fn init(module: &RModule) -> Result<(), Error> {
     class.define_method("old_value", method!(Operation::old_value, 0))?;
     class.define_method("value", method!(Operation::value, 0))?;
     class.define_method("to_s", method!(Operation::to_s, 0))?;
+    class.define_method("to_json", method!(Operation::to_json, -1))?;
     class.define_method("inspect", method!(Operation::inspect, 0))?;
 
     Ok(())
@@ -0,0 +1,116 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Map, Value as JsonValue};
+use taskchampion::{Operation as TCOperation, Uuid};
+
+/// Encode an operation as a JSON object. The `type` field uses the names from
+/// `Operation#operation_type`, and update timestamps are RFC 3339 strings
+/// carrying all of TaskChampion's sub-second precision.
+pub fn to_json(op: &TCOperation) -> JsonValue {
+    match op {
+        TCOperation::Create { uuid } => json!({ "type": "create", "uuid": uuid.to_string() }),
+        TCOperation::Delete { uuid, old_task } => {
+            json!({ "type": "delete", "uuid": uuid.to_string(), "old_task": old_task })
+        }
+        TCOperation::Update { uuid, property, timestamp, old_value, value } => json!({
+            "type": "update",
+            "uuid": uuid.to_string(),
+            "property": property,
+            "timestamp": timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            "old_value": old_value,
+            "value": value,
+        }),
+        TCOperation::UndoPoint => json!({ "type": "undo_point" }),
+    }
+}
+
+/// Decode an operation written by `to_json`
+pub fn from_json(value: &JsonValue) -> Result<TCOperation, String> {
+    let object = value.as_object().ok_or("Expected a JSON object for an operation")?;
+    let kind = object
+        .get("type")
+        .and_then(JsonValue::as_str)
+        .ok_or("Operation is missing its 'type'")?;
+
+    match kind {
+        "create" => Ok(TCOperation::Create { uuid: uuid_field(object)? }),
+        "delete" => {
+            let old_task = match object.get("old_task") {
+                Some(JsonValue::Object(map)) => map
+                    .iter()
+                    .map(|(k, v)| match v.as_str() {
+                        Some(s) => Ok((k.clone(), s.to_string())),
+                        None => Err(format!("Value of old_task property '{}' must be a string", k)),
+                    })
+                    .collect::<Result<_, _>>()?,
+                Some(_) => return Err("'old_task' must be an object".to_string()),
+                None => return Err("Delete operation is missing 'old_task'".to_string()),
+            };
+            Ok(TCOperation::Delete { uuid: uuid_field(object)?, old_task })
+        }
+        "update" => {
+            let property = object
+                .get("property")
+                .and_then(JsonValue::as_str)
+                .ok_or("Update operation is missing 'property'")?
+                .to_string();
+            let timestamp = object
+                .get("timestamp")
+                .and_then(JsonValue::as_str)
+                .ok_or("Update operation is missing 'timestamp'")?;
+            let timestamp = DateTime::parse_from_rfc3339(timestamp)
+                .map_err(|_| format!("Invalid timestamp '{}'", timestamp))?
+                .with_timezone(&Utc);
+            Ok(TCOperation::Update {
+                uuid: uuid_field(object)?,
+                property,
+                timestamp,
+                old_value: optional_string(object, "old_value")?,
+                value: optional_string(object, "value")?,
+            })
+        }
+        "undo_point" => Ok(TCOperation::UndoPoint),
+        other => Err(format!("Unknown operation type '{}'", other)),
+    }
+}
+
+fn uuid_field(object: &Map<String, JsonValue>) -> Result<Uuid, String> {
+    let uuid = object
+        .get("uuid")
+        .and_then(JsonValue::as_str)
+        .ok_or("Operation is missing its 'uuid'")?;
+    Uuid::parse_str(uuid).map_err(|_| format!("Invalid UUID '{}'", uuid))
+}
+
+fn optional_string(object: &Map<String, JsonValue>, key: &str) -> Result<Option<String>, String> {
+    match object.get(key) {
+        None | Some(JsonValue::Null) => Ok(None),
+        Some(JsonValue::String(s)) => Ok(Some(s.clone())),
+        Some(_) => Err(format!("'{}' must be a string or null", key)),
+    }
+}
+
+/// Parse a JSON array of operations
+pub fn parse_array(input: &str) -> Result<Vec<TCOperation>, String> {
+    match serde_json::from_str(input) {
+        Ok(JsonValue::Array(values)) => values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| from_json(value).map_err(|e| format!("Operation {}: {}", i, e)))
+            .collect(),
+        Ok(_) => Err("Expected a JSON array of operations".to_string()),
+        Err(e) => Err(format!("Invalid JSON: {}", e)),
+    }
+}
+
+/// Parse one NDJSON line, returning None for blank lines. `line_number` is
+/// 1-based and only used in error messages.
+pub fn parse_line(line: &str, line_number: usize) -> Result<Option<TCOperation>, String> {
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+    let value: JsonValue = serde_json::from_str(line)
+        .map_err(|e| format!("Invalid JSON on line {}: {}", line_number, e))?;
+    from_json(&value)
+        .map(Some)
+        .map_err(|e| format!("Line {}: {}", line_number, e))
+}
@@ -1,10 +1,13 @@
 use magnus::{
-    class, function, method, prelude::*, Error, IntoValue, RArray, RModule, Ruby, Value,
+    class, function, method, prelude::*, Error, IntoValue, RArray, RModule, RString, Ruby, Value,
 };
+use serde_json::Value as JsonValue;
 use std::cell::RefCell;
 use taskchampion::Operations as TCOperations;
 
+use crate::error::validation_error;
 use crate::operation::Operation;
+use crate::operation_json;
 use crate::thread_check::ThreadBound;
 
 #[magnus::wrap(class = "Taskchampion::Operations", free_immediately)]
@@ -21,7 +24,7 @@ impl Operations {
         Ok(())
     }
 
-    fn len(&self) -> Result<usize, Error> {
+    pub(crate) fn len(&self) -> Result<usize, Error> {
         let ops = self.0.get()?;
         let borrowed = ops.borrow();
         Ok(borrowed.len())
@@ -103,12 +106,69 @@ impl Operations {
         Ok(format!("#<Taskchampion::Operations: {} operations>", ops.borrow().len()))
     }
 
-    fn clear(&self) -> Result<(), Error> {
+    pub(crate) fn clear(&self) -> Result<(), Error> {
         let ops = self.0.get()?;
         ops.borrow_mut().clear();
         Ok(())
     }
 
+    // Arguments from JSON.generate are accepted and ignored
+    fn to_json(&self, _args: &[Value]) -> Result<String, Error> {
+        let ops = self.0.get()?;
+        let ops = ops.borrow();
+        Ok(JsonValue::Array(ops.iter().map(operation_json::to_json).collect()).to_string())
+    }
+
+    fn from_json(json: String) -> Result<Self, Error> {
+        let ops = operation_json::parse_array(&json).map_err(|e| Error::new(validation_error(), e))?;
+        Ok(Operations::from(ops))
+    }
+
+    fn ndjson_lines(&self) -> Result<Vec<String>, Error> {
+        let ops = self.0.get()?;
+        let ops = ops.borrow();
+        Ok(ops.iter().map(|op| operation_json::to_json(op).to_string() + "\n").collect())
+    }
+
+    fn to_ndjson(&self) -> Result<String, Error> {
+        Ok(self.ndjson_lines()?.concat())
+    }
+
+    /// Write one line per operation to `io`, returning the number written
+    fn dump_ndjson(&self, io: Value) -> Result<usize, Error> {
+        // Encode first so no borrow is held while Ruby code runs
+        let lines = self.ndjson_lines()?;
+        for line in &lines {
+            io.funcall::<_, _, Value>("write", (line.as_str(),))?;
+        }
+        Ok(lines.len())
+    }
+
+    /// Read operations from an NDJSON String, or line by line from anything
+    /// that responds to `gets` such as an IO or StringIO
+    fn load_ndjson(source: Value) -> Result<Self, Error> {
+        let mut ops = TCOperations::new();
+        let mut push_line = |line: &str, line_number: usize| -> Result<(), Error> {
+            let op = operation_json::parse_line(line, line_number).map_err(|e| Error::new(validation_error(), e))?;
+            ops.extend(op);
+            Ok(())
+        };
+
+        if let Some(text) = RString::from_value(source) {
+            for (i, line) in text.to_string()?.lines().enumerate() {
+                push_line(line, i + 1)?;
+            }
+        } else {
+            let mut line_number = 0;
+            while let Some(line) = source.funcall::<_, _, Option<String>>("gets", ())? {
+                line_number += 1;
+                push_line(&line, line_number)?;
+            }
+        }
+
+        Ok(Operations::from(ops))
+    }
+
     // Internal method for accessing the operations
     pub(crate) fn clone_inner(&self) -> Result<TCOperations, Error> {
         let ops = self.0.get()?;
@@ -174,6 +234,8 @@ pub fn init(module: &RModule) -> Result<(), Error> {
     let class = module.define_class("Operations", class::object())?;
 
     class.define_singleton_method("new", function!(Operations::new, 0))?;
+    class.define_singleton_method("from_json", function!(Operations::from_json, 1))?;
+    class.define_singleton_method("load_ndjson", function!(Operations::load_ndjson, 1))?;
     class.define_method("push", method!(Operations::push, 1))?;
     class.define_method("<<", method!(Operations::push, 1))?;
     class.define_method("length", method!(Operations::len, 0))?;
@@ -184,6 +246,9 @@ pub fn init(module: &RModule) -> Result<(), Error> {
     class.define_method("to_a", method!(Operations::to_array, 0))?;
     class.define_method("inspect", method!(Operations::inspect, 0))?;
     class.define_method("clear", method!(Operations::clear, 0))?;
+    class.define_method("to_json", method!(Operations::to_json, -1))?;
+    class.define_method("to_ndjson", method!(Operations::to_ndjson, 0))?;
+    class.define_method("dump_ndjson", method!(Operations::dump_ndjson, 1))?;
 
     Ok(())
 }
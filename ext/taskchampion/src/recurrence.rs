@@ -0,0 +1,380 @@
+use chrono::{DateTime, Months, NaiveDate, NaiveDateTime, TimeDelta, Utc};
+use magnus::Error;
+use std::collections::{HashMap, HashSet};
+use taskchampion::{
+    Operation as TCOperation, Operations as TCOperations, Replica as TCReplica, TaskData as TCTaskData, Uuid,
+};
+
+use crate::error::validation_error;
+
+/// Upper bound on instances generated for one template in a single call, so
+/// a short period with a distant horizon cannot run away
+const MAX_INSTANCES: usize = 10_000;
+
+/// Template properties that are not copied to generated instances
+const TEMPLATE_ONLY: &[&str] = &["status", "mask", "imask", "parent", "entry", "modified", "start", "end"];
+
+/// A recurrence period. Months are applied first, as calendar months, so a
+/// monthly task due on the 31st falls on the last day of shorter months.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Period {
+    months: u32,
+    days: u32,
+    seconds: u32,
+}
+
+impl Period {
+    fn new(months: u32, days: u32, seconds: u32) -> Option<Self> {
+        (months > 0 || days > 0 || seconds > 0).then_some(Period { months, days, seconds })
+    }
+
+    /// Parse a Taskwarrior `recur` value: a named period (`daily`, `weekly`,
+    /// `monthly`, `quarterly`, `annual`, ...), a count and unit (`2w`,
+    /// `3days`) or an ISO 8601 duration (`P2W`, `P1M`, `PT12H`)
+    pub fn parse(recur: &str) -> Option<Self> {
+        let recur = recur.trim();
+        let named = match recur.to_ascii_lowercase().as_str() {
+            "daily" | "day" => Some((0, 1, 0)),
+            "weekly" | "week" => Some((0, 7, 0)),
+            "biweekly" | "fortnight" => Some((0, 14, 0)),
+            "monthly" | "month" => Some((1, 0, 0)),
+            "bimonthly" => Some((2, 0, 0)),
+            "quarterly" | "quarter" => Some((3, 0, 0)),
+            "semiannual" => Some((6, 0, 0)),
+            "annual" | "yearly" | "year" => Some((12, 0, 0)),
+            "biannual" | "biyearly" => Some((24, 0, 0)),
+            _ => None,
+        };
+        if let Some((months, days, seconds)) = named {
+            return Period::new(months, days, seconds);
+        }
+        if recur.starts_with('P') {
+            return Self::parse_iso(recur);
+        }
+
+        let split = recur.find(|c: char| !c.is_ascii_digit())?;
+        let (count, unit) = recur.split_at(split);
+        let count: u32 = count.parse().ok()?;
+        let (months, days, seconds) = match unit.to_ascii_lowercase().as_str() {
+            "h" | "hr" | "hrs" | "hour" | "hours" => (0, 0, count.checked_mul(3600)?),
+            "d" | "day" | "days" => (0, count, 0),
+            "w" | "wk" | "wks" | "week" | "weeks" => (0, count.checked_mul(7)?, 0),
+            "m" | "mo" | "mos" | "month" | "months" => (count, 0, 0),
+            "q" | "qtr" | "qtrs" | "quarter" | "quarters" => (count.checked_mul(3)?, 0, 0),
+            "y" | "yr" | "yrs" | "year" | "years" => (count.checked_mul(12)?, 0, 0),
+            _ => return None,
+        };
+        Period::new(months, days, seconds)
+    }
+
+    fn parse_iso(recur: &str) -> Option<Self> {
+        let (date, time) = match recur[1..].split_once('T') {
+            Some((date, time)) => (date, Some(time)),
+            None => (&recur[1..], None),
+        };
+        let (mut months, mut days, mut seconds) = (0u32, 0u32, 0u32);
+        let mut number = String::new();
+        for c in date.chars() {
+            if c.is_ascii_digit() {
+                number.push(c);
+                continue;
+            }
+            let n: u32 = std::mem::take(&mut number).parse().ok()?;
+            match c {
+                'Y' => months = months.checked_add(n.checked_mul(12)?)?,
+                'M' => months = months.checked_add(n)?,
+                'W' => days = days.checked_add(n.checked_mul(7)?)?,
+                'D' => days = days.checked_add(n)?,
+                _ => return None,
+            }
+        }
+        for c in time.unwrap_or("").chars() {
+            if c.is_ascii_digit() {
+                number.push(c);
+                continue;
+            }
+            let n: u32 = std::mem::take(&mut number).parse().ok()?;
+            let unit = match c {
+                'H' => 3600,
+                'M' => 60,
+                'S' => 1,
+                _ => return None,
+            };
+            seconds = seconds.checked_add(n.checked_mul(unit)?)?;
+        }
+        // Trailing digits without a unit, or a bare `P`/`PT`
+        if !number.is_empty() {
+            return None;
+        }
+        Period::new(months, days, seconds)
+    }
+
+    /// The ISO 8601 form, used to store RRULE-based recurrences
+    fn to_iso(self) -> String {
+        let mut iso = String::from("P");
+        if self.months > 0 {
+            iso.push_str(&format!("{}M", self.months));
+        }
+        if self.days > 0 {
+            if self.days.is_multiple_of(7) && self.months == 0 {
+                iso.push_str(&format!("{}W", self.days / 7));
+            } else {
+                iso.push_str(&format!("{}D", self.days));
+            }
+        }
+        if self.seconds > 0 {
+            iso.push_str(&format!("T{}S", self.seconds));
+        }
+        iso
+    }
+
+    /// The `index`th occurrence after `start`, counting `start` as the 0th
+    pub fn nth(&self, start: DateTime<Utc>, index: u32) -> Option<DateTime<Utc>> {
+        let shifted = start.checked_add_months(Months::new(self.months.checked_mul(index)?))?;
+        let days = TimeDelta::try_days(i64::from(self.days) * i64::from(index))?;
+        let seconds = TimeDelta::try_seconds(i64::from(self.seconds) * i64::from(index))?;
+        shifted.checked_add_signed(days)?.checked_add_signed(seconds)
+    }
+
+    /// Length in seconds, counting a year as 365 days and other months as
+    /// 30, as Taskwarrior does for durations
+    pub fn as_seconds(self) -> u64 {
+        let years = u64::from(self.months / 12);
+        let months = u64::from(self.months % 12);
+        (years * 365 + months * 30 + u64::from(self.days)) * 86_400 + u64::from(self.seconds)
+    }
+}
+
+/// Parse a duration value such as an estimate UDA: a whole number of
+/// seconds or anything `Period::parse` accepts
+pub fn parse_duration(value: &str) -> Option<u64> {
+    let value = value.trim();
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(seconds),
+        // A zero period is not a valid recurrence, but is a valid duration
+        Err(_) if value == "PT0S" => Some(0),
+        Err(_) => Period::parse(value).map(Period::as_seconds),
+    }
+}
+
+/// The ISO 8601 form of a duration in seconds, as Taskwarrior stores
+/// duration UDAs, using days and smaller units (`P1DT2H30M`)
+pub fn format_duration(seconds: u64) -> String {
+    if seconds == 0 {
+        return "PT0S".to_string();
+    }
+    let (days, rest) = (seconds / 86_400, seconds % 86_400);
+    let (hours, minutes, seconds) = (rest / 3600, rest % 3600 / 60, rest % 60);
+
+    let mut iso = String::from("P");
+    if days > 0 {
+        iso.push_str(&format!("{}D", days));
+    }
+    if rest > 0 {
+        iso.push('T');
+        for (count, unit) in [(hours, 'H'), (minutes, 'M'), (seconds, 'S')] {
+            if count > 0 {
+                iso.push_str(&format!("{}{}", count, unit));
+            }
+        }
+    }
+    iso
+}
+
+/// How an RRULE limits its occurrences
+pub enum Limit {
+    Until(DateTime<Utc>),
+    Count(u32),
+}
+
+/// A validated recurrence rule: the value to store in `recur`, plus any end
+/// the rule itself specified
+pub struct Rule {
+    pub recur: String,
+    pub period: Period,
+    pub limit: Option<Limit>,
+}
+
+fn rule_error(message: impl Into<String>) -> Error {
+    Error::new(validation_error(), message.into())
+}
+
+/// Parse a Taskwarrior period or an RRULE string (`FREQ=WEEKLY;INTERVAL=2`,
+/// optionally prefixed with `RRULE:`). RRULEs are stored as the equivalent
+/// ISO 8601 duration so Taskwarrior can read them too; only `FREQ`,
+/// `INTERVAL`, `COUNT`, `UNTIL` and `WKST` are supported.
+pub fn parse_rule(rule: &str) -> Result<Rule, Error> {
+    let trimmed = rule.trim();
+    let rrule = trimmed.strip_prefix("RRULE:").unwrap_or(trimmed);
+    if !rrule.to_ascii_uppercase().starts_with("FREQ=") {
+        let period = Period::parse(trimmed)
+            .ok_or_else(|| rule_error(format!("Invalid recurrence '{}'", rule)))?;
+        return Ok(Rule { recur: trimmed.to_string(), period, limit: None });
+    }
+
+    let mut freq = None;
+    let mut interval = 1u32;
+    let mut limit = None;
+    for part in rrule.split(';').filter(|p| !p.is_empty()) {
+        let (key, value) = part
+            .split_once('=')
+            .ok_or_else(|| rule_error(format!("Invalid RRULE part '{}'", part)))?;
+        match key.to_ascii_uppercase().as_str() {
+            "FREQ" => freq = Some(value.to_ascii_uppercase()),
+            "INTERVAL" => {
+                interval = value
+                    .parse()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| rule_error(format!("Invalid RRULE INTERVAL '{}'", value)))?;
+            }
+            "COUNT" => {
+                let count = value
+                    .parse()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| rule_error(format!("Invalid RRULE COUNT '{}'", value)))?;
+                limit = Some(Limit::Count(count));
+            }
+            "UNTIL" => {
+                let until = parse_rrule_date(value)
+                    .ok_or_else(|| rule_error(format!("Invalid RRULE UNTIL '{}'", value)))?;
+                limit = Some(Limit::Until(until));
+            }
+            // Only affects BYDAY expansion, which is not supported
+            "WKST" => {}
+            other => return Err(rule_error(format!("Unsupported RRULE part '{}'", other))),
+        }
+    }
+
+    let period = match freq.as_deref() {
+        Some("HOURLY") => interval.checked_mul(3600).and_then(|s| Period::new(0, 0, s)),
+        Some("DAILY") => Period::new(0, interval, 0),
+        Some("WEEKLY") => interval.checked_mul(7).and_then(|d| Period::new(0, d, 0)),
+        Some("MONTHLY") => Period::new(interval, 0, 0),
+        Some("YEARLY") => interval.checked_mul(12).and_then(|m| Period::new(m, 0, 0)),
+        _ => None,
+    }
+    .ok_or_else(|| rule_error(format!("Unsupported RRULE FREQ in '{}'", rule)))?;
+
+    Ok(Rule { recur: period.to_iso(), period, limit })
+}
+
+fn parse_rrule_date(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), "%Y%m%dT%H%M%S") {
+        return Some(dt.and_utc());
+    }
+    // A date-only UNTIL includes the whole day
+    NaiveDate::parse_from_str(value, "%Y%m%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(23, 59, 59))
+        .map(|dt| dt.and_utc())
+}
+
+fn timestamp(task: &TCTaskData, name: &str) -> Option<DateTime<Utc>> {
+    task.get(name)
+        .and_then(|s| s.parse::<i64>().ok())
+        .and_then(|secs| DateTime::from_timestamp(secs, 0))
+}
+
+/// Build the operations creating the pending instances of every recurring
+/// template that fall due by `horizon`. Instances already recorded in a
+/// template's `mask`, or pending with its `parent` and `imask`, are not
+/// created again.
+///
+/// Returns the operations, for the caller to commit, and the new instances'
+/// UUIDs.
+pub fn generate_instances(
+    replica: &mut TCReplica,
+    horizon: DateTime<Utc>,
+) -> Result<(TCOperations, Vec<Uuid>), taskchampion::Error> {
+    let now = Utc::now();
+    // Recurring templates are kept in the working set along with pending tasks
+    let working_set = replica.pending_task_data()?;
+
+    let mut existing: HashMap<Uuid, HashSet<u32>> = HashMap::new();
+    for data in &working_set {
+        let parent = data.get("parent").and_then(|p| Uuid::parse_str(p).ok());
+        let imask = data.get("imask").and_then(|i| i.parse::<f64>().ok());
+        if let (Some(parent), Some(imask)) = (parent, imask) {
+            existing.entry(parent).or_default().insert(imask as u32);
+        }
+    }
+
+    let mut templates: Vec<TCTaskData> = working_set
+        .into_iter()
+        .filter(|data| data.get("status") == Some("recurring"))
+        .collect();
+    templates.sort_by_key(|data| data.get_uuid());
+
+    let mut ops = TCOperations::new();
+    ops.push(TCOperation::UndoPoint);
+    let mut created = Vec::new();
+
+    for mut template in templates {
+        // Templates Taskwarrior could not expand either are left alone
+        let Some(period) = template.get("recur").and_then(Period::parse) else {
+            continue;
+        };
+        let Some(due) = timestamp(&template, "due") else {
+            continue;
+        };
+        let until = timestamp(&template, "until");
+        let wait_offset = timestamp(&template, "wait").map(|wait| wait - due);
+        let scheduled_offset = timestamp(&template, "scheduled").map(|scheduled| scheduled - due);
+        let children = existing.remove(&template.get_uuid()).unwrap_or_default();
+
+        let original_mask = template.get("mask").unwrap_or("").to_string();
+        let mut mask: Vec<char> = original_mask.chars().collect();
+
+        for index in 0..MAX_INSTANCES as u32 {
+            let Some(instance_due) = period.nth(due, index) else {
+                break;
+            };
+            if instance_due > horizon || until.is_some_and(|until| instance_due > until) {
+                break;
+            }
+            let position = index as usize;
+            if position < mask.len() || children.contains(&index) {
+                continue;
+            }
+
+            let uuid = Uuid::new_v4();
+            let mut child = TCTaskData::create(uuid, &mut ops);
+            let mut properties: Vec<(String, String)> = template
+                .iter()
+                .filter(|(key, _)| !TEMPLATE_ONLY.contains(&key.as_str()))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+            properties.sort();
+            for (key, value) in properties {
+                child.update(key, Some(value), &mut ops);
+            }
+            child.update("status", Some("pending".into()), &mut ops);
+            child.update("parent", Some(template.get_uuid().to_string()), &mut ops);
+            child.update("imask", Some(index.to_string()), &mut ops);
+            child.update("due", Some(instance_due.timestamp().to_string()), &mut ops);
+            if let Some(offset) = wait_offset {
+                child.update("wait", Some((instance_due + offset).timestamp().to_string()), &mut ops);
+            }
+            if let Some(offset) = scheduled_offset {
+                child.update("scheduled", Some((instance_due + offset).timestamp().to_string()), &mut ops);
+            }
+            child.update("entry", Some(now.timestamp().to_string()), &mut ops);
+            child.update("modified", Some(now.timestamp().to_string()), &mut ops);
+            created.push(uuid);
+
+            // Positions of instances that exist without a mask entry are
+            // treated as pending, like the new one
+            mask.resize(position + 1, '-');
+        }
+
+        let mask: String = mask.into_iter().collect();
+        if mask != original_mask {
+            template.update("mask", Some(mask), &mut ops);
+            template.update("modified", Some(now.timestamp().to_string()), &mut ops);
+        }
+    }
+
+    Ok((ops, created))
+}
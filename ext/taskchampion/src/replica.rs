@@ -1,19 +1,66 @@
 use magnus::{
-    class, function, method, prelude::*, Error, IntoValue, RArray, RHash, RModule, Symbol, TryConvert, Value,
+    block::Proc, class, function, gc::Marker, method, prelude::*, typed_data::Obj, value::Opaque,
+    DataTypeFunctions, Error, IntoValue, KwArgs, RArray, RHash, RModule, Ruby, Symbol, TryConvert, TypedData, Value,
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use magnus::scan_args::{get_kwargs, scan_args};
+use std::cell::RefMut;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use taskchampion::{
+    server::AwsCredentials, DependencyMap as TCDependencyMap, Operation as TCOperation, Operations as TCOperations,
+    Replica as TCReplica, ServerConfig, StorageConfig, Task as TCTask, TaskData as TCTaskData, Uuid,
+    WorkingSet as TCWorkingSet,
 };
-use taskchampion::{Replica as TCReplica, ServerConfig, StorageConfig};
 
 use crate::access_mode::AccessMode;
 use crate::operations::Operations;
-use crate::task::Task;
+use crate::task::{cycle_error, Task};
 use crate::task_data::TaskData;
+use crate::transaction::Transaction;
+use crate::taskwarrior;
 use crate::working_set::WorkingSet;
 use crate::dependency_map::DependencyMap;
+use crate::dependency_graph;
 use crate::thread_check::ThreadBound;
-use crate::util::{into_error, option_to_ruby, uuid2tc, vec_to_ruby};
-
-#[magnus::wrap(class = "Taskchampion::Replica", free_immediately)]
-pub struct Replica(ThreadBound<TCReplica>);
+use crate::backup;
+use crate::error::{hook_error, storage_error, validation_error};
+use crate::filter::{Filter, FilterContext};
+use crate::gvl::without_gvl;
+use crate::recurrence;
+use crate::search::{self, SearchIndex, StorageVersion};
+use crate::status::Status;
+use crate::urgency::{self, Coefficients, UrgencyConfig};
+use crate::history::{self, TaskChange};
+use crate::ical;
+use crate::hooks::{self, HookConfig, HookError};
+use crate::sync_result::{sync_replica, SyncResult};
+use crate::uda_schema::UdaSchema;
+use crate::util::{datetime_to_ruby, into_error, option_to_ruby, ruby_to_datetime, uuid2tc, vec_to_ruby};
+
+/// The second field is the storage directory of an on-disk replica, used
+/// for backups. The third is the search index, if one has been enabled, the
+/// fourth the UDA schema given to tasks loaded from the replica, the fifth
+/// the procs registered with `on_commit` and the sixth the Taskwarrior hook
+/// directory and timeout, if hooks have been enabled.
+#[derive(TypedData)]
+#[magnus(class = "Taskchampion::Replica", free_immediately, mark)]
+pub struct Replica(
+    ThreadBound<TCReplica>,
+    Option<PathBuf>,
+    ThreadBound<Option<SearchIndex>>,
+    ThreadBound<Option<UdaSchema>>,
+    Opaque<RArray>,
+    ThreadBound<Option<HookConfig>>,
+);
+
+impl DataTypeFunctions for Replica {
+    fn mark(&self, marker: &Marker) {
+        marker.mark(self.4);
+    }
+}
 
 impl Replica {
     fn new_on_disk(
@@ -21,33 +68,53 @@ impl Replica {
         create_if_missing: bool,
         access_mode: Option<Symbol>,
     ) -> Result<Self, Error> {
-        let access_mode = match access_mode {
-            Some(sym) => AccessMode::from_symbol(sym)?,
-            None => AccessMode::from_symbol(Symbol::new("read_write"))?,
-        };
-
-        let replica = TCReplica::new(
-            StorageConfig::OnDisk {
-                taskdb_dir: path.into(),
-                create_if_missing,
-                access_mode: access_mode.into(),
-            }
-            .into_storage()
-            .map_err(into_error)?,
-        );
-        Ok(Replica(ThreadBound::new(replica)))
+        let taskdb_dir = PathBuf::from(&path);
+        let replica = on_disk_replica(path, create_if_missing, access_mode)?;
+        Ok(Replica(
+            ThreadBound::new(replica),
+            Some(taskdb_dir),
+            ThreadBound::new(None),
+            ThreadBound::new(None),
+            RArray::new().into(),
+            ThreadBound::new(None),
+        ))
     }
 
     fn new_in_memory() -> Result<Self, Error> {
-        let replica = TCReplica::new(
-            StorageConfig::InMemory
-                .into_storage()
-                .map_err(into_error)?,
-        );
-        Ok(Replica(ThreadBound::new(replica)))
+        let replica = in_memory_replica()?;
+        Ok(Replica(
+            ThreadBound::new(replica),
+            None,
+            ThreadBound::new(None),
+            ThreadBound::new(None),
+            RArray::new().into(),
+            ThreadBound::new(None),
+        ))
     }
 
-    fn create_task(&self, uuid: String, operations: &Operations) -> Result<Value, Error> {
+    fn restore_from(path: String, kwargs: RHash) -> Result<Self, Error> {
+        let into: String = kwargs.fetch(Symbol::new("into")).map_err(|_| Error::new(
+            magnus::exception::arg_error(),
+            "Missing required parameter: into"
+        ))?;
+
+        without_gvl(|| backup::restore(Path::new(&path), Path::new(&into)))?
+            .map_err(|e| Error::new(storage_error(), e))?;
+        Self::new_on_disk(into, false, None)
+    }
+
+    fn backup_to(&self, path: String) -> Result<(), Error> {
+        self.0.check_thread()?;
+        let Some(taskdb_dir) = &self.1 else {
+            return Err(Error::new(validation_error(), "Only on-disk replicas can be backed up"));
+        };
+
+        let source = taskdb_dir.join(backup::DB_FILE);
+        without_gvl(|| backup::vacuum_into(&source, Path::new(&path)))?
+            .map_err(|e| Error::new(storage_error(), e))
+    }
+
+    pub(crate) fn create_task(&self, uuid: String, operations: &Operations) -> Result<Value, Error> {
         let mut tc_replica = self.0.get_mut()?;
         let tc_uuid = uuid2tc(&uuid)?;
 
@@ -56,36 +123,215 @@ impl Replica {
 
         // Create the task in TaskChampion
         let tc_task = tc_replica.create_task(tc_uuid, &mut tc_ops).map_err(into_error)?;
+        let depmap = tc_replica.dependency_map(false).map_err(into_error)?;
 
         // Add the resulting operations to the provided Operations object
         operations.extend_from_tc(tc_ops)?;
 
         // Convert to Ruby Task object
-        let task = Task::from_tc_task(tc_task);
+        let task = self.wrap_task(tc_task, depmap)?;
 
         Ok(task.into_value())
     }
 
-    fn commit_operations(&self, operations: &Operations) -> Result<(), Error> {
+    pub(crate) fn commit_operations(&self, operations: &Operations) -> Result<(), Error> {
         let mut tc_replica = self.0.get_mut()?;
 
         // Convert Operations to TaskChampion Operations
-        let tc_operations = operations.clone_inner()?;
+        let mut tc_operations = operations.clone_inner()?;
+        // Check before the hooks so they never see a change that is rejected
+        reject_new_cycle(&mut tc_replica, &tc_operations)?;
+        self.run_taskwarrior_hooks(&mut tc_replica, &mut tc_operations)?;
+
+        self.commit_and_notify(tc_replica, tc_operations, "commit")
+    }
+
+    /// Run the enabled Taskwarrior hooks on the tasks `tc_operations` add
+    /// or modify, adding the hooks' changes to them. Hooks can add
+    /// dependencies too, so the amended operations are checked for cycles.
+    fn run_taskwarrior_hooks(&self, tc_replica: &mut TCReplica, tc_operations: &mut TCOperations) -> Result<(), Error> {
+        let Some(config) = self.5.get()?.clone() else {
+            return Ok(());
+        };
+        let data_dir = self.1.as_deref();
+        let results = without_gvl(|| hooks::run_hooks(tc_replica, &config, data_dir, tc_operations))?
+            .map_err(|e| match e {
+                HookError::TaskChampion(e) => into_error(e),
+                HookError::Hook(message) => Error::new(hook_error(), message),
+            })?;
+        let hook_operations = hooks::hook_operations(&results)?;
+        if hook_operations.is_empty() {
+            return Ok(());
+        }
+        tc_operations.extend(hook_operations);
+        reject_new_cycle(tc_replica, tc_operations)
+    }
 
-        // Commit the operations
-        tc_replica.commit_operations(tc_operations).map_err(into_error)?;
+    /// Run the Taskwarrior on-add and on-modify hooks in `dir`, or in the
+    /// `hooks` directory beside an on-disk replica's database, on every
+    /// change that adds or modifies tasks, killing any that run longer than
+    /// `timeout:` seconds
+    fn enable_taskwarrior_hooks(&self, args: &[Value]) -> Result<(), Error> {
+        let args = scan_args::<(), (Option<Option<String>>,), (), (), RHash, ()>(args)?;
+        let (dir,) = args.optional;
+        let kwargs = get_kwargs::<_, (), (Option<Option<f64>>,), ()>(args.keywords, &[], &["timeout"])?;
+        let (timeout,) = kwargs.optional;
+        let timeout = match timeout.flatten() {
+            Some(seconds) => Duration::try_from_secs_f64(seconds)
+                .ok()
+                .filter(|timeout| !timeout.is_zero())
+                .ok_or_else(|| Error::new(
+                    validation_error(),
+                    format!("Hook timeout must be a positive number of seconds, got {}", seconds)
+                ))?,
+            None => hooks::DEFAULT_TIMEOUT,
+        };
+        let dir = match (dir.flatten(), &self.1) {
+            (Some(dir), _) => PathBuf::from(dir),
+            (None, Some(taskdb_dir)) => taskdb_dir.join("hooks"),
+            (None, None) => {
+                return Err(Error::new(
+                    validation_error(),
+                    "In-memory replicas need a hook directory",
+                ))
+            }
+        };
+        if !dir.is_dir() {
+            return Err(Error::new(
+                validation_error(),
+                format!("Hook directory {} does not exist", dir.display()),
+            ));
+        }
+        *self.5.get_mut()? = Some(HookConfig { dir, timeout });
+        Ok(())
+    }
 
+    fn disable_taskwarrior_hooks(&self) -> Result<(), Error> {
+        *self.5.get_mut()? = None;
         Ok(())
     }
 
+    fn taskwarrior_hooks_dir(&self) -> Result<Option<String>, Error> {
+        Ok(self.5.get()?.as_ref().map(|config| config.dir.display().to_string()))
+    }
+
+    /// Register a block to call with a summary of each commit, undo, sync or
+    /// import that changes tasks. Returns the block, for `remove_on_commit`.
+    fn on_commit(ruby: &Ruby, rb_self: Obj<Self>) -> Result<Proc, Error> {
+        rb_self.0.check_thread()?;
+        let block = ruby.block_proc()?;
+        ruby.get_inner(rb_self.4).push(block)?;
+        Ok(block)
+    }
+
+    /// Unregister a block added with `on_commit`, returning whether it was
+    /// registered
+    fn remove_on_commit(ruby: &Ruby, rb_self: Obj<Self>, hook: Proc) -> Result<bool, Error> {
+        rb_self.0.check_thread()?;
+        let hooks = ruby.get_inner(rb_self.4);
+        if !hooks.includes(hook) {
+            return Ok(false);
+        }
+        hooks.delete(hook)?;
+        Ok(true)
+    }
+
+    fn has_commit_hooks(&self) -> Result<bool, Error> {
+        let ruby = Ruby::get().map_err(|e| Error::new(magnus::exception::runtime_error(), e.to_string()))?;
+        Ok(!ruby.get_inner(self.4).is_empty())
+    }
+
+    /// Call the `on_commit` hooks with `changes`, if there are any. The
+    /// replica must not be borrowed, so hooks can use it. An exception from a
+    /// hook propagates, but the changes have already been committed.
+    fn notify_commit(&self, changes: Vec<TaskChange>, source: &str) -> Result<(), Error> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let ruby = Ruby::get().map_err(|e| Error::new(magnus::exception::runtime_error(), e.to_string()))?;
+        // Hooks may register or remove hooks, which takes effect next time
+        let hooks: Vec<Proc> = ruby.get_inner(self.4).to_vec()?;
+        if hooks.is_empty() {
+            return Ok(());
+        }
+
+        let array = RArray::new();
+        for change in changes {
+            let diffs = RHash::new();
+            for (property, old_value, value) in change.changes {
+                diffs.aset(property, RArray::from_vec(vec![old_value, value]))?;
+            }
+
+            let hash = RHash::new();
+            hash.aset(Symbol::new("uuid"), change.uuid.to_string())?;
+            hash.aset(Symbol::new("kind"), Symbol::new(change.kind.name()))?;
+            hash.aset(Symbol::new("source"), Symbol::new(source))?;
+            hash.aset(Symbol::new("changes"), diffs)?;
+            array.push(hash)?;
+        }
+
+        for hook in hooks {
+            hook.call::<_, Value>((array,))?;
+        }
+        Ok(())
+    }
+
+    /// Commit `tc_operations`, update the search index for the tasks they
+    /// touch and call the `on_commit` hooks, naming `source` as the change's
+    /// origin. Takes the replica borrow so it can be released before the
+    /// hooks run.
+    fn commit_and_notify(
+        &self,
+        mut tc_replica: RefMut<'_, TCReplica>,
+        tc_operations: TCOperations,
+        source: &str,
+    ) -> Result<(), Error> {
+        let touched = search::touched_uuids(&tc_operations);
+        let changes = if self.has_commit_hooks()? {
+            history::summarize_operations(&tc_operations)
+        } else {
+            Vec::new()
+        };
+
+        let index_version = self.search_index_version()?;
+        without_gvl(|| tc_replica.commit_operations(tc_operations))?.map_err(into_error)?;
+        self.update_search_index(&mut tc_replica, &touched, index_version)?;
+
+        // Hooks may use the replica
+        drop(tc_replica);
+        self.notify_commit(changes, source)
+    }
+
+    /// Wrap a task loaded from this replica, with the replica's UDA schema
+    fn wrap_task(&self, tc_task: TCTask, depmap: Arc<TCDependencyMap>) -> Result<Task, Error> {
+        let uda_schema = self.3.get()?.clone();
+        Ok(Task::from_tc_task(tc_task, depmap).with_uda_schema(uda_schema))
+    }
+
+    fn uda_schema(&self) -> Result<Option<UdaSchema>, Error> {
+        Ok(self.3.get()?.clone())
+    }
+
+    /// Register `schema` for tasks loaded from now on, or remove it with nil.
+    /// The schema is shared, so later definitions apply to those tasks too.
+    fn set_uda_schema(&self, schema: Option<&UdaSchema>) -> Result<(), Error> {
+        *self.3.get_mut()? = schema.cloned();
+        Ok(())
+    }
+
+    fn transaction(ruby: &Ruby, rb_self: Obj<Self>) -> Result<Value, Error> {
+        Transaction::run(ruby, rb_self)
+    }
+
     fn all_tasks(&self) -> Result<RHash, Error> {
         let mut tc_replica = self.0.get_mut()?;
 
-        let tasks = tc_replica.all_tasks().map_err(into_error)?;
+        let tasks = without_gvl(|| tc_replica.all_tasks())?.map_err(into_error)?;
+        let depmap = tc_replica.dependency_map(false).map_err(into_error)?;
         let hash = RHash::new();
 
         for (uuid, task) in tasks {
-            let ruby_task = Task::from_tc_task(task);
+            let ruby_task = self.wrap_task(task, depmap.clone())?;
             // Magnus automatically wraps ruby_task as a Taskchampion::Task Ruby object
             hash.aset(uuid.to_string(), ruby_task)?;
         }
@@ -96,17 +342,409 @@ impl Replica {
     fn pending_tasks(&self) -> Result<RArray, Error> {
         let mut tc_replica = self.0.get_mut()?;
 
-        let tc_tasks = tc_replica.pending_tasks().map_err(into_error)?;
+        let tc_tasks = without_gvl(|| tc_replica.pending_tasks())?.map_err(into_error)?;
+        let depmap = tc_replica.dependency_map(false).map_err(into_error)?;
 
         let array = RArray::new();
         for tc_task in tc_tasks {
-            let ruby_task = crate::task::Task::from_tc_task(tc_task);
+            let ruby_task = self.wrap_task(tc_task, depmap.clone())?;
             array.push(ruby_task)?;
         }
 
         Ok(array)
     }
 
+    fn active_tasks(&self) -> Result<RArray, Error> {
+        let mut tc_replica = self.0.get_mut()?;
+
+        // The working set keeps this cheap compared to scanning every task in
+        // the replica, but it can still hold tasks completed since it was built
+        let tc_tasks = tc_replica.pending_tasks().map_err(into_error)?;
+        let depmap = tc_replica.dependency_map(false).map_err(into_error)?;
+
+        let array = RArray::new();
+        for tc_task in tc_tasks
+            .into_iter()
+            .filter(|t| t.get_status() == taskchampion::Status::Pending && t.is_active())
+        {
+            array.push(self.wrap_task(tc_task, depmap.clone())?)?;
+        }
+
+        Ok(array)
+    }
+
+    fn tasks_waking_between(&self, from: Value, to: Value) -> Result<RArray, Error> {
+        let from = ruby_to_datetime(from)?;
+        let to = ruby_to_datetime(to)?;
+        if to < from {
+            return Err(Error::new(
+                validation_error(),
+                "End of the wake-up window must not be before its start"
+            ));
+        }
+
+        let mut tc_replica = self.0.get_mut()?;
+
+        // Waiting tasks are pending, so the working set covers them. It can
+        // still hold tasks completed since it was built, so check the status
+        let tc_tasks = tc_replica.pending_tasks().map_err(into_error)?;
+        let depmap = tc_replica.dependency_map(false).map_err(into_error)?;
+
+        let mut waking: Vec<_> = tc_tasks
+            .into_iter()
+            .filter(|t| t.get_status() == taskchampion::Status::Pending)
+            .filter_map(|t| match t.get_wait() {
+                Some(wait) if wait >= from && wait < to => Some((wait, t)),
+                _ => None,
+            })
+            .collect();
+        waking.sort_by_key(|(wait, _)| *wait);
+
+        let array = RArray::with_capacity(waking.len());
+        for (_, tc_task) in waking {
+            array.push(self.wrap_task(tc_task, depmap.clone())?)?;
+        }
+
+        Ok(array)
+    }
+
+    fn query(&self, filter: String) -> Result<RArray, Error> {
+        let filter = Filter::parse(&filter)?;
+        let mut tc_replica = self.0.get_mut()?;
+
+        let matched = without_gvl(|| filtered_task_data(&mut tc_replica, &filter))?
+            .map_err(into_error)?;
+
+        let depmap = tc_replica.dependency_map(false).map_err(into_error)?;
+        let array = RArray::with_capacity(matched.len());
+        for data in matched {
+            if let Some(tc_task) = tc_replica.get_task(data.get_uuid()).map_err(into_error)? {
+                array.push(self.wrap_task(tc_task, depmap.clone())?)?;
+            }
+        }
+
+        Ok(array)
+    }
+
+    fn tasks_by_urgency(&self, args: &[Value]) -> Result<RArray, Error> {
+        let args = scan_args::<(), (Option<Option<&UrgencyConfig>>,), (), (), (), ()>(args)?;
+        let (config,) = args.optional;
+        let coefficients = match config.flatten() {
+            Some(config) => config.coefficients()?,
+            None => Coefficients::default(),
+        };
+        let mut tc_replica = self.0.get_mut()?;
+
+        let ranked = without_gvl(|| -> Result<Vec<Uuid>, taskchampion::Error> {
+            let depmap = tc_replica.dependency_map(false)?;
+            let ctx = FilterContext { depmap: Some(&depmap), now: Utc::now() };
+            let mut scored: Vec<(f64, TCTaskData)> = tc_replica
+                .pending_task_data()?
+                .into_iter()
+                // The working set can still hold tasks completed since it was built
+                .filter(|data| data.get("status") == Some("pending"))
+                .map(|data| (urgency::urgency(&data, &coefficients, &ctx), data))
+                .collect();
+            // Most urgent first; ties go to the older task, as in `task next`
+            let entry = |data: &TCTaskData| data.get("entry").and_then(|e| e.parse::<i64>().ok());
+            scored.sort_by(|(a, a_data), (b, b_data)| {
+                b.total_cmp(a)
+                    .then_with(|| entry(a_data).cmp(&entry(b_data)))
+                    .then_with(|| a_data.get_uuid().cmp(&b_data.get_uuid()))
+            });
+            Ok(scored.into_iter().map(|(_, data)| data.get_uuid()).collect())
+        })?
+        .map_err(into_error)?;
+
+        let depmap = tc_replica.dependency_map(false).map_err(into_error)?;
+        let array = RArray::with_capacity(ranked.len());
+        for uuid in ranked {
+            if let Some(tc_task) = tc_replica.get_task(uuid).map_err(into_error)? {
+                array.push(self.wrap_task(tc_task, depmap.clone())?)?;
+            }
+        }
+
+        Ok(array)
+    }
+
+    fn generate_recurring_instances(&self, kwargs: RHash) -> Result<RArray, Error> {
+        let horizon: Value = kwargs.fetch(Symbol::new("horizon")).map_err(|_| Error::new(
+            magnus::exception::arg_error(),
+            "Missing required parameter: horizon"
+        ))?;
+        let horizon = ruby_to_datetime(horizon)?;
+        let mut tc_replica = self.0.get_mut()?;
+
+        let (mut tc_operations, created) = without_gvl(|| recurrence::generate_instances(&mut tc_replica, horizon))?
+            .map_err(into_error)?;
+        if !created.is_empty() {
+            self.run_taskwarrior_hooks(&mut tc_replica, &mut tc_operations)?;
+            self.commit_and_notify(tc_replica, tc_operations, "generate")?;
+            tc_replica = self.0.get_mut()?;
+        }
+
+        let depmap = tc_replica.dependency_map(false).map_err(into_error)?;
+        let array = RArray::with_capacity(created.len());
+        for uuid in created {
+            if let Some(tc_task) = tc_replica.get_task(uuid).map_err(into_error)? {
+                array.push(self.wrap_task(tc_task, depmap.clone())?)?;
+            }
+        }
+
+        Ok(array)
+    }
+
+    fn export_json(&self, args: &[Value]) -> Result<String, Error> {
+        let args = scan_args::<(), (Option<Option<String>>,), (), (), (), ()>(args)?;
+        let (filter,) = args.optional;
+        let filter = Filter::parse(filter.flatten().as_deref().unwrap_or(""))?;
+        let mut tc_replica = self.0.get_mut()?;
+
+        without_gvl(|| -> Result<String, taskchampion::Error> {
+            let matched = filtered_task_data(&mut tc_replica, &filter)?;
+            let working_set = tc_replica.working_set()?;
+            let tasks: Vec<_> = matched
+                .iter()
+                .map(|data| taskwarrior::task_to_json(data, working_set.by_uuid(data.get_uuid()).unwrap_or(0)))
+                .collect();
+            Ok(serde_json::Value::Array(tasks).to_string())
+        })?
+        .map_err(into_error)
+    }
+
+    fn import_json(&self, input: Value) -> Result<RHash, Error> {
+        let text = read_input(input)?;
+        let imported = taskwarrior::parse_tasks(&text)?
+            .iter()
+            .map(taskwarrior::json_to_task)
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let mut tc_replica = self.0.get_mut()?;
+
+        let (mut ops, created, updated, skipped) = without_gvl(|| -> Result<_, taskchampion::Error> {
+            let (mut created, mut updated, mut skipped) = (0usize, 0usize, 0usize);
+            let mut ops = TCOperations::new();
+            ops.push(TCOperation::UndoPoint);
+            // Tasks touched earlier in this import, in case a UUID appears twice
+            let mut touched: HashMap<Uuid, TCTaskData> = HashMap::new();
+
+            for (uuid, properties) in imported {
+                let existing = match touched.remove(&uuid) {
+                    Some(data) => Some(data),
+                    None => tc_replica.get_task_data(uuid)?,
+                };
+                let mut keys: Vec<&String> = properties.keys().collect();
+                keys.sort();
+
+                let data = match existing {
+                    None => {
+                        let mut data = TCTaskData::create(uuid, &mut ops);
+                        for key in keys {
+                            data.update(key, Some(properties[key].clone()), &mut ops);
+                        }
+                        created += 1;
+                        data
+                    }
+                    Some(mut data) => {
+                        // The imported task replaces the stored one, so drop properties it lacks
+                        let stale: Vec<String> = data
+                            .properties()
+                            .filter(|key| !properties.contains_key(*key))
+                            .cloned()
+                            .collect();
+                        let changed: Vec<&String> = keys
+                            .into_iter()
+                            .filter(|key| data.get(key) != Some(properties[*key].as_str()))
+                            .collect();
+                        if stale.is_empty() && changed.is_empty() {
+                            skipped += 1;
+                        } else {
+                            for key in stale {
+                                data.update(key, None, &mut ops);
+                            }
+                            for key in changed {
+                                data.update(key, Some(properties[key].clone()), &mut ops);
+                            }
+                            updated += 1;
+                        }
+                        data
+                    }
+                };
+                touched.insert(uuid, data);
+            }
+            Ok((ops, created, updated, skipped))
+        })?
+        .map_err(into_error)?;
+        // Only the undo point means nothing changed
+        if ops.len() > 1 {
+            reject_new_cycle(&mut tc_replica, &ops)?;
+            self.run_taskwarrior_hooks(&mut tc_replica, &mut ops)?;
+            self.commit_and_notify(tc_replica, ops, "import")?;
+        }
+
+        let result = RHash::new();
+        result.aset(Symbol::new("created"), created)?;
+        result.aset(Symbol::new("updated"), updated)?;
+        result.aset(Symbol::new("skipped"), skipped)?;
+        Ok(result)
+    }
+
+    fn export_ical(&self, args: &[Value]) -> Result<String, Error> {
+        let args = scan_args::<(), (Option<Option<String>>,), (), (), (), ()>(args)?;
+        let (filter,) = args.optional;
+        let filter = Filter::parse(filter.flatten().as_deref().unwrap_or(""))?;
+        let mut tc_replica = self.0.get_mut()?;
+
+        without_gvl(|| -> Result<String, taskchampion::Error> {
+            let matched = filtered_task_data(&mut tc_replica, &filter)?;
+            Ok(ical::tasks_to_ical(&matched, Utc::now()))
+        })?
+        .map_err(into_error)
+    }
+
+    fn import_ical(&self, input: Value) -> Result<RHash, Error> {
+        let text = read_input(input)?;
+        let todos = ical::parse_todos(&text)?;
+        let mut tc_replica = self.0.get_mut()?;
+
+        let (mut ops, created, updated, skipped) =
+            without_gvl(|| ical::import_todos(&mut tc_replica, &todos, Utc::now()))?.map_err(into_error)?;
+        // Only the undo point means nothing changed
+        if ops.len() > 1 {
+            reject_new_cycle(&mut tc_replica, &ops)?;
+            self.run_taskwarrior_hooks(&mut tc_replica, &mut ops)?;
+            self.commit_and_notify(tc_replica, ops, "import")?;
+        }
+
+        let result = RHash::new();
+        result.aset(Symbol::new("created"), created)?;
+        result.aset(Symbol::new("updated"), updated)?;
+        result.aset(Symbol::new("skipped"), skipped)?;
+        Ok(result)
+    }
+
+    fn search(&self, args: &[Value]) -> Result<RArray, Error> {
+        let args = scan_args::<(String,), (), (), (), RHash, ()>(args)?;
+        let (text,) = args.required;
+        let kwargs = get_kwargs::<_, (), (Option<Option<RArray>>, Option<Option<usize>>), ()>(
+            args.keywords,
+            &[],
+            &["fields", "limit"],
+        )?;
+        let (fields, limit) = kwargs.optional;
+        let fields = match fields.flatten() {
+            Some(names) => names
+                .into_iter()
+                .map(|name| {
+                    let name = match Symbol::from_value(name) {
+                        Some(symbol) => symbol.name()?.into_owned(),
+                        None => String::try_convert(name)?,
+                    };
+                    search::Field::from_name(&name).ok_or_else(|| Error::new(
+                        validation_error(),
+                        format!("Unknown search field '{}'; expected description, annotations, project or tags", name)
+                    ))
+                })
+                .collect::<Result<Vec<_>, Error>>()?,
+            None => search::DEFAULT_FIELDS.to_vec(),
+        };
+        let query = search::Query::new(&text, fields);
+        let limit = limit.flatten();
+
+        let mut tc_replica = self.0.get_mut()?;
+        let mut index = self.2.get_mut()?;
+        let taskdb_dir = self.1.as_deref();
+
+        let found = without_gvl(|| -> Result<Vec<Uuid>, taskchampion::Error> {
+            match index.as_mut() {
+                Some(index) => {
+                    // Another replica object or process may have changed the database
+                    let version = taskdb_dir.map(search::storage_version).transpose()?;
+                    if index.is_stale(version.as_ref()) {
+                        *index = SearchIndex::build(&mut tc_replica, version)?;
+                    }
+                    Ok(index.search(&query, limit))
+                }
+                None => Ok(search::search_tasks(&tc_replica.all_task_data()?, &query, limit)),
+            }
+        })?
+        .map_err(into_error)?;
+
+        let depmap = tc_replica.dependency_map(false).map_err(into_error)?;
+        let array = RArray::with_capacity(found.len());
+        for uuid in found {
+            if let Some(tc_task) = tc_replica.get_task(uuid).map_err(into_error)? {
+                array.push(self.wrap_task(tc_task, depmap.clone())?)?;
+            }
+        }
+
+        Ok(array)
+    }
+
+    fn enable_search_index(&self) -> Result<(), Error> {
+        let mut tc_replica = self.0.get_mut()?;
+        let taskdb_dir = self.1.as_deref();
+
+        let index = without_gvl(|| {
+            let version = taskdb_dir.map(search::storage_version).transpose()?;
+            SearchIndex::build(&mut tc_replica, version)
+        })?
+        .map_err(into_error)?;
+        *self.2.get_mut()? = Some(index);
+        Ok(())
+    }
+
+    fn disable_search_index(&self) -> Result<(), Error> {
+        *self.2.get_mut()? = None;
+        Ok(())
+    }
+
+    fn search_index_enabled(&self) -> Result<bool, Error> {
+        Ok(self.2.get()?.is_some())
+    }
+
+    /// The storage version an on-disk replica's search index is compared
+    /// with, read before a commit and passed to `update_search_index`
+    fn search_index_version(&self) -> Result<Option<StorageVersion>, Error> {
+        match (self.2.get()?.as_ref(), self.1.as_deref()) {
+            (Some(_), Some(taskdb_dir)) => search::storage_version(taskdb_dir).map(Some).map_err(into_error),
+            _ => Ok(None),
+        }
+    }
+
+    /// Refresh the search index entries for `uuids` after a commit through
+    /// this replica. If the storage had already moved past the index
+    /// before the commit (`before`), it is left stale to be rebuilt instead.
+    fn update_search_index(
+        &self,
+        tc_replica: &mut TCReplica,
+        uuids: &[Uuid],
+        before: Option<StorageVersion>,
+    ) -> Result<(), Error> {
+        if let Some(index) = self.2.get_mut()?.as_mut() {
+            if index.is_stale(before.as_ref()) {
+                index.invalidate();
+                return Ok(());
+            }
+            for uuid in uuids {
+                let data = tc_replica.get_task_data(*uuid).map_err(into_error)?;
+                index.update(*uuid, data.as_ref());
+            }
+            let version = self.1.as_deref().map(search::storage_version).transpose().map_err(into_error)?;
+            index.set_version(version);
+        }
+        Ok(())
+    }
+
+    /// Mark the search index for a rebuild after changes it can't follow
+    /// task by task
+    fn invalidate_search_index(&self) -> Result<(), Error> {
+        if let Some(index) = self.2.get_mut()?.as_mut() {
+            index.invalidate();
+        }
+        Ok(())
+    }
+
     fn task_data(&self, uuid: String) -> Result<Value, Error> {
         let mut tc_replica = self.0.get_mut()?;
 
@@ -120,19 +758,71 @@ impl Replica {
         })
     }
 
-    fn task(&self, uuid: String) -> Result<Value, Error> {
+    pub(crate) fn task(&self, uuid: String) -> Result<Value, Error> {
         let mut tc_replica = self.0.get_mut()?;
 
         let task = tc_replica
             .get_task(uuid2tc(&uuid)?)
             .map_err(into_error)?;
+        let depmap = tc_replica.dependency_map(false).map_err(into_error)?;
 
         option_to_ruby(task, |task| {
-            let ruby_task = Task::from_tc_task(task);
+            let ruby_task = self.wrap_task(task, depmap)?;
             Ok(ruby_task.into_value()) // Convert to Value
         })
     }
 
+    /// Yield every task, or those with `status`, reading them from storage
+    /// `TASK_BATCH_SIZE` at a time rather than all at once
+    fn each_task(ruby: &Ruby, rb_self: Obj<Self>, args: &[Value]) -> Result<Value, Error> {
+        let args = scan_args::<(), (), (), (), RHash, ()>(args)?;
+        let keywords = args.keywords;
+        let status = status_kwarg(keywords)?;
+        if !ruby.block_given() {
+            return Ok(rb_self.enumeratorize("each_task", (KwArgs(keywords),)).into_value());
+        }
+        let block = ruby.block_proc()?;
+
+        let uuids = {
+            let mut tc_replica = rb_self.0.get_mut()?;
+            without_gvl(|| candidate_uuids(&mut tc_replica, status))?.map_err(into_error)?
+        };
+        for batch in uuids.chunks(TASK_BATCH_SIZE) {
+            // The replica is released before yielding, so the block can use it
+            let (tasks, depmap) = {
+                let mut tc_replica = rb_self.0.get_mut()?;
+                let tasks = without_gvl(|| load_tasks(&mut tc_replica, batch, status))?.map_err(into_error)?;
+                (tasks, tc_replica.dependency_map(false).map_err(into_error)?)
+            };
+            for tc_task in tasks {
+                block.call::<_, Value>((rb_self.wrap_task(tc_task, depmap.clone())?,))?;
+            }
+        }
+
+        Ok(rb_self.into_value())
+    }
+
+    /// The number of tasks, or of those with `status`, without building
+    /// Ruby objects for them
+    fn count_tasks(&self, args: &[Value]) -> Result<usize, Error> {
+        let args = scan_args::<(), (), (), (), RHash, ()>(args)?;
+        let status = status_kwarg(args.keywords)?;
+        let mut tc_replica = self.0.get_mut()?;
+
+        without_gvl(|| -> Result<usize, taskchampion::Error> {
+            let uuids = candidate_uuids(&mut tc_replica, status)?;
+            if status.is_none() {
+                return Ok(uuids.len());
+            }
+            let mut count = 0;
+            for batch in uuids.chunks(TASK_BATCH_SIZE) {
+                count += load_tasks(&mut tc_replica, batch, status)?.len();
+            }
+            Ok(count)
+        })?
+        .map_err(into_error)
+    }
+
     fn task_uuids(&self) -> Result<RArray, Error> {
         let mut tc_replica = self.0.get_mut()?;
 
@@ -140,77 +830,98 @@ impl Replica {
         vec_to_ruby(uuids, |uuid| Ok(uuid.to_string().into_value()))
     }
 
-    fn working_set(&self) -> Result<Value, Error> {
+    fn working_set(rb_self: Obj<Self>) -> Result<WorkingSet, Error> {
+        let tc_working_set = rb_self.load_working_set(false)?;
+        Ok(WorkingSet::new(tc_working_set, rb_self))
+    }
+
+    /// Read the working set, renumbering it first if `renumber` is set
+    pub(crate) fn load_working_set(&self, renumber: bool) -> Result<TCWorkingSet, Error> {
         let mut tc_replica = self.0.get_mut()?;
 
-        let tc_working_set = tc_replica.working_set().map_err(into_error)?;
-        let working_set = WorkingSet::from_tc_working_set(tc_working_set.into());
+        if renumber {
+            tc_replica.rebuild_working_set(true).map_err(into_error)?;
+        }
+        tc_replica.working_set().map_err(into_error)
+    }
+
+    /// Look up each of `uuids`, sharing one dependency map between the tasks
+    pub(crate) fn tasks_by_uuid(&self, uuids: &[Uuid]) -> Result<Vec<Option<Task>>, Error> {
+        let mut tc_replica = self.0.get_mut()?;
 
-        Ok(working_set.into_value())
+        let depmap = tc_replica.dependency_map(false).map_err(into_error)?;
+        uuids
+            .iter()
+            .map(|uuid| {
+                let task = tc_replica.get_task(*uuid).map_err(into_error)?;
+                task.map(|task| self.wrap_task(task, depmap.clone())).transpose()
+            })
+            .collect()
     }
 
-    fn dependency_map(&self, force: Option<bool>) -> Result<Value, Error> {
+    /// The value of `property` for each of `uuids` that has one
+    pub(crate) fn task_values(&self, uuids: &[Uuid], property: &str) -> Result<HashMap<Uuid, String>, Error> {
         let mut tc_replica = self.0.get_mut()?;
+
+        let mut values = HashMap::new();
+        for uuid in uuids {
+            if let Some(data) = tc_replica.get_task_data(*uuid).map_err(into_error)? {
+                if let Some(value) = data.get(property) {
+                    values.insert(*uuid, value.to_string());
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    fn dependency_map(rb_self: Obj<Self>, force: Option<bool>) -> Result<DependencyMap, Error> {
+        let mut tc_replica = rb_self.0.get_mut()?;
         let force = force.unwrap_or(false);
 
         let tc_dm = tc_replica.dependency_map(force).map_err(into_error)?;
-        let dependency_map = DependencyMap::from_tc_dependency_map(tc_dm);
-
-        Ok(dependency_map.into_value())
+        // The map's edges all start in the working set
+        let tasks: Vec<Uuid> = tc_replica
+            .working_set()
+            .map_err(into_error)?
+            .iter()
+            .map(|(_, uuid)| uuid)
+            .collect();
+        drop(tc_replica);
+
+        Ok(DependencyMap::new(tc_dm, tasks, rb_self))
     }
 
-    fn sync_to_local(&self, server_dir: String, avoid_snapshots: Option<bool>) -> Result<(), Error> {
+    fn sync_to_local(&self, server_dir: String, avoid_snapshots: Option<bool>) -> Result<SyncResult, Error> {
         let mut tc_replica = self.0.get_mut()?;
         let avoid_snapshots = avoid_snapshots.unwrap_or(false);
 
-        let mut server = ServerConfig::Local {
+        let config = ServerConfig::Local {
             server_dir: server_dir.into(),
-        }
-        .into_server()
-        .map_err(into_error)?;
+        };
 
-        tc_replica
-            .sync(&mut server, avoid_snapshots)
-            .map_err(into_error)
+        let (result, changes) =
+            without_gvl(|| sync_replica(&mut tc_replica, || config.into_server(), avoid_snapshots))?
+                .map_err(into_error)?;
+        self.invalidate_search_index()?;
+        drop(tc_replica);
+        self.notify_commit(changes, "sync")?;
+        Ok(result)
     }
 
     fn sync_to_remote(
         &self,
         kwargs: RHash,
-    ) -> Result<(), Error> {
-
-        // Extract required keyword arguments with proper exception type
-        let url: String = kwargs.fetch(Symbol::new("url")).map_err(|_| Error::new(
-            magnus::exception::arg_error(),
-            "Missing required parameter: url"
-        ))?;
-        let client_id: String = kwargs.fetch(Symbol::new("client_id")).map_err(|_| Error::new(
-            magnus::exception::arg_error(),
-            "Missing required parameter: client_id"
-        ))?;
-        let encryption_secret: String = kwargs.fetch(Symbol::new("encryption_secret")).map_err(|_| Error::new(
-            magnus::exception::arg_error(),
-            "Missing required parameter: encryption_secret"
-        ))?;
-        let avoid_snapshots: bool = kwargs
-            .fetch::<_, Value>(Symbol::new("avoid_snapshots"))
-            .ok()
-            .and_then(|v| bool::try_convert(v).ok())
-            .unwrap_or(false);
-
+    ) -> Result<SyncResult, Error> {
+        let (config, avoid_snapshots) = remote_config(kwargs)?;
         let mut tc_replica = self.0.get_mut()?;
 
-        let mut server = ServerConfig::Remote {
-            url,
-            client_id: uuid2tc(&client_id)?,
-            encryption_secret: encryption_secret.into(),
-        }
-        .into_server()
-        .map_err(into_error)?;
-
-        tc_replica
-            .sync(&mut server, avoid_snapshots)
-            .map_err(into_error)
+        let (result, changes) =
+            without_gvl(|| sync_replica(&mut tc_replica, || config.into_server(), avoid_snapshots))?
+                .map_err(into_error)?;
+        self.invalidate_search_index()?;
+        drop(tc_replica);
+        self.notify_commit(changes, "sync")?;
+        Ok(result)
     }
 
     fn rebuild_working_set(&self, renumber: Option<bool>) -> Result<(), Error> {
@@ -222,13 +933,33 @@ impl Replica {
             .map_err(into_error)
     }
 
+    /// Delete tasks that were marked deleted more than six months ago, as
+    /// TaskChampion's `expire_tasks` does, but through `commit_and_notify`
     fn expire_tasks(&self) -> Result<(), Error> {
         let mut tc_replica = self.0.get_mut()?;
 
-        tc_replica.expire_tasks().map_err(into_error)
+        let ops = without_gvl(|| -> Result<TCOperations, taskchampion::Error> {
+            let six_months_ago = Utc::now() - TimeDelta::days(180);
+            let mut ops = TCOperations::new();
+            for (_, mut data) in tc_replica.all_task_data()? {
+                let modified = data
+                    .get("modified")
+                    .and_then(|m| m.parse::<i64>().ok())
+                    .and_then(|secs| DateTime::from_timestamp(secs, 0));
+                if data.get("status") == Some("deleted") && modified.is_some_and(|m| m < six_months_ago) {
+                    data.delete(&mut ops);
+                }
+            }
+            Ok(ops)
+        })?
+        .map_err(into_error)?;
+        if ops.is_empty() {
+            return Ok(());
+        }
+        self.commit_and_notify(tc_replica, ops, "expire")
     }
 
-    fn sync_to_gcp(&self, kwargs: RHash) -> Result<(), Error> {
+    fn sync_to_gcp(&self, kwargs: RHash) -> Result<SyncResult, Error> {
         // Extract required keyword arguments with proper exception type
         let bucket: String = kwargs.fetch(Symbol::new("bucket")).map_err(|_| Error::new(
             magnus::exception::arg_error(),
@@ -250,17 +981,81 @@ impl Replica {
 
         let mut tc_replica = self.0.get_mut()?;
 
-        let mut server = ServerConfig::Gcp {
+        let config = ServerConfig::Gcp {
             bucket,
             credential_path: credential_path.into(),
             encryption_secret: encryption_secret.into(),
+        };
+
+        let (result, changes) =
+            without_gvl(|| sync_replica(&mut tc_replica, || config.into_server(), avoid_snapshots))?
+                .map_err(into_error)?;
+        self.invalidate_search_index()?;
+        drop(tc_replica);
+        self.notify_commit(changes, "sync")?;
+        Ok(result)
+    }
+
+    fn sync_to_aws(&self, kwargs: RHash) -> Result<SyncResult, Error> {
+        // Extract required keyword arguments with proper exception type
+        let region: String = kwargs.fetch(Symbol::new("region")).map_err(|_| Error::new(
+            magnus::exception::arg_error(),
+            "Missing required parameter: region"
+        ))?;
+        let bucket: String = kwargs.fetch(Symbol::new("bucket")).map_err(|_| Error::new(
+            magnus::exception::arg_error(),
+            "Missing required parameter: bucket"
+        ))?;
+        let encryption_secret: String = kwargs.fetch(Symbol::new("encryption_secret")).map_err(|_| Error::new(
+            magnus::exception::arg_error(),
+            "Missing required parameter: encryption_secret"
+        ))?;
+        let access_key_id: Option<String> = kwargs.lookup(Symbol::new("access_key_id"))?;
+        let secret_access_key: Option<String> = kwargs.lookup(Symbol::new("secret_access_key"))?;
+        // The SDK can only be pointed elsewhere through its environment
+        if kwargs.get(Symbol::new("endpoint")).is_some() {
+            return Err(Error::new(
+                magnus::exception::arg_error(),
+                "sync_to_aws does not take endpoint:; set AWS_ENDPOINT_URL before starting the process instead",
+            ));
         }
-        .into_server()
-        .map_err(into_error)?;
+        let avoid_snapshots: bool = kwargs
+            .fetch::<_, Value>(Symbol::new("avoid_snapshots"))
+            .ok()
+            .and_then(|v| bool::try_convert(v).ok())
+            .unwrap_or(false);
 
-        tc_replica
-            .sync(&mut server, avoid_snapshots)
-            .map_err(into_error)
+        // Without explicit keys, fall back to the SDK's default credential chain
+        let credentials = match (access_key_id, secret_access_key) {
+            (Some(access_key_id), Some(secret_access_key)) => AwsCredentials::AccessKey {
+                access_key_id,
+                secret_access_key,
+            },
+            (None, None) => AwsCredentials::Default,
+            _ => {
+                return Err(Error::new(
+                    magnus::exception::arg_error(),
+                    "access_key_id and secret_access_key must be given together",
+                ))
+            }
+        };
+
+        let mut tc_replica = self.0.get_mut()?;
+
+        let config = ServerConfig::Aws {
+            region,
+            bucket,
+            credentials,
+            encryption_secret: encryption_secret.into(),
+        };
+
+        let (result, changes) =
+            without_gvl(|| sync_replica(&mut tc_replica, || config.into_server(), avoid_snapshots))?
+                .map_err(into_error)?;
+        self.invalidate_search_index()?;
+        drop(tc_replica);
+        self.notify_commit(changes, "sync")?;
+        Ok(result)
     }
 
     fn num_local_operations(&self) -> Result<usize, Error> {
@@ -285,6 +1080,53 @@ impl Replica {
         Ok(operations.into_value())
     }
 
+    fn task_history(&self, uuid: String) -> Result<RArray, Error> {
+        let mut tc_replica = self.0.get_mut()?;
+        let tc_uuid = uuid2tc(&uuid)?;
+
+        let tc_operations = tc_replica.get_task_operations(tc_uuid).map_err(into_error)?;
+        let array = RArray::new();
+        for entry in history::task_history(&tc_operations) {
+            let changes = RHash::new();
+            for (property, old_value, value) in entry.changes {
+                changes.aset(property, RArray::from_vec(vec![old_value, value]))?;
+            }
+
+            let properties = RHash::new();
+            let mut sorted: Vec<(String, String)> = entry.properties.into_iter().collect();
+            sorted.sort();
+            for (key, value) in sorted {
+                properties.aset(key, value)?;
+            }
+
+            let hash = RHash::new();
+            hash.aset(Symbol::new("timestamp"), datetime_to_ruby(entry.timestamp)?)?;
+            hash.aset(Symbol::new("kind"), Symbol::new(entry.kind.name()))?;
+            hash.aset(Symbol::new("changes"), changes)?;
+            hash.aset(Symbol::new("properties"), properties)?;
+            array.push(hash)?;
+        }
+
+        Ok(array)
+    }
+
+    fn task_at(&self, uuid: String, time: Value) -> Result<Value, Error> {
+        let mut tc_replica = self.0.get_mut()?;
+        let tc_uuid = uuid2tc(&uuid)?;
+        let time = ruby_to_datetime(time)?;
+
+        let tc_operations = tc_replica.get_task_operations(tc_uuid).map_err(into_error)?;
+        option_to_ruby(history::task_at(&tc_operations, time), |properties| {
+            // Rebuild the task outside the replica; the operations are discarded
+            let mut ops = TCOperations::new();
+            let mut tc_task_data = TCTaskData::create(tc_uuid, &mut ops);
+            for (property, value) in properties {
+                tc_task_data.update(property, Some(value), &mut ops);
+            }
+            Ok(TaskData::from_tc_task_data(tc_task_data).into_value())
+        })
+    }
+
     fn get_undo_operations(&self) -> Result<Value, Error> {
         let mut tc_replica = self.0.get_mut()?;
 
@@ -300,41 +1142,243 @@ impl Replica {
         // Convert Operations to TaskChampion Operations
         let tc_operations = operations.clone_inner()?;
 
+        let touched = search::touched_uuids(&tc_operations);
+        // Reversed operations are checked against the replica and may not
+        // apply, so the summary comes from the tasks' data either side
+        let before = if self.has_commit_hooks()? {
+            Some(task_states(&mut tc_replica, &touched).map_err(into_error)?)
+        } else {
+            None
+        };
+
         // Commit the reversed operations
-        let success = tc_replica.commit_reversed_operations(tc_operations).map_err(into_error)?;
+        let index_version = self.search_index_version()?;
+        let success = without_gvl(|| tc_replica.commit_reversed_operations(tc_operations))?
+            .map_err(into_error)?;
+        if !success {
+            return Ok(false);
+        }
+        self.update_search_index(&mut tc_replica, &touched, index_version)?;
+
+        let changes = match before {
+            Some(before) => {
+                let after = task_states(&mut tc_replica, &touched).map_err(into_error)?;
+                history::summarize_states(&before, &after)
+            }
+            None => Vec::new(),
+        };
+        drop(tc_replica);
+        self.notify_commit(changes, "undo")?;
 
-        Ok(success)
+        Ok(true)
     }
 
 }
 
+pub(crate) fn on_disk_replica(
+    path: String,
+    create_if_missing: bool,
+    access_mode: Option<Symbol>,
+) -> Result<TCReplica, Error> {
+    let access_mode = match access_mode {
+        Some(sym) => AccessMode::from_symbol(sym)?,
+        None => AccessMode::from_symbol(Symbol::new("read_write"))?,
+    };
+
+    let storage = StorageConfig::OnDisk {
+        taskdb_dir: path.into(),
+        create_if_missing,
+        access_mode: access_mode.into(),
+    }
+    .into_storage()
+    .map_err(into_error)?;
+    Ok(TCReplica::new(storage))
+}
+
+pub(crate) fn in_memory_replica() -> Result<TCReplica, Error> {
+    let storage = StorageConfig::InMemory.into_storage().map_err(into_error)?;
+    Ok(TCReplica::new(storage))
+}
+
+/// Read the `sync_to_remote` keyword arguments, returning the server
+/// configuration and the `avoid_snapshots` flag
+pub(crate) fn remote_config(kwargs: RHash) -> Result<(ServerConfig, bool), Error> {
+    // Extract required keyword arguments with proper exception type
+    let url: String = kwargs.fetch(Symbol::new("url")).map_err(|_| Error::new(
+        magnus::exception::arg_error(),
+        "Missing required parameter: url"
+    ))?;
+    let client_id: String = kwargs.fetch(Symbol::new("client_id")).map_err(|_| Error::new(
+        magnus::exception::arg_error(),
+        "Missing required parameter: client_id"
+    ))?;
+    let encryption_secret: String = kwargs.fetch(Symbol::new("encryption_secret")).map_err(|_| Error::new(
+        magnus::exception::arg_error(),
+        "Missing required parameter: encryption_secret"
+    ))?;
+    let avoid_snapshots: bool = kwargs
+        .fetch::<_, Value>(Symbol::new("avoid_snapshots"))
+        .ok()
+        .and_then(|v| bool::try_convert(v).ok())
+        .unwrap_or(false);
+
+    let config = ServerConfig::Remote {
+        url,
+        client_id: uuid2tc(&client_id)?,
+        encryption_secret: encryption_secret.into(),
+    };
+    Ok((config, avoid_snapshots))
+}
+
+/// The current data of those of `uuids` that exist
+fn task_states(tc_replica: &mut TCReplica, uuids: &[Uuid]) -> Result<HashMap<Uuid, TCTaskData>, taskchampion::Error> {
+    let mut states = HashMap::new();
+    for uuid in uuids {
+        if let Some(data) = tc_replica.get_task_data(*uuid)? {
+            states.insert(*uuid, data);
+        }
+    }
+    Ok(states)
+}
+
+/// `each_task` and `count_tasks` read tasks from storage this many at a
+/// time, so only one batch is held in memory
+const TASK_BATCH_SIZE: usize = 500;
+
+/// The optional `status:` keyword of `each_task` and `count_tasks`
+fn status_kwarg(keywords: RHash) -> Result<Option<Status>, Error> {
+    let kwargs = get_kwargs::<_, (), (Option<Option<Value>>,), ()>(keywords, &[], &["status"])?;
+    let (status,) = kwargs.optional;
+    status.flatten().map(Status::from_value).transpose()
+}
+
+/// UUIDs of the tasks that may have `status`: the working set for pending
+/// tasks, since every pending task is in it, otherwise all tasks
+fn candidate_uuids(tc_replica: &mut TCReplica, status: Option<Status>) -> Result<Vec<Uuid>, taskchampion::Error> {
+    if status == Some(Status::from(taskchampion::Status::Pending)) {
+        Ok(tc_replica.working_set()?.iter().map(|(_, uuid)| uuid).collect())
+    } else {
+        tc_replica.all_task_uuids()
+    }
+}
+
+/// Load the tasks in `uuids` that still exist and have `status`, if given
+fn load_tasks(
+    tc_replica: &mut TCReplica,
+    uuids: &[Uuid],
+    status: Option<Status>,
+) -> Result<Vec<TCTask>, taskchampion::Error> {
+    let mut tasks = Vec::with_capacity(uuids.len());
+    for uuid in uuids {
+        if let Some(task) = tc_replica.get_task(*uuid)? {
+            if status.is_none_or(|status| Status::from(task.get_status()) == status) {
+                tasks.push(task);
+            }
+        }
+    }
+    Ok(tasks)
+}
+
+/// Fail with the cycle path if committing `operations` would create a
+/// dependency cycle
+fn reject_new_cycle(tc_replica: &mut TCReplica, operations: &TCOperations) -> Result<(), Error> {
+    let cycle = without_gvl(|| dependency_graph::new_cycle(tc_replica, operations))?.map_err(into_error)?;
+    match cycle {
+        Some(cycle) => Err(cycle_error(&cycle)),
+        None => Ok(()),
+    }
+}
+
+/// The text of a String, or of an IO or anything else that responds to `read`
+fn read_input(input: Value) -> Result<String, Error> {
+    if input.respond_to("read", false)? {
+        input.funcall("read", ())
+    } else {
+        String::try_convert(input)
+    }
+}
+
+/// Load the task data matching `filter`, oldest first like Taskwarrior's
+/// default report order.
+fn filtered_task_data(tc_replica: &mut TCReplica, filter: &Filter) -> Result<Vec<TCTaskData>, taskchampion::Error> {
+    let depmap = if filter.needs_dependency_map() {
+        Some(tc_replica.dependency_map(false)?)
+    } else {
+        None
+    };
+    let ctx = FilterContext { depmap: depmap.as_deref(), now: Utc::now() };
+
+    // A filter that requires status:pending only needs the working set
+    let candidates = if filter.pending_only() {
+        tc_replica.pending_task_data()?
+    } else {
+        tc_replica.all_task_data()?.into_values().collect()
+    };
+
+    let mut matched: Vec<_> = candidates
+        .into_iter()
+        .filter(|data| filter.matches(data, &ctx))
+        .map(|data| (data.get("entry").and_then(|e| e.parse::<i64>().ok()), data.get_uuid(), data))
+        .collect();
+    matched.sort_by_key(|(entry, uuid, _)| (*entry, *uuid));
+    Ok(matched.into_iter().map(|(_, _, data)| data).collect())
+}
+
 pub fn init(module: &RModule) -> Result<(), Error> {
     let class = module.define_class("Replica", class::object())?;
 
     // Class methods
     class.define_singleton_method("new_on_disk", function!(Replica::new_on_disk, 3))?;
     class.define_singleton_method("new_in_memory", function!(Replica::new_in_memory, 0))?;
+    class.define_singleton_method("restore_from", function!(Replica::restore_from, 2))?;
 
     // Instance methods
     class.define_method("create_task", method!(Replica::create_task, 2))?;
     class.define_method("commit_operations", method!(Replica::commit_operations, 1))?;
+    class.define_method("transaction", method!(Replica::transaction, 0))?;
+    class.define_method("on_commit", method!(Replica::on_commit, 0))?;
+    class.define_method("remove_on_commit", method!(Replica::remove_on_commit, 1))?;
+    class.define_method("enable_taskwarrior_hooks", method!(Replica::enable_taskwarrior_hooks, -1))?;
+    class.define_method("disable_taskwarrior_hooks", method!(Replica::disable_taskwarrior_hooks, 0))?;
+    class.define_method("taskwarrior_hooks_dir", method!(Replica::taskwarrior_hooks_dir, 0))?;
+    class.define_method("uda_schema", method!(Replica::uda_schema, 0))?;
+    class.define_method("uda_schema=", method!(Replica::set_uda_schema, 1))?;
     class.define_method("all_tasks", method!(Replica::all_tasks, 0))?;
     class.define_method("task", method!(Replica::task, 1))?;
     class.define_method("task_data", method!(Replica::task_data, 1))?;
     class.define_method("task_uuids", method!(Replica::task_uuids, 0))?;
+    class.define_method("each_task", method!(Replica::each_task, -1))?;
+    class.define_method("count_tasks", method!(Replica::count_tasks, -1))?;
     class.define_method("working_set", method!(Replica::working_set, 0))?;
     class.define_method("dependency_map", method!(Replica::dependency_map, 1))?;
     class.define_method("sync_to_local", method!(Replica::sync_to_local, 2))?;
     class.define_method("sync_to_remote", method!(Replica::sync_to_remote, 1))?;
     class.define_method("sync_to_gcp", method!(Replica::sync_to_gcp, 1))?;
+    class.define_method("sync_to_aws", method!(Replica::sync_to_aws, 1))?;
     class.define_method("rebuild_working_set", method!(Replica::rebuild_working_set, 1))?;
     class.define_method("expire_tasks", method!(Replica::expire_tasks, 0))?;
+    class.define_method("backup_to", method!(Replica::backup_to, 1))?;
     class.define_method("num_local_operations", method!(Replica::num_local_operations, 0))?;
     class.define_method("num_undo_points", method!(Replica::num_undo_points, 0))?;
     class.define_method("get_task_operations", method!(Replica::get_task_operations, 1))?;
+    class.define_method("task_history", method!(Replica::task_history, 1))?;
+    class.define_method("task_at", method!(Replica::task_at, 2))?;
     class.define_method("get_undo_operations", method!(Replica::get_undo_operations, 0))?;
     class.define_method("commit_reversed_operations", method!(Replica::commit_reversed_operations, 1))?;
     class.define_method("pending_tasks", method!(Replica::pending_tasks, 0))?;
+    class.define_method("active_tasks", method!(Replica::active_tasks, 0))?;
+    class.define_method("tasks_waking_between", method!(Replica::tasks_waking_between, 2))?;
+    class.define_method("query", method!(Replica::query, 1))?;
+    class.define_method("search", method!(Replica::search, -1))?;
+    class.define_method("enable_search_index", method!(Replica::enable_search_index, 0))?;
+    class.define_method("disable_search_index", method!(Replica::disable_search_index, 0))?;
+    class.define_method("search_index_enabled?", method!(Replica::search_index_enabled, 0))?;
+    class.define_method("tasks_by_urgency", method!(Replica::tasks_by_urgency, -1))?;
+    class.define_method("generate_recurring_instances", method!(Replica::generate_recurring_instances, 1))?;
+    class.define_method("export_json", method!(Replica::export_json, -1))?;
+    class.define_method("import_json", method!(Replica::import_json, 1))?;
+    class.define_method("export_ical", method!(Replica::export_ical, -1))?;
+    class.define_method("import_ical", method!(Replica::import_ical, 1))?;
 
     Ok(())
 }
@@ -0,0 +1,298 @@
+use crate::backup::DB_FILE;
+use rusqlite::{Connection, OpenFlags};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use taskchampion::{Operation as TCOperation, Replica as TCReplica, TaskData as TCTaskData, Uuid};
+
+/// A part of a task that `Replica#search` can look in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Field {
+    Description,
+    Annotations,
+    Project,
+    Tags,
+}
+
+pub const DEFAULT_FIELDS: &[Field] = &[Field::Description, Field::Annotations];
+
+const ALL_FIELDS: &[Field] = &[Field::Description, Field::Annotations, Field::Project, Field::Tags];
+
+impl Field {
+    pub fn from_name(name: &str) -> Option<Field> {
+        match name {
+            "description" => Some(Field::Description),
+            "annotations" => Some(Field::Annotations),
+            "project" => Some(Field::Project),
+            "tags" => Some(Field::Tags),
+            _ => None,
+        }
+    }
+
+    /// A match in the description counts for more than one in an annotation
+    fn weight(self) -> f64 {
+        match self {
+            Field::Description => 2.0,
+            Field::Project | Field::Tags => 1.5,
+            Field::Annotations => 1.0,
+        }
+    }
+
+    fn text(self, task: &TCTaskData) -> Vec<&str> {
+        match self {
+            Field::Description => task.get("description").into_iter().collect(),
+            Field::Project => task.get("project").into_iter().collect(),
+            Field::Annotations => task
+                .properties()
+                .filter(|p| p.starts_with("annotation_"))
+                .filter_map(|p| task.get(p))
+                .collect(),
+            Field::Tags => task.properties().filter_map(|p| p.strip_prefix("tag_")).collect(),
+        }
+    }
+}
+
+/// Split text into lower-cased runs of letters and digits
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Token counts for each searchable field of one task
+#[derive(Default)]
+pub struct Document(HashMap<Field, HashMap<String, u32>>);
+
+impl Document {
+    pub fn new(task: &TCTaskData) -> Self {
+        let mut fields = HashMap::new();
+        for field in ALL_FIELDS {
+            let mut counts: HashMap<String, u32> = HashMap::new();
+            for text in field.text(task) {
+                for token in tokenize(text) {
+                    *counts.entry(token).or_default() += 1;
+                }
+            }
+            if !counts.is_empty() {
+                fields.insert(*field, counts);
+            }
+        }
+        Document(fields)
+    }
+
+    fn tokens(&self) -> impl Iterator<Item = &String> {
+        self.0.values().flat_map(|counts| counts.keys())
+    }
+
+    /// How strongly `term` appears in `fields`, with tokens starting with
+    /// `term` counting as matches so "invoice" finds "invoices". Zero if it
+    /// does not appear.
+    fn term_weight(&self, term: &str, fields: &[Field]) -> f64 {
+        fields
+            .iter()
+            .filter_map(|field| Some((field, self.0.get(field)?)))
+            .map(|(field, counts)| {
+                let frequency: u32 = counts
+                    .iter()
+                    .filter(|(token, _)| token.starts_with(term))
+                    .map(|(_, count)| count)
+                    .sum();
+                if frequency == 0 {
+                    0.0
+                } else {
+                    field.weight() * (1.0 + (frequency as f64).ln())
+                }
+            })
+            .sum()
+    }
+}
+
+pub struct Query {
+    terms: Vec<String>,
+    fields: Vec<Field>,
+}
+
+impl Query {
+    pub fn new(text: &str, fields: Vec<Field>) -> Self {
+        let mut terms: Vec<String> = tokenize(text).collect();
+        terms.sort();
+        terms.dedup();
+        Query { terms, fields }
+    }
+}
+
+/// Rank `docs` against `query`, best first. A document must contain every
+/// term in one of the query's fields. Each term is weighted by its inverse
+/// document frequency among `docs`, so `docs` must include every document
+/// containing any of the terms.
+fn rank<'a>(
+    docs: impl Iterator<Item = (Uuid, &'a Document)>,
+    total: usize,
+    query: &Query,
+    limit: Option<usize>,
+) -> Vec<Uuid> {
+    if query.terms.is_empty() {
+        return Vec::new();
+    }
+
+    let mut document_frequency = vec![0usize; query.terms.len()];
+    let mut matches = Vec::new();
+    for (uuid, doc) in docs {
+        let weights: Vec<f64> = query.terms.iter().map(|term| doc.term_weight(term, &query.fields)).collect();
+        for (df, weight) in document_frequency.iter_mut().zip(&weights) {
+            if *weight > 0.0 {
+                *df += 1;
+            }
+        }
+        if weights.iter().all(|weight| *weight > 0.0) {
+            matches.push((uuid, weights));
+        }
+    }
+
+    let idf: Vec<f64> = document_frequency
+        .iter()
+        .map(|df| (1.0 + total as f64 / (*df).max(1) as f64).ln())
+        .collect();
+    let mut scored: Vec<(f64, Uuid)> = matches
+        .into_iter()
+        .map(|(uuid, weights)| (weights.iter().zip(&idf).map(|(w, idf)| w * idf).sum(), uuid))
+        .collect();
+    scored.sort_by(|(a_score, a_uuid), (b_score, b_uuid)| b_score.total_cmp(a_score).then(a_uuid.cmp(b_uuid)));
+
+    scored
+        .into_iter()
+        .map(|(_, uuid)| uuid)
+        .take(limit.unwrap_or(usize::MAX))
+        .collect()
+}
+
+/// Search `tasks` without an index
+pub fn search_tasks(tasks: &HashMap<Uuid, TCTaskData>, query: &Query, limit: Option<usize>) -> Vec<Uuid> {
+    let docs: Vec<(Uuid, Document)> = tasks.iter().map(|(uuid, task)| (*uuid, Document::new(task))).collect();
+    rank(docs.iter().map(|(uuid, doc)| (*uuid, doc)), docs.len(), query, limit)
+}
+
+/// UUIDs of the tasks `operations` change, in order of first appearance
+pub fn touched_uuids(operations: &[TCOperation]) -> Vec<Uuid> {
+    let mut seen = HashSet::new();
+    operations
+        .iter()
+        .filter_map(|op| match op {
+            TCOperation::Create { uuid } | TCOperation::Delete { uuid, .. } | TCOperation::Update { uuid, .. } => {
+                Some(*uuid)
+            }
+            TCOperation::UndoPoint => None,
+        })
+        .filter(|uuid| seen.insert(*uuid))
+        .collect()
+}
+
+/// A marker of an on-disk replica's stored state. Every commit, undo and
+/// sync changes it, whichever process or replica object makes them: commits
+/// advance the operation sequence, undos remove operations and syncs move
+/// the base version.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StorageVersion {
+    last_operation: Option<i64>,
+    operations: i64,
+    base_version: Option<String>,
+}
+
+/// Read the `StorageVersion` of the database in `taskdb_dir`
+pub fn storage_version(taskdb_dir: &Path) -> Result<StorageVersion, taskchampion::Error> {
+    let read = || -> rusqlite::Result<StorageVersion> {
+        let con = Connection::open_with_flags(taskdb_dir.join(DB_FILE), OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        con.query_row(
+            "SELECT (SELECT seq FROM sqlite_sequence WHERE name = 'operations'),
+                    (SELECT count(*) FROM operations),
+                    (SELECT value FROM sync_meta WHERE key = 'base_version')",
+            [],
+            |row| {
+                Ok(StorageVersion {
+                    last_operation: row.get(0)?,
+                    operations: row.get(1)?,
+                    base_version: row.get(2)?,
+                })
+            },
+        )
+    };
+    read().map_err(|e| taskchampion::Error::Database(format!("Cannot read {}: {}", taskdb_dir.display(), e)))
+}
+
+/// An in-memory inverted index from tokens to the tasks containing them.
+///
+/// The replica updates it for its own commits and marks it stale after
+/// syncs. For on-disk replicas it also remembers the
+/// `StorageVersion` it reflects, so a search can tell when another replica
+/// object or process has changed the database and rebuild first.
+#[derive(Default)]
+pub struct SearchIndex {
+    docs: HashMap<Uuid, Document>,
+    postings: BTreeMap<String, HashSet<Uuid>>,
+    stale: bool,
+    version: Option<StorageVersion>,
+}
+
+impl SearchIndex {
+    /// Index every task in `replica`, whose storage is at `version`
+    pub fn build(replica: &mut TCReplica, version: Option<StorageVersion>) -> Result<Self, taskchampion::Error> {
+        let mut index = SearchIndex { version, ..SearchIndex::default() };
+        for (uuid, task) in replica.all_task_data()? {
+            index.update(uuid, Some(&task));
+        }
+        Ok(index)
+    }
+
+    /// Whether the index needs rebuilding before it can serve a search of
+    /// storage at `version`
+    pub fn is_stale(&self, version: Option<&StorageVersion>) -> bool {
+        self.stale || self.version.as_ref() != version
+    }
+
+    /// Record the storage version the index now reflects
+    pub fn set_version(&mut self, version: Option<StorageVersion>) {
+        self.version = version;
+    }
+
+    pub fn invalidate(&mut self) {
+        self.stale = true;
+    }
+
+    /// Replace the entry for `uuid` with `task`, or remove it if the task no
+    /// longer exists
+    pub fn update(&mut self, uuid: Uuid, task: Option<&TCTaskData>) {
+        if let Some(old) = self.docs.remove(&uuid) {
+            for token in old.tokens() {
+                if let Some(uuids) = self.postings.get_mut(token) {
+                    uuids.remove(&uuid);
+                    if uuids.is_empty() {
+                        self.postings.remove(token);
+                    }
+                }
+            }
+        }
+
+        if let Some(task) = task {
+            let doc = Document::new(task);
+            for token in doc.tokens() {
+                self.postings.entry(token.clone()).or_default().insert(uuid);
+            }
+            self.docs.insert(uuid, doc);
+        }
+    }
+
+    pub fn search(&self, query: &Query, limit: Option<usize>) -> Vec<Uuid> {
+        let mut candidates = HashSet::new();
+        for term in &query.terms {
+            let with_prefix = self
+                .postings
+                .range(term.clone()..)
+                .take_while(|(token, _)| token.starts_with(term.as_str()));
+            for (_, uuids) in with_prefix {
+                candidates.extend(uuids.iter().copied());
+            }
+        }
+
+        let docs = candidates.into_iter().filter_map(|uuid| Some((uuid, self.docs.get(&uuid)?)));
+        rank(docs, self.docs.len(), query, limit)
+    }
+}
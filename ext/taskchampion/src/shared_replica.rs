@@ -0,0 +1,187 @@
+use magnus::{class, function, method, prelude::*, Error, IntoValue, RArray, RHash, RModule, Symbol, Value};
+use std::sync::Mutex;
+use taskchampion::{Replica as TCReplica, ServerConfig};
+
+use crate::dependency_graph;
+use crate::gvl::without_gvl;
+use crate::operations::Operations;
+use crate::replica::{in_memory_replica, on_disk_replica, remote_config};
+use crate::sync_result::{sync_replica, SyncResult};
+use crate::task::{cycle_error, Task};
+use crate::task_snapshot::TaskSnapshot;
+use crate::util::{into_error, option_to_ruby, uuid2tc, vec_to_ruby};
+
+/// Owns a replica on behalf of whichever thread holds the lock.
+///
+/// TaskChampion's storage is a `Box<dyn Storage>` without a `Send` bound, but
+/// the backends this extension opens (SQLite and in-memory) can move between
+/// threads, and the mutex in `SharedReplica` keeps use to one thread at a time.
+struct SendReplica(TCReplica);
+
+unsafe impl Send for SendReplica {}
+
+/// A replica that any Ruby thread may use, serialised by a mutex. Tasks it
+/// returns are bound to the calling thread like any other `Task`.
+#[magnus::wrap(class = "Taskchampion::SharedReplica", free_immediately)]
+pub struct SharedReplica(Mutex<SendReplica>);
+
+impl SharedReplica {
+    fn new_on_disk(
+        path: String,
+        create_if_missing: bool,
+        access_mode: Option<Symbol>,
+    ) -> Result<Self, Error> {
+        let replica = on_disk_replica(path, create_if_missing, access_mode)?;
+        Ok(SharedReplica(Mutex::new(SendReplica(replica))))
+    }
+
+    fn new_in_memory() -> Result<Self, Error> {
+        let replica = in_memory_replica()?;
+        Ok(SharedReplica(Mutex::new(SendReplica(replica))))
+    }
+
+    /// Run `f` with the replica locked. The GVL is released while waiting for
+    /// the lock and while `f` runs, so a thread blocked on another's sync
+    /// doesn't stop the rest of Ruby.
+    fn with_replica<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut TCReplica) -> Result<T, taskchampion::Error>,
+    {
+        without_gvl(|| {
+            // A panic while locked leaves the replica usable; TaskChampion
+            // keeps its own state consistent through storage transactions
+            let mut guard = self.0.lock().unwrap_or_else(|e| e.into_inner());
+            f(&mut guard.0)
+        })?
+        .map_err(into_error)
+    }
+
+    fn create_task(&self, uuid: String, operations: &Operations) -> Result<Value, Error> {
+        let tc_uuid = uuid2tc(&uuid)?;
+
+        let (tc_task, depmap, tc_ops) = self.with_replica(|replica| {
+            let mut tc_ops = vec![];
+            let tc_task = replica.create_task(tc_uuid, &mut tc_ops)?;
+            Ok((tc_task, replica.dependency_map(false)?, tc_ops))
+        })?;
+        operations.extend_from_tc(tc_ops)?;
+
+        Ok(Task::from_tc_task(tc_task, depmap).into_value())
+    }
+
+    fn commit_operations(&self, operations: &Operations) -> Result<(), Error> {
+        let tc_operations = operations.clone_inner()?;
+        let cycle = self.with_replica(|replica| {
+            match dependency_graph::new_cycle(replica, &tc_operations)? {
+                Some(cycle) => Ok(Some(cycle)),
+                None => replica.commit_operations(tc_operations).map(|_| None),
+            }
+        })?;
+        match cycle {
+            Some(cycle) => Err(cycle_error(&cycle)),
+            None => Ok(()),
+        }
+    }
+
+    fn task(&self, uuid: String) -> Result<Value, Error> {
+        let tc_uuid = uuid2tc(&uuid)?;
+
+        let (task, depmap) = self.with_replica(|replica| {
+            Ok((replica.get_task(tc_uuid)?, replica.dependency_map(false)?))
+        })?;
+
+        option_to_ruby(task, |task| Ok(Task::from_tc_task(task, depmap).into_value()))
+    }
+
+    fn snapshot(&self, uuid: String) -> Result<Value, Error> {
+        let tc_uuid = uuid2tc(&uuid)?;
+
+        let (task, depmap) = self.with_replica(|replica| {
+            Ok((replica.get_task(tc_uuid)?, replica.dependency_map(false)?))
+        })?;
+
+        option_to_ruby(task, |task| Ok(TaskSnapshot::new(task, Some(depmap)).into_value()))
+    }
+
+    fn all_tasks(&self) -> Result<RHash, Error> {
+        let (tasks, depmap) = self.with_replica(|replica| {
+            Ok((replica.all_tasks()?, replica.dependency_map(false)?))
+        })?;
+
+        let hash = RHash::new();
+        for (uuid, task) in tasks {
+            hash.aset(uuid.to_string(), Task::from_tc_task(task, depmap.clone()))?;
+        }
+        Ok(hash)
+    }
+
+    fn pending_tasks(&self) -> Result<RArray, Error> {
+        let (tasks, depmap) = self.with_replica(|replica| {
+            Ok((replica.pending_tasks()?, replica.dependency_map(false)?))
+        })?;
+
+        let array = RArray::with_capacity(tasks.len());
+        for task in tasks {
+            array.push(Task::from_tc_task(task, depmap.clone()))?;
+        }
+        Ok(array)
+    }
+
+    fn task_uuids(&self) -> Result<RArray, Error> {
+        let uuids = self.with_replica(|replica| replica.all_task_uuids())?;
+        vec_to_ruby(uuids, |uuid| Ok(uuid.to_string().into_value()))
+    }
+
+    fn rebuild_working_set(&self, renumber: Option<bool>) -> Result<(), Error> {
+        let renumber = renumber.unwrap_or(false);
+        self.with_replica(|replica| replica.rebuild_working_set(renumber))
+    }
+
+    fn num_local_operations(&self) -> Result<usize, Error> {
+        self.with_replica(|replica| replica.num_local_operations())
+    }
+
+    fn num_undo_points(&self) -> Result<usize, Error> {
+        self.with_replica(|replica| replica.num_undo_points())
+    }
+
+    fn sync_to_local(&self, server_dir: String, avoid_snapshots: Option<bool>) -> Result<SyncResult, Error> {
+        let avoid_snapshots = avoid_snapshots.unwrap_or(false);
+        let config = ServerConfig::Local {
+            server_dir: server_dir.into(),
+        };
+
+        self.with_replica(|replica| {
+            sync_replica(replica, || config.into_server(), avoid_snapshots).map(|(result, _)| result)
+        })
+    }
+
+    fn sync_to_remote(&self, kwargs: RHash) -> Result<SyncResult, Error> {
+        let (config, avoid_snapshots) = remote_config(kwargs)?;
+        self.with_replica(|replica| {
+            sync_replica(replica, || config.into_server(), avoid_snapshots).map(|(result, _)| result)
+        })
+    }
+}
+
+pub fn init(module: &RModule) -> Result<(), Error> {
+    let class = module.define_class("SharedReplica", class::object())?;
+
+    class.define_singleton_method("new_on_disk", function!(SharedReplica::new_on_disk, 3))?;
+    class.define_singleton_method("new_in_memory", function!(SharedReplica::new_in_memory, 0))?;
+
+    class.define_method("create_task", method!(SharedReplica::create_task, 2))?;
+    class.define_method("commit_operations", method!(SharedReplica::commit_operations, 1))?;
+    class.define_method("task", method!(SharedReplica::task, 1))?;
+    class.define_method("snapshot", method!(SharedReplica::snapshot, 1))?;
+    class.define_method("all_tasks", method!(SharedReplica::all_tasks, 0))?;
+    class.define_method("pending_tasks", method!(SharedReplica::pending_tasks, 0))?;
+    class.define_method("task_uuids", method!(SharedReplica::task_uuids, 0))?;
+    class.define_method("rebuild_working_set", method!(SharedReplica::rebuild_working_set, 1))?;
+    class.define_method("num_local_operations", method!(SharedReplica::num_local_operations, 0))?;
+    class.define_method("num_undo_points", method!(SharedReplica::num_undo_points, 0))?;
+    class.define_method("sync_to_local", method!(SharedReplica::sync_to_local, 2))?;
+    class.define_method("sync_to_remote", method!(SharedReplica::sync_to_remote, 1))?;
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use magnus::{class, function, method, prelude::*, Error, RModule, Symbol, TryConvert};
+use magnus::{class, function, method, prelude::*, Error, RModule, Symbol, TryConvert, Value};
 pub use taskchampion::Status as TCStatus;
 use crate::error::validation_error;
 
@@ -113,6 +113,20 @@ impl Status {
         }
     }
 
+    /// A status argument, given as a Status object or a symbol
+    pub fn from_value(value: Value) -> Result<Self, Error> {
+        if let Ok(status) = <&Status>::try_convert(value) {
+            Ok(*status)
+        } else if let Ok(symbol) = Symbol::try_convert(value) {
+            Status::from_symbol(symbol)
+        } else {
+            Err(Error::new(
+                validation_error(),
+                "Status must be a Taskchampion::Status object or a symbol (:pending, :completed, :deleted, etc.)"
+            ))
+        }
+    }
+
     pub fn to_symbol(&self) -> Symbol {
         match self.0 {
             StatusKind::Pending => Symbol::new("pending"),
@@ -0,0 +1,213 @@
+use magnus::{class, method, prelude::*, Error, RArray, RHash, RModule, Symbol};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use taskchampion::server::{
+    AddVersionResult, GetVersionResult, HistorySegment, Snapshot, SnapshotUrgency, VersionId,
+};
+use taskchampion::{Replica as TCReplica, Server, TaskData as TCTaskData, Uuid};
+
+use crate::gvl;
+use crate::history::{self, TaskChange};
+
+/// Summary of a single `Replica#sync_to_*` call.
+///
+/// This is a plain value computed once the sync has finished, so unlike most
+/// wrapped types it is not tied to the thread that created it.
+#[magnus::wrap(class = "Taskchampion::SyncResult", free_immediately)]
+pub struct SyncResult {
+    operations_sent: usize,
+    versions_fetched: usize,
+    versions_pushed: usize,
+    snapshot_uploaded: bool,
+    changed_task_uuids: Vec<Uuid>,
+    elapsed: Duration,
+}
+
+impl SyncResult {
+    fn operations_sent(&self) -> usize {
+        self.operations_sent
+    }
+
+    fn versions_fetched(&self) -> usize {
+        self.versions_fetched
+    }
+
+    fn versions_pushed(&self) -> usize {
+        self.versions_pushed
+    }
+
+    fn snapshot_uploaded(&self) -> bool {
+        self.snapshot_uploaded
+    }
+
+    fn changed_task_uuids(&self) -> RArray {
+        let array = RArray::new();
+        for uuid in &self.changed_task_uuids {
+            // Pushing a String into an RArray cannot fail
+            let _ = array.push(uuid.to_string());
+        }
+        array
+    }
+
+    fn elapsed(&self) -> f64 {
+        self.elapsed.as_secs_f64()
+    }
+
+    fn changed(&self) -> bool {
+        self.operations_sent > 0 || !self.changed_task_uuids.is_empty()
+    }
+
+    fn to_h(&self) -> Result<RHash, Error> {
+        let hash = RHash::new();
+        hash.aset(Symbol::new("operations_sent"), self.operations_sent)?;
+        hash.aset(Symbol::new("versions_fetched"), self.versions_fetched)?;
+        hash.aset(Symbol::new("versions_pushed"), self.versions_pushed)?;
+        hash.aset(Symbol::new("snapshot_uploaded"), self.snapshot_uploaded)?;
+        hash.aset(Symbol::new("changed_task_uuids"), self.changed_task_uuids())?;
+        hash.aset(Symbol::new("elapsed"), self.elapsed())?;
+        Ok(hash)
+    }
+
+    fn inspect(&self) -> String {
+        format!(
+            "#<Taskchampion::SyncResult operations_sent={} versions_fetched={} versions_pushed={} snapshot_uploaded={} changed_tasks={} elapsed={:.3}s>",
+            self.operations_sent,
+            self.versions_fetched,
+            self.versions_pushed,
+            self.snapshot_uploaded,
+            self.changed_task_uuids.len(),
+            self.elapsed.as_secs_f64(),
+        )
+    }
+}
+
+#[derive(Default)]
+struct SyncCounters {
+    versions_fetched: Cell<usize>,
+    versions_pushed: Cell<usize>,
+    snapshot_uploaded: Cell<bool>,
+}
+
+/// Delegates to the real server, counting the versions and snapshots that
+/// pass through it during a sync. Each request first checks whether the
+/// Ruby thread has been interrupted, so a sync stops between requests.
+struct CountingServer {
+    inner: Box<dyn Server>,
+    counters: Rc<SyncCounters>,
+}
+
+fn check_interrupted() -> Result<(), taskchampion::Error> {
+    if gvl::interrupted() {
+        return Err(taskchampion::Error::Server("Sync interrupted".into()));
+    }
+    Ok(())
+}
+
+impl Server for CountingServer {
+    fn add_version(
+        &mut self,
+        parent_version_id: VersionId,
+        history_segment: HistorySegment,
+    ) -> Result<(AddVersionResult, SnapshotUrgency), taskchampion::Error> {
+        check_interrupted()?;
+        let result = self.inner.add_version(parent_version_id, history_segment)?;
+        if let (AddVersionResult::Ok(_), _) = result {
+            self.counters.versions_pushed.set(self.counters.versions_pushed.get() + 1);
+        }
+        Ok(result)
+    }
+
+    fn get_child_version(
+        &mut self,
+        parent_version_id: VersionId,
+    ) -> Result<GetVersionResult, taskchampion::Error> {
+        check_interrupted()?;
+        let result = self.inner.get_child_version(parent_version_id)?;
+        if let GetVersionResult::Version { .. } = result {
+            self.counters.versions_fetched.set(self.counters.versions_fetched.get() + 1);
+        }
+        Ok(result)
+    }
+
+    fn add_snapshot(&mut self, version_id: VersionId, snapshot: Snapshot) -> Result<(), taskchampion::Error> {
+        check_interrupted()?;
+        self.inner.add_snapshot(version_id, snapshot)?;
+        self.counters.snapshot_uploaded.set(true);
+        Ok(())
+    }
+
+    fn get_snapshot(&mut self) -> Result<Option<(VersionId, Snapshot)>, taskchampion::Error> {
+        check_interrupted()?;
+        self.inner.get_snapshot()
+    }
+}
+
+/// UUIDs of tasks that were created, modified or removed between two
+/// snapshots of the replica, in sorted order
+fn changed_uuids(before: &HashMap<Uuid, TCTaskData>, after: &HashMap<Uuid, TCTaskData>) -> Vec<Uuid> {
+    let mut uuids: Vec<Uuid> = before
+        .keys()
+        .chain(after.keys())
+        .filter(|uuid| before.get(uuid) != after.get(uuid))
+        .copied()
+        .collect();
+    uuids.sort();
+    uuids.dedup();
+    uuids
+}
+
+/// Sync `replica` against the server returned by `connect`, comparing the
+/// replica's state before and after to build a `SyncResult` and a summary
+/// of the changes for `on_commit` hooks.
+pub fn sync_replica<F>(
+    replica: &mut TCReplica,
+    connect: F,
+    avoid_snapshots: bool,
+) -> Result<(SyncResult, Vec<TaskChange>), taskchampion::Error>
+where
+    F: FnOnce() -> Result<Box<dyn Server>, taskchampion::Error>,
+{
+    let started = Instant::now();
+    check_interrupted()?;
+    let counters = Rc::new(SyncCounters::default());
+    let mut server: Box<dyn Server> = Box::new(CountingServer {
+        inner: connect()?,
+        counters: counters.clone(),
+    });
+
+    let operations_before = replica.num_local_operations()?;
+    let tasks_before = replica.all_task_data()?;
+
+    replica.sync(&mut server, avoid_snapshots)?;
+
+    let operations_after = replica.num_local_operations()?;
+    let tasks_after = replica.all_task_data()?;
+
+    let result = SyncResult {
+        operations_sent: operations_before.saturating_sub(operations_after),
+        versions_fetched: counters.versions_fetched.get(),
+        versions_pushed: counters.versions_pushed.get(),
+        snapshot_uploaded: counters.snapshot_uploaded.get(),
+        changed_task_uuids: changed_uuids(&tasks_before, &tasks_after),
+        elapsed: started.elapsed(),
+    };
+    Ok((result, history::summarize_states(&tasks_before, &tasks_after)))
+}
+
+pub fn init(module: &RModule) -> Result<(), Error> {
+    let class = module.define_class("SyncResult", class::object())?;
+
+    class.define_method("operations_sent", method!(SyncResult::operations_sent, 0))?;
+    class.define_method("versions_fetched", method!(SyncResult::versions_fetched, 0))?;
+    class.define_method("versions_pushed", method!(SyncResult::versions_pushed, 0))?;
+    class.define_method("snapshot_uploaded?", method!(SyncResult::snapshot_uploaded, 0))?;
+    class.define_method("changed_task_uuids", method!(SyncResult::changed_task_uuids, 0))?;
+    class.define_method("elapsed", method!(SyncResult::elapsed, 0))?;
+    class.define_method("changed?", method!(SyncResult::changed, 0))?;
+    class.define_method("to_h", method!(SyncResult::to_h, 0))?;
+    class.define_method("inspect", method!(SyncResult::inspect, 0))?;
+
+    Ok(())
+}
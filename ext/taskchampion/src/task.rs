@@ -1,20 +1,118 @@
 use magnus::{
-    class, method, prelude::*, Error, IntoValue, RArray, RModule, Symbol, TryConvert, Value,
+    class, method, prelude::*, Error, IntoValue, RArray, RHash, RModule, Symbol, TryConvert, Value,
 };
-use taskchampion::Task as TCTask;
+use chrono::Utc;
+use magnus::scan_args::{get_kwargs, scan_args};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use taskchampion::{DependencyMap as TCDependencyMap, Task as TCTask, Uuid};
 
 use crate::annotation::Annotation;
+use crate::filter::FilterContext;
+use crate::recurrence;
 use crate::status::Status;
 use crate::tag::Tag;
+use crate::task_snapshot::TaskSnapshot;
 use crate::thread_check::ThreadBound;
-use crate::util::{datetime_to_ruby, option_to_ruby, ruby_to_datetime, ruby_to_option, vec_to_ruby};
-
+use crate::uda_schema::{Definition, UdaSchema};
+use crate::urgency::{self, Coefficients, UrgencyConfig};
+use crate::util::{datetime_to_ruby, option_to_ruby, ruby_to_datetime, ruby_to_option, uuid2tc, vec_to_ruby};
+
+/// The second field is the replica's dependency map at the time the task was
+/// loaded, used to reject dependency cycles early. Tasks built without a
+/// replica have no map and only reject self-dependencies; either way
+/// `Replica#commit_operations` checks again against the stored tasks. The third is the UDA
+/// schema registered on the replica, used by `set_uda` and `uda`.
 #[magnus::wrap(class = "Taskchampion::Task", free_immediately)]
-pub struct Task(ThreadBound<TCTask>);
+pub struct Task(ThreadBound<TCTask>, Option<Arc<TCDependencyMap>>, Option<UdaSchema>);
+
+/// A ValidationError for a dependency that would close `cycle`, which starts
+/// and ends with the task given the dependency
+pub(crate) fn cycle_error(cycle: &[Uuid]) -> Error {
+    let path: Vec<String> = cycle.iter().map(Uuid::to_string).collect();
+    Error::new(
+        crate::error::validation_error(),
+        format!("Adding dependency would create a cycle: {}", path.join(" -> "))
+    )
+}
 
 impl Task {
-    pub fn from_tc_task(tc_task: TCTask) -> Self {
-        Task(ThreadBound::new(tc_task))
+    pub fn from_tc_task(tc_task: TCTask, depmap: Arc<TCDependencyMap>) -> Self {
+        Task(ThreadBound::new(tc_task), Some(depmap), None)
+    }
+
+    pub fn from_parts(tc_task: TCTask, depmap: Option<Arc<TCDependencyMap>>) -> Self {
+        Task(ThreadBound::new(tc_task), depmap, None)
+    }
+
+    pub fn with_uda_schema(mut self, uda_schema: Option<UdaSchema>) -> Self {
+        self.2 = uda_schema;
+        self
+    }
+
+    /// A copy of this task that can be passed to other threads
+    fn to_snapshot(&self) -> Result<TaskSnapshot, Error> {
+        let task = self.0.get()?;
+        Ok(TaskSnapshot::new(task.clone(), self.1.clone()).with_uda_schema(self.2.clone()))
+    }
+
+    /// The schema definition for the UDA stored as `name`, if any
+    fn uda_definition(&self, name: &str) -> Option<Definition> {
+        self.2.as_ref().and_then(|schema| schema.definition(name))
+    }
+
+    /// Resolve a Ruby Task or UUID string into a Uuid
+    fn uuid_from_value(value: Value) -> Result<Uuid, Error> {
+        if let Ok(task) = <&Task>::try_convert(value) {
+            Ok(task.0.get()?.get_uuid())
+        } else if let Ok(uuid) = String::try_convert(value) {
+            uuid2tc(&uuid)
+        } else {
+            Err(Error::new(
+                crate::error::validation_error(),
+                "Dependency must be a Taskchampion::Task or a UUID string"
+            ))
+        }
+    }
+
+    /// Find a dependency path from `from` back to `to`, following the current
+    /// task's own dependencies plus the edges in the dependency map. Returns the
+    /// path including both endpoints, or None if `to` is unreachable.
+    fn dependency_path(&self, task: &TCTask, from: Uuid, to: Uuid) -> Option<Vec<Uuid>> {
+        let own_uuid = task.get_uuid();
+        let own_deps: Vec<Uuid> = task.get_dependencies().collect();
+        let depmap = self.1.as_deref();
+
+        let mut previous: HashMap<Uuid, Uuid> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(current) = queue.pop_front() {
+            if current == to {
+                let mut path = vec![current];
+                let mut node = current;
+                while let Some(prev) = previous.get(&node) {
+                    path.push(*prev);
+                    node = *prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            // The task's own data is fresher than the cached map, so prefer it
+            let next: Vec<Uuid> = if current == own_uuid {
+                own_deps.clone()
+            } else {
+                depmap
+                    .map(|dm| dm.dependencies(current).collect())
+                    .unwrap_or_default()
+            };
+            for dep in next {
+                if dep != from && !previous.contains_key(&dep) {
+                    previous.insert(dep, current);
+                    queue.push_back(dep);
+                }
+            }
+        }
+        None
     }
 
     fn inspect(&self) -> Result<String, Error> {
@@ -62,6 +160,17 @@ impl Task {
         option_to_ruby(task.get_due(), datetime_to_ruby)
     }
 
+    fn start_time(&self) -> Result<Value, Error> {
+        let task = self.0.get()?;
+        // TaskChampion stores "start" as a Unix timestamp string and only sets it
+        // while the task is active
+        let start = task
+            .get_value("start")
+            .and_then(|s| s.parse::<i64>().ok())
+            .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0));
+        option_to_ruby(start, datetime_to_ruby)
+    }
+
     fn dependencies(&self) -> Result<RArray, Error> {
         let task = self.0.get()?;
         let deps: Vec<String> = task.get_dependencies().map(|uuid| uuid.to_string()).collect();
@@ -139,7 +248,10 @@ impl Task {
         let task = self.0.get()?;
         let combined_key = if namespace.is_empty() { key } else { format!("{}.{}", namespace, key) };
         match task.get_user_defined_attribute(&combined_key) {
-            Some(value) => Ok(value.into_value()),
+            Some(value) => match self.uda_definition(&combined_key) {
+                Some(definition) => definition.decode(value),
+                None => Ok(value.into_value()),
+            },
             None => Ok(().into_value()),
         }
     }
@@ -178,16 +290,7 @@ impl Task {
         let mut task = self.0.get_mut()?;
 
         // Handle both Status objects and symbols
-        let status = if let Ok(status_obj) = <&Status>::try_convert(status) {
-            *status_obj // Copy the Status object
-        } else if let Ok(symbol) = Symbol::try_convert(status) {
-            Status::from_symbol(symbol)?
-        } else {
-            return Err(Error::new(
-                crate::error::validation_error(),
-                "Status must be a Taskchampion::Status object or a symbol (:pending, :completed, :deleted, etc.)"
-            ));
-        };
+        let status = Status::from_value(status)?;
 
         operations.with_inner_mut(|ops| {
             task.set_status(status.into(), ops)
@@ -294,6 +397,23 @@ impl Task {
         Ok(())
     }
 
+    fn set_wait(&self, wait: Value, operations: &crate::operations::Operations) -> Result<(), Error> {
+        let mut task = self.0.get_mut()?;
+        let wait_datetime = ruby_to_option(wait, ruby_to_datetime)?;
+        operations.with_inner_mut(|ops| {
+            task.set_wait(wait_datetime, ops)
+        })?;
+        Ok(())
+    }
+
+    fn clear_wait(&self, operations: &crate::operations::Operations) -> Result<(), Error> {
+        let mut task = self.0.get_mut()?;
+        operations.with_inner_mut(|ops| {
+            task.set_wait(None, ops)
+        })?;
+        Ok(())
+    }
+
     fn set_entry(&self, entry: Value, operations: &crate::operations::Operations) -> Result<(), Error> {
         let mut task = self.0.get_mut()?;
         let entry_datetime = ruby_to_option(entry, ruby_to_datetime)?;
@@ -379,7 +499,7 @@ impl Task {
         }
     }
 
-    fn set_uda(&self, namespace: String, key: String, value: String, operations: &crate::operations::Operations) -> Result<(), Error> {
+    fn set_uda(&self, namespace: String, key: String, value: Value, operations: &crate::operations::Operations) -> Result<(), Error> {
         if namespace.trim().is_empty() {
             return Err(Error::new(
                 crate::error::validation_error(),
@@ -393,8 +513,13 @@ impl Task {
             ));
         }
 
-        let mut task = self.0.get_mut()?;
         let combined_key = format!("{}.{}", namespace, key);
+        let value = match self.uda_definition(&combined_key) {
+            Some(definition) => definition.encode(&combined_key, value)?,
+            None => String::try_convert(value)?,
+        };
+
+        let mut task = self.0.get_mut()?;
         operations.with_inner_mut(|ops| {
             task.set_user_defined_attribute(&combined_key, &value, ops)
         })?;
@@ -423,6 +548,94 @@ impl Task {
         Ok(())
     }
 
+    fn start(&self, operations: &crate::operations::Operations) -> Result<(), Error> {
+        let mut task = self.0.get_mut()?;
+        operations.with_inner_mut(|ops| {
+            task.start(ops)
+        })?;
+        Ok(())
+    }
+
+    fn stop(&self, operations: &crate::operations::Operations) -> Result<(), Error> {
+        let mut task = self.0.get_mut()?;
+        operations.with_inner_mut(|ops| {
+            task.stop(ops)
+        })?;
+        Ok(())
+    }
+
+    fn add_dependency(&self, dependency: Value, operations: &crate::operations::Operations) -> Result<(), Error> {
+        // Resolve before borrowing self mutably, as the dependency may be this task
+        let dep_uuid = Self::uuid_from_value(dependency)?;
+
+        let mut task = self.0.get_mut()?;
+        let task_uuid = task.get_uuid();
+        if let Some(path) = self.dependency_path(&task, dep_uuid, task_uuid) {
+            let cycle: Vec<Uuid> = std::iter::once(task_uuid).chain(path).collect();
+            return Err(cycle_error(&cycle));
+        }
+
+        operations.with_inner_mut(|ops| {
+            task.add_dependency(dep_uuid, ops)
+        })?;
+        Ok(())
+    }
+
+    fn remove_dependency(&self, dependency: Value, operations: &crate::operations::Operations) -> Result<(), Error> {
+        let dep_uuid = Self::uuid_from_value(dependency)?;
+
+        let mut task = self.0.get_mut()?;
+        operations.with_inner_mut(|ops| {
+            task.remove_dependency(dep_uuid, ops)
+        })?;
+        Ok(())
+    }
+
+    fn set_recurrence(&self, args: &[Value]) -> Result<(), Error> {
+        let args = scan_args::<(String, &crate::operations::Operations), (), (), (), RHash, ()>(args)?;
+        let (rule, operations) = args.required;
+        let kwargs = get_kwargs::<_, (), (Option<Value>,), ()>(args.keywords, &[], &["until"])?;
+        let (until,) = kwargs.optional;
+
+        let rule = recurrence::parse_rule(&rule)?;
+        let mut task = self.0.get_mut()?;
+        let due = task.get_due().ok_or_else(|| Error::new(
+            crate::error::validation_error(),
+            "A recurring task needs a due date; call set_due first"
+        ))?;
+
+        // An explicit until: wins over the RRULE's own COUNT or UNTIL
+        let until = match until {
+            Some(until) => ruby_to_option(until, ruby_to_datetime)?,
+            None => match rule.limit {
+                Some(recurrence::Limit::Until(until)) => Some(until),
+                Some(recurrence::Limit::Count(count)) => rule.period.nth(due, count - 1),
+                None => None,
+            },
+        };
+
+        operations.with_inner_mut(|ops| {
+            task.set_value("recur", Some(rule.recur.clone()), ops)?;
+            task.set_value("until", until.map(|u| u.timestamp().to_string()), ops)?;
+            task.set_status(taskchampion::Status::Recurring, ops)
+        })?;
+        Ok(())
+    }
+
+    fn urgency(&self, args: &[Value]) -> Result<f64, Error> {
+        let args = scan_args::<(), (Option<Option<&UrgencyConfig>>,), (), (), (), ()>(args)?;
+        let (config,) = args.optional;
+        let coefficients = match config.flatten() {
+            Some(config) => config.coefficients()?,
+            None => Coefficients::default(),
+        };
+
+        let task = self.0.get()?;
+        let task_data = task.clone().into_task_data();
+        let ctx = FilterContext { depmap: self.1.as_deref(), now: Utc::now() };
+        Ok(urgency::urgency(&task_data, &coefficients, &ctx))
+    }
+
     fn done(&self, operations: &crate::operations::Operations) -> Result<(), Error> {
         let mut task = self.0.get_mut()?;
         operations.with_inner_mut(|ops| {
@@ -438,7 +651,7 @@ impl Task {
 
 impl From<TCTask> for Task {
     fn from(value: TCTask) -> Self {
-        Task(ThreadBound::new(value))
+        Task(ThreadBound::new(value), None, None)
     }
 }
 
@@ -455,6 +668,7 @@ pub fn init(module: &RModule) -> Result<(), Error> {
     class.define_method("wait", method!(Task::wait, 0))?;
     class.define_method("modified", method!(Task::modified, 0))?;
     class.define_method("due", method!(Task::due, 0))?;
+    class.define_method("start_time", method!(Task::start_time, 0))?;
     class.define_method("dependencies", method!(Task::dependencies, 0))?;
 
     // Boolean methods with ? suffix
@@ -488,6 +702,8 @@ pub fn init(module: &RModule) -> Result<(), Error> {
     class.define_method("remove_annotation", method!(Task::remove_annotation, 2))?;
     class.define_method("add_annotation_with_timestamp", method!(Task::add_annotation_with_timestamp, 3))?;
     class.define_method("set_due", method!(Task::set_due, 2))?;
+    class.define_method("set_wait", method!(Task::set_wait, 2))?;
+    class.define_method("clear_wait", method!(Task::clear_wait, 1))?;
     class.define_method("set_entry", method!(Task::set_entry, 2))?;
     class.define_method("set_modified", method!(Task::set_modified, 2))?;
     class.define_method("set_value", method!(Task::set_value, 3))?;
@@ -495,6 +711,13 @@ pub fn init(module: &RModule) -> Result<(), Error> {
     class.define_method("get_timestamp", method!(Task::get_timestamp, 1))?;
     class.define_method("set_uda", method!(Task::set_uda, 4))?;
     class.define_method("delete_uda", method!(Task::delete_uda, 3))?;
+    class.define_method("start", method!(Task::start, 1))?;
+    class.define_method("stop", method!(Task::stop, 1))?;
+    class.define_method("add_dependency", method!(Task::add_dependency, 2))?;
+    class.define_method("remove_dependency", method!(Task::remove_dependency, 2))?;
+    class.define_method("set_recurrence", method!(Task::set_recurrence, -1))?;
+    class.define_method("urgency", method!(Task::urgency, -1))?;
     class.define_method("done", method!(Task::done, 1))?;
+    class.define_method("to_snapshot", method!(Task::to_snapshot, 0))?;
     Ok(())
 }
@@ -0,0 +1,169 @@
+use magnus::{class, method, prelude::*, Error, IntoValue, RArray, RHash, RModule, Symbol, TryConvert, Value};
+use std::sync::Arc;
+use taskchampion::{DependencyMap as TCDependencyMap, Status as TCStatus, Task as TCTask};
+
+use crate::annotation::Annotation;
+use crate::status::Status;
+use crate::tag::Tag;
+use crate::task::Task;
+use crate::uda_schema::UdaSchema;
+use crate::util::{datetime_to_ruby, option_to_ruby, vec_to_ruby};
+
+/// A read-only copy of a task taken at a point in time.
+///
+/// Unlike `Task` it is not tied to the thread that created it, so snapshots
+/// can be handed between threads, e.g. through a `Queue`. `to_task` turns a
+/// snapshot back into a `Task` owned by the calling thread.
+#[magnus::wrap(class = "Taskchampion::TaskSnapshot", free_immediately)]
+pub struct TaskSnapshot {
+    task: TCTask,
+    depmap: Option<Arc<TCDependencyMap>>,
+    uda_schema: Option<UdaSchema>,
+}
+
+impl TaskSnapshot {
+    pub fn new(task: TCTask, depmap: Option<Arc<TCDependencyMap>>) -> Self {
+        TaskSnapshot { task, depmap, uda_schema: None }
+    }
+
+    pub fn with_uda_schema(mut self, uda_schema: Option<UdaSchema>) -> Self {
+        self.uda_schema = uda_schema;
+        self
+    }
+
+    fn uuid(&self) -> String {
+        self.task.get_uuid().to_string()
+    }
+
+    fn status(&self) -> Symbol {
+        Status::from(self.task.get_status()).to_symbol()
+    }
+
+    fn description(&self) -> String {
+        self.task.get_description().to_string()
+    }
+
+    fn priority(&self) -> String {
+        self.task.get_priority().to_string()
+    }
+
+    fn entry(&self) -> Result<Value, Error> {
+        option_to_ruby(self.task.get_entry(), datetime_to_ruby)
+    }
+
+    fn wait(&self) -> Result<Value, Error> {
+        option_to_ruby(self.task.get_wait(), datetime_to_ruby)
+    }
+
+    fn modified(&self) -> Result<Value, Error> {
+        option_to_ruby(self.task.get_modified(), datetime_to_ruby)
+    }
+
+    fn due(&self) -> Result<Value, Error> {
+        option_to_ruby(self.task.get_due(), datetime_to_ruby)
+    }
+
+    fn dependencies(&self) -> Result<RArray, Error> {
+        let deps: Vec<String> = self.task.get_dependencies().map(|uuid| uuid.to_string()).collect();
+        vec_to_ruby(deps, |s| Ok(s.into_value()))
+    }
+
+    fn tags(&self) -> Result<RArray, Error> {
+        let tags: Vec<Tag> = self.task.get_tags().map(Tag::from).collect();
+        vec_to_ruby(tags, |tag| Ok(tag.into_value()))
+    }
+
+    fn annotations(&self) -> Result<RArray, Error> {
+        let annotations: Vec<Annotation> = self.task.get_annotations().map(Annotation::from).collect();
+        vec_to_ruby(annotations, |ann| Ok(ann.into_value()))
+    }
+
+    fn has_tag(&self, tag: &Tag) -> bool {
+        self.task.has_tag(tag.as_ref())
+    }
+
+    fn get_value(&self, property: String) -> Option<String> {
+        self.task.get_value(property).map(str::to_string)
+    }
+
+    fn waiting(&self) -> bool {
+        self.task.is_waiting()
+    }
+
+    fn active(&self) -> bool {
+        self.task.is_active()
+    }
+
+    fn blocked(&self) -> bool {
+        self.task.is_blocked()
+    }
+
+    fn blocking(&self) -> bool {
+        self.task.is_blocking()
+    }
+
+    fn pending(&self) -> bool {
+        self.task.get_status() == TCStatus::Pending
+    }
+
+    fn completed(&self) -> bool {
+        self.task.get_status() == TCStatus::Completed
+    }
+
+    fn deleted(&self) -> bool {
+        self.task.get_status() == TCStatus::Deleted
+    }
+
+    /// A `Task` for this snapshot, bound to the calling thread
+    fn to_task(&self) -> Task {
+        Task::from_parts(self.task.clone(), self.depmap.clone()).with_uda_schema(self.uda_schema.clone())
+    }
+
+    /// The task's raw properties, as stored by TaskChampion
+    fn to_h(&self) -> Result<RHash, Error> {
+        let hash = RHash::new();
+        for (key, value) in self.task.get_taskmap() {
+            hash.aset(key.as_str(), value.as_str())?;
+        }
+        Ok(hash)
+    }
+
+    fn eq(&self, other: Value) -> bool {
+        <&TaskSnapshot>::try_convert(other).is_ok_and(|other| self.task == other.task)
+    }
+
+    fn inspect(&self) -> String {
+        format!("#<Taskchampion::TaskSnapshot: {}>", self.task.get_uuid())
+    }
+}
+
+pub fn init(module: &RModule) -> Result<(), Error> {
+    let class = module.define_class("TaskSnapshot", class::object())?;
+
+    class.define_method("uuid", method!(TaskSnapshot::uuid, 0))?;
+    class.define_method("status", method!(TaskSnapshot::status, 0))?;
+    class.define_method("description", method!(TaskSnapshot::description, 0))?;
+    class.define_method("priority", method!(TaskSnapshot::priority, 0))?;
+    class.define_method("entry", method!(TaskSnapshot::entry, 0))?;
+    class.define_method("wait", method!(TaskSnapshot::wait, 0))?;
+    class.define_method("modified", method!(TaskSnapshot::modified, 0))?;
+    class.define_method("due", method!(TaskSnapshot::due, 0))?;
+    class.define_method("dependencies", method!(TaskSnapshot::dependencies, 0))?;
+    class.define_method("tags", method!(TaskSnapshot::tags, 0))?;
+    class.define_method("annotations", method!(TaskSnapshot::annotations, 0))?;
+    class.define_method("has_tag?", method!(TaskSnapshot::has_tag, 1))?;
+    class.define_method("value", method!(TaskSnapshot::get_value, 1))?;
+    class.define_method("waiting?", method!(TaskSnapshot::waiting, 0))?;
+    class.define_method("active?", method!(TaskSnapshot::active, 0))?;
+    class.define_method("blocked?", method!(TaskSnapshot::blocked, 0))?;
+    class.define_method("blocking?", method!(TaskSnapshot::blocking, 0))?;
+    class.define_method("pending?", method!(TaskSnapshot::pending, 0))?;
+    class.define_method("completed?", method!(TaskSnapshot::completed, 0))?;
+    class.define_method("deleted?", method!(TaskSnapshot::deleted, 0))?;
+    class.define_method("to_task", method!(TaskSnapshot::to_task, 0))?;
+    class.define_method("to_h", method!(TaskSnapshot::to_h, 0))?;
+    class.define_method("==", method!(TaskSnapshot::eq, 1))?;
+    class.define_method("inspect", method!(TaskSnapshot::inspect, 0))?;
+
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use magnus::Error;
+use serde_json::{Map, Value as JsonValue};
+use std::collections::HashMap;
+use taskchampion::{TaskData as TCTaskData, Uuid};
+
+use crate::error::validation_error;
+
+/// Properties holding Unix timestamps, written as Taskwarrior compact dates
+const DATE_PROPERTIES: &[&str] = &[
+    "due", "wait", "entry", "modified", "start", "end", "scheduled", "until",
+];
+
+/// Computed fields in `task export` output that are not task properties
+const COMPUTED_FIELDS: &[&str] = &["id", "urgency"];
+
+const TW_DATE_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+fn format_timestamp(value: &str) -> Option<String> {
+    let secs = value.parse::<i64>().ok()?;
+    DateTime::from_timestamp(secs, 0).map(|dt| dt.format(TW_DATE_FORMAT).to_string())
+}
+
+/// Parse a Taskwarrior compact date, an ISO 8601 timestamp or a Unix
+/// timestamp into a Unix timestamp string
+fn parse_timestamp(value: &str) -> Option<String> {
+    if let Ok(dt) = NaiveDateTime::parse_from_str(value, TW_DATE_FORMAT) {
+        return Some(dt.and_utc().timestamp().to_string());
+    }
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Some(dt.with_timezone(&Utc).timestamp().to_string());
+    }
+    value.parse::<i64>().ok().map(|secs| secs.to_string())
+}
+
+/// Convert a task into a Taskwarrior `task export` JSON object. `id` is the
+/// working-set index, or 0 for tasks outside the working set.
+pub fn task_to_json(task: &TCTaskData, id: usize) -> JsonValue {
+    let mut object = Map::new();
+    let mut tags = Vec::new();
+    let mut annotations = Vec::new();
+    let mut depends = Vec::new();
+
+    object.insert("id".into(), id.into());
+    object.insert("uuid".into(), task.get_uuid().to_string().into());
+
+    let mut properties: Vec<(&String, &String)> = task.iter().collect();
+    properties.sort();
+    for (key, value) in properties {
+        if let Some(tag) = key.strip_prefix("tag_") {
+            tags.push(JsonValue::from(tag));
+        } else if let Some(dep) = key.strip_prefix("dep_") {
+            depends.push(JsonValue::from(dep));
+        } else if let Some(entry) = key.strip_prefix("annotation_") {
+            let mut annotation = Map::new();
+            annotation.insert("entry".into(), format_timestamp(entry).unwrap_or_else(|| entry.to_string()).into());
+            annotation.insert("description".into(), value.clone().into());
+            annotations.push(JsonValue::Object(annotation));
+        } else if DATE_PROPERTIES.contains(&key.as_str()) {
+            if let Some(date) = format_timestamp(value) {
+                object.insert(key.clone(), date.into());
+            }
+        } else if key == "imask" {
+            // Taskwarrior stores the recurrence instance mask as a number
+            let imask = value.parse::<i64>().map(JsonValue::from).unwrap_or_else(|_| value.clone().into());
+            object.insert(key.clone(), imask);
+        } else {
+            object.insert(key.clone(), value.clone().into());
+        }
+    }
+
+    if !tags.is_empty() {
+        object.insert("tags".into(), tags.into());
+    }
+    if !annotations.is_empty() {
+        object.insert("annotations".into(), annotations.into());
+    }
+    if !depends.is_empty() {
+        object.insert("depends".into(), depends.into());
+    }
+    JsonValue::Object(object)
+}
+
+fn import_error(message: impl Into<String>) -> Error {
+    Error::new(validation_error(), message.into())
+}
+
+fn scalar_to_string(value: &JsonValue) -> Option<String> {
+    match value {
+        JsonValue::Null => None,
+        JsonValue::String(s) => Some(s.clone()),
+        JsonValue::Number(n) => Some(n.to_string()),
+        JsonValue::Bool(b) => Some(b.to_string()),
+        other => Some(other.to_string()),
+    }
+}
+
+/// Convert one Taskwarrior JSON task into its UUID and TaskChampion
+/// properties. Tasks without a UUID are given a new one, as Taskwarrior does.
+pub fn json_to_task(value: &JsonValue) -> Result<(Uuid, HashMap<String, String>), Error> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| import_error("Each imported task must be a JSON object"))?;
+
+    let uuid = match object.get("uuid").and_then(JsonValue::as_str) {
+        Some(uuid) => crate::util::uuid2tc(uuid)?,
+        None => Uuid::new_v4(),
+    };
+
+    let mut properties = HashMap::new();
+    for (key, value) in object {
+        match key.as_str() {
+            "uuid" => {}
+            key if COMPUTED_FIELDS.contains(&key) => {}
+            "tags" => {
+                let tags = value
+                    .as_array()
+                    .ok_or_else(|| import_error("\"tags\" must be an array"))?;
+                for tag in tags.iter().filter_map(JsonValue::as_str) {
+                    properties.insert(format!("tag_{}", tag), String::new());
+                }
+            }
+            "depends" => {
+                // Taskwarrior 2.5 and earlier wrote a comma-separated string
+                let deps: Vec<String> = match value {
+                    JsonValue::Array(deps) => deps.iter().filter_map(JsonValue::as_str).map(str::to_string).collect(),
+                    JsonValue::String(deps) => deps.split(',').map(|d| d.trim().to_string()).filter(|d| !d.is_empty()).collect(),
+                    _ => return Err(import_error("\"depends\" must be an array or a string")),
+                };
+                for dep in deps {
+                    properties.insert(format!("dep_{}", crate::util::uuid2tc(&dep)?), String::new());
+                }
+            }
+            "annotations" => {
+                let annotations = value
+                    .as_array()
+                    .ok_or_else(|| import_error("\"annotations\" must be an array"))?;
+                for annotation in annotations {
+                    let entry = annotation.get("entry").and_then(JsonValue::as_str).and_then(parse_timestamp);
+                    let description = annotation.get("description").and_then(JsonValue::as_str);
+                    match (entry, description) {
+                        (Some(entry), Some(description)) => {
+                            properties.insert(format!("annotation_{}", entry), description.to_string());
+                        }
+                        _ => return Err(import_error("Annotations need an \"entry\" date and a \"description\"")),
+                    }
+                }
+            }
+            key if DATE_PROPERTIES.contains(&key) => {
+                if let Some(date) = value.as_str() {
+                    let timestamp = parse_timestamp(date)
+                        .ok_or_else(|| import_error(format!("Invalid date '{}' for '{}'", date, key)))?;
+                    properties.insert(key.to_string(), timestamp);
+                }
+            }
+            key => {
+                if let Some(value) = scalar_to_string(value) {
+                    properties.insert(key.to_string(), value);
+                }
+            }
+        }
+    }
+
+    Ok((uuid, properties))
+}
+
+/// Parse `task export` output (a JSON array) or one JSON task per line
+pub fn parse_tasks(input: &str) -> Result<Vec<JsonValue>, Error> {
+    let trimmed = input.trim_start();
+    if trimmed.is_empty() {
+        return Ok(Vec::new());
+    }
+    if trimmed.starts_with('[') {
+        return match serde_json::from_str(trimmed) {
+            Ok(JsonValue::Array(tasks)) => Ok(tasks),
+            Ok(_) => Err(import_error("Expected a JSON array of tasks")),
+            Err(e) => Err(import_error(format!("Invalid JSON: {}", e))),
+        };
+    }
+    trimmed
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| import_error(format!("Invalid JSON on line {}: {}", i + 1, e)))
+        })
+        .collect()
+}
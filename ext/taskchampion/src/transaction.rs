@@ -0,0 +1,116 @@
+use magnus::{
+    class, gc::Marker, method, prelude::*, typed_data::Obj, value::Opaque, DataTypeFunctions, Error,
+    RModule, Ruby, TypedData, Value,
+};
+use taskchampion::{Operation as TCOperation, Operations as TCOperations};
+
+use crate::error::validation_error;
+use crate::operations::Operations;
+use crate::replica::Replica;
+use crate::thread_check::ThreadBound;
+
+/// The handle yielded by `Replica#transaction`. It collects operations for a
+/// single commit, starting with an undo point, and stops accepting work once
+/// the block has returned.
+#[derive(TypedData)]
+#[magnus(class = "Taskchampion::Transaction", free_immediately, mark)]
+pub struct Transaction {
+    replica: Opaque<Obj<Replica>>,
+    operations: Opaque<Obj<Operations>>,
+    open: ThreadBound<bool>,
+}
+
+impl DataTypeFunctions for Transaction {
+    fn mark(&self, marker: &Marker) {
+        marker.mark(self.replica);
+        marker.mark(self.operations);
+    }
+}
+
+impl Transaction {
+    /// Run the current block as a transaction on `replica`, committing its
+    /// operations if the block returns normally and discarding them otherwise.
+    /// Returns the block's value.
+    pub fn run(ruby: &Ruby, replica: Obj<Replica>) -> Result<Value, Error> {
+        if !ruby.block_given() {
+            return Err(Error::new(ruby.exception_local_jump_error(), "no block given"));
+        }
+        let block = ruby.block_proc()?;
+
+        let operations = Operations::from(TCOperations::new());
+        operations.with_inner_mut(|ops| {
+            ops.push(TCOperation::UndoPoint);
+            Ok(())
+        })?;
+        let operations = ruby.obj_wrap(operations);
+
+        let transaction = ruby.obj_wrap(Transaction {
+            replica: replica.into(),
+            operations: operations.into(),
+            open: ThreadBound::new(true),
+        });
+
+        let result = block.call::<_, Value>((transaction,));
+        *transaction.open.get_mut()? = false;
+
+        match result {
+            Ok(value) => {
+                // Nothing to commit beyond the undo point
+                if operations.len()? > 1 {
+                    replica.commit_operations(&operations)?;
+                }
+                Ok(value)
+            }
+            Err(e) => {
+                operations.clear()?;
+                Err(e)
+            }
+        }
+    }
+
+    fn ensure_open(&self) -> Result<(), Error> {
+        if *self.open.get()? {
+            Ok(())
+        } else {
+            Err(Error::new(validation_error(), "Transaction has already finished"))
+        }
+    }
+
+    fn operations(ruby: &Ruby, rb_self: &Self) -> Result<Obj<Operations>, Error> {
+        rb_self.ensure_open()?;
+        Ok(ruby.get_inner(rb_self.operations))
+    }
+
+    fn create_task(ruby: &Ruby, rb_self: &Self, uuid: String) -> Result<Value, Error> {
+        rb_self.ensure_open()?;
+        let operations = ruby.get_inner(rb_self.operations);
+        ruby.get_inner(rb_self.replica).create_task(uuid, &operations)
+    }
+
+    fn task(ruby: &Ruby, rb_self: &Self, uuid: String) -> Result<Value, Error> {
+        rb_self.ensure_open()?;
+        ruby.get_inner(rb_self.replica).task(uuid)
+    }
+
+    fn is_open(&self) -> Result<bool, Error> {
+        Ok(*self.open.get()?)
+    }
+
+    fn inspect(ruby: &Ruby, rb_self: &Self) -> Result<String, Error> {
+        let state = if *rb_self.open.get()? { "open" } else { "finished" };
+        let operations = ruby.get_inner(rb_self.operations).len()?;
+        Ok(format!("#<Taskchampion::Transaction: {}, {} operations>", state, operations))
+    }
+}
+
+pub fn init(module: &RModule) -> Result<(), Error> {
+    let class = module.define_class("Transaction", class::object())?;
+
+    class.define_method("operations", method!(Transaction::operations, 0))?;
+    class.define_method("create_task", method!(Transaction::create_task, 1))?;
+    class.define_method("task", method!(Transaction::task, 1))?;
+    class.define_method("open?", method!(Transaction::is_open, 0))?;
+    class.define_method("inspect", method!(Transaction::inspect, 0))?;
+
+    Ok(())
+}
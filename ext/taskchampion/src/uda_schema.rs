@@ -0,0 +1,253 @@
+use chrono::{DateTime, Utc};
+use magnus::{
+    class, function, method, prelude::*, scan_args::{get_kwargs, scan_args}, typed_data::Obj, Error, Float,
+    Integer, IntoValue, RArray, RHash, RModule, RString, Symbol, TryConvert, Value,
+};
+use std::collections::BTreeMap;
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crate::error::validation_error;
+use crate::recurrence;
+use crate::util::{datetime_to_ruby, ruby_to_datetime};
+
+/// The kinds of value a UDA can hold: Taskwarrior's `uda.<name>.type`s,
+/// plus `enum` for strings limited to a list of values
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum UdaType {
+    String,
+    Numeric,
+    Date,
+    Duration,
+    Enum,
+}
+
+impl UdaType {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "string" => Some(UdaType::String),
+            "numeric" => Some(UdaType::Numeric),
+            "date" => Some(UdaType::Date),
+            "duration" => Some(UdaType::Duration),
+            "enum" => Some(UdaType::Enum),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            UdaType::String => "string",
+            UdaType::Numeric => "numeric",
+            UdaType::Date => "date",
+            UdaType::Duration => "duration",
+            UdaType::Enum => "enum",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Definition {
+    kind: UdaType,
+    /// Allowed values, for strings and enums
+    values: Option<Vec<String>>,
+}
+
+impl Definition {
+    /// Check `value` against the definition and convert it to the string
+    /// TaskChampion stores
+    pub fn encode(&self, name: &str, value: Value) -> Result<String, Error> {
+        let invalid = |expected: &str| {
+            Error::new(
+                validation_error(),
+                format!("UDA '{}' must be {}, got {}", name, expected, value.inspect()),
+            )
+        };
+
+        let stored = match self.kind {
+            UdaType::String => RString::from_value(value)
+                .ok_or_else(|| invalid("a String"))?
+                .to_string()?,
+            UdaType::Enum => match Symbol::from_value(value) {
+                Some(symbol) => symbol.name()?.into_owned(),
+                None => RString::from_value(value)
+                    .ok_or_else(|| invalid("a String or Symbol"))?
+                    .to_string()?,
+            },
+            UdaType::Numeric => {
+                if let Some(integer) = Integer::from_value(value) {
+                    integer.to_i64()?.to_string()
+                } else if let Some(float) = Float::from_value(value) {
+                    let float = float.to_f64();
+                    if !float.is_finite() {
+                        return Err(invalid("a finite number"));
+                    }
+                    float.to_string()
+                } else {
+                    let text = RString::from_value(value)
+                        .ok_or_else(|| invalid("a number"))?
+                        .to_string()?;
+                    let text = text.trim();
+                    if !text.parse::<f64>().is_ok_and(f64::is_finite) {
+                        return Err(invalid("a number"));
+                    }
+                    text.to_string()
+                }
+            }
+            UdaType::Date => ruby_to_datetime(value)
+                .map_err(|_| invalid("a Time, Date, Integer epoch or time String"))?
+                .timestamp()
+                .to_string(),
+            UdaType::Duration => {
+                let seconds = if let Some(integer) = Integer::from_value(value) {
+                    u64::try_from(integer.to_i64()?).map_err(|_| invalid("a non-negative number of seconds"))?
+                } else {
+                    let text = RString::from_value(value)
+                        .ok_or_else(|| invalid("a number of seconds or a duration String"))?
+                        .to_string()?;
+                    recurrence::parse_duration(&text).ok_or_else(|| invalid("a duration such as 2h or PT90M"))?
+                };
+                recurrence::format_duration(seconds)
+            }
+        };
+
+        if let Some(values) = &self.values {
+            if !values.contains(&stored) {
+                return Err(invalid(&format!("one of {}", values.join(", "))));
+            }
+        }
+        Ok(stored)
+    }
+
+    /// Convert a stored value to Ruby. Values that don't fit the type, such
+    /// as those set by other clients, are returned as Strings.
+    pub fn decode(&self, stored: &str) -> Result<Value, Error> {
+        let coerced = match self.kind {
+            UdaType::String | UdaType::Enum => None,
+            UdaType::Numeric => match stored.trim().parse::<i64>() {
+                Ok(integer) => Some(integer.into_value()),
+                Err(_) => stored.trim().parse::<f64>().ok().map(IntoValue::into_value),
+            },
+            UdaType::Date => match stored.parse::<i64>().ok().and_then(|ts| DateTime::<Utc>::from_timestamp(ts, 0)) {
+                Some(datetime) => Some(datetime_to_ruby(datetime)?),
+                None => None,
+            },
+            UdaType::Duration => recurrence::parse_duration(stored).map(IntoValue::into_value),
+        };
+        Ok(coerced.unwrap_or_else(|| stored.into_value()))
+    }
+
+    fn to_h(&self) -> Result<RHash, Error> {
+        let hash = RHash::new();
+        hash.aset(Symbol::new("type"), Symbol::new(self.kind.name()))?;
+        if let Some(values) = &self.values {
+            hash.aset(Symbol::new("values"), RArray::from_vec(values.clone()))?;
+        }
+        Ok(hash)
+    }
+}
+
+/// UDA definitions, keyed by full property name (`namespace.key`).
+///
+/// A replica shares its schema with the tasks it loads, so definitions added
+/// after registering it apply straight away. Unlike most wrapped types it
+/// can be used from any thread, so task snapshots can carry it.
+#[magnus::wrap(class = "Taskchampion::UdaSchema", free_immediately)]
+#[derive(Clone, Default)]
+pub struct UdaSchema(Arc<RwLock<BTreeMap<String, Definition>>>);
+
+impl UdaSchema {
+    fn new() -> Self {
+        UdaSchema::default()
+    }
+
+    /// The definition for the UDA stored as `name`, if there is one
+    pub fn definition(&self, name: &str) -> Option<Definition> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner).get(name).cloned()
+    }
+
+    fn define(rb_self: Obj<Self>, args: &[Value]) -> Result<Obj<Self>, Error> {
+        let args = scan_args::<(Value, Value), (), (), (), RHash, ()>(args)?;
+        let (name, kind) = args.required;
+        let kwargs = get_kwargs::<_, (), (Option<Option<Vec<String>>>,), ()>(args.keywords, &[], &["values"])?;
+        let (values,) = kwargs.optional;
+        let values = values.flatten();
+
+        let name = name_to_string(name)?;
+        if name.trim().is_empty() {
+            return Err(Error::new(validation_error(), "UDA name cannot be empty or whitespace-only"));
+        }
+        let kind_name = name_to_string(kind)?;
+        let kind = UdaType::from_name(&kind_name).ok_or_else(|| {
+            Error::new(
+                validation_error(),
+                format!(
+                    "Unknown UDA type '{}'; expected string, numeric, date, duration or enum",
+                    kind_name
+                ),
+            )
+        })?;
+        match (kind, &values) {
+            (UdaType::Enum, None) => {
+                return Err(Error::new(validation_error(), format!("Enum UDA '{}' needs values:", name)));
+            }
+            (UdaType::Enum | UdaType::String, Some(values)) if values.is_empty() => {
+                return Err(Error::new(validation_error(), format!("UDA '{}' needs at least one value", name)));
+            }
+            (UdaType::Numeric | UdaType::Date | UdaType::Duration, Some(_)) => {
+                return Err(Error::new(
+                    validation_error(),
+                    format!("Only string and enum UDAs take values:, not {} UDA '{}'", kind.name(), name),
+                ));
+            }
+            _ => {}
+        }
+
+        rb_self
+            .0
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(name, Definition { kind, values });
+        Ok(rb_self)
+    }
+
+    fn get(&self, name: Value) -> Result<Option<RHash>, Error> {
+        let name = name_to_string(name)?;
+        self.definition(&name).map(|definition| definition.to_h()).transpose()
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner).keys().cloned().collect()
+    }
+
+    fn to_h(&self) -> Result<RHash, Error> {
+        let hash = RHash::new();
+        for (name, definition) in self.0.read().unwrap_or_else(PoisonError::into_inner).iter() {
+            hash.aset(name.as_str(), definition.to_h()?)?;
+        }
+        Ok(hash)
+    }
+
+    fn inspect(&self) -> String {
+        format!("#<Taskchampion::UdaSchema: {}>", self.names().join(", "))
+    }
+}
+
+/// Accept names as Strings or Symbols
+fn name_to_string(name: Value) -> Result<String, Error> {
+    if let Some(symbol) = Symbol::from_value(name) {
+        return Ok(symbol.name()?.into_owned());
+    }
+    RString::try_convert(name)?.to_string()
+}
+
+pub fn init(module: &RModule) -> Result<(), Error> {
+    let class = module.define_class("UdaSchema", class::object())?;
+
+    class.define_singleton_method("new", function!(UdaSchema::new, 0))?;
+    class.define_method("define", method!(UdaSchema::define, -1))?;
+    class.define_method("[]", method!(UdaSchema::get, 1))?;
+    class.define_method("names", method!(UdaSchema::names, 0))?;
+    class.define_method("to_h", method!(UdaSchema::to_h, 0))?;
+    class.define_method("inspect", method!(UdaSchema::inspect, 0))?;
+
+    Ok(())
+}
@@ -0,0 +1,229 @@
+use chrono::{DateTime, Utc};
+use magnus::{
+    class, function, method, prelude::*, Error, RHash, RModule, RString, Symbol, TryConvert, Value,
+};
+use magnus::scan_args::scan_args;
+use std::collections::BTreeMap;
+use taskchampion::{Tag as TCTag, TaskData as TCTaskData};
+
+use crate::error::validation_error;
+use crate::filter::{has_tag, FilterContext};
+use crate::thread_check::ThreadBound;
+
+/// Taskwarrior's default urgency settings, by their `.taskrc` names
+const DEFAULTS: &[(&str, f64)] = &[
+    ("urgency.active.coefficient", 4.0),
+    ("urgency.age.coefficient", 2.0),
+    ("urgency.age.max", 365.0),
+    ("urgency.annotations.coefficient", 1.0),
+    ("urgency.blocked.coefficient", -5.0),
+    ("urgency.blocking.coefficient", 8.0),
+    ("urgency.due.coefficient", 12.0),
+    ("urgency.project.coefficient", 1.0),
+    ("urgency.scheduled.coefficient", 5.0),
+    ("urgency.tags.coefficient", 1.0),
+    ("urgency.uda.priority.H.coefficient", 6.0),
+    ("urgency.uda.priority.L.coefficient", 1.8),
+    ("urgency.uda.priority.M.coefficient", 3.9),
+    ("urgency.user.tag.next.coefficient", 15.0),
+    ("urgency.waiting.coefficient", -3.0),
+];
+
+/// Urgency coefficients keyed by their Taskwarrior configuration names
+#[derive(Clone)]
+pub struct Coefficients(BTreeMap<String, f64>);
+
+impl Default for Coefficients {
+    fn default() -> Self {
+        Coefficients(DEFAULTS.iter().map(|(k, v)| (k.to_string(), *v)).collect())
+    }
+}
+
+impl Coefficients {
+    fn get(&self, key: &str) -> f64 {
+        self.0.get(key).copied().unwrap_or(0.0)
+    }
+
+    /// Check `key` is a setting Taskwarrior's urgency model knows about
+    fn validate_key(key: &str) -> Result<(), Error> {
+        let known = DEFAULTS.iter().any(|(k, _)| *k == key);
+        let per_item = ["urgency.user.tag.", "urgency.user.project.", "urgency.uda."]
+            .iter()
+            .any(|prefix| {
+                key.strip_prefix(prefix)
+                    .and_then(|rest| rest.strip_suffix(".coefficient"))
+                    .is_some_and(|name| !name.is_empty())
+            });
+        if known || per_item {
+            Ok(())
+        } else {
+            Err(Error::new(validation_error(), format!("Unknown urgency setting '{}'", key)))
+        }
+    }
+
+    fn set(&mut self, key: String, value: f64) -> Result<(), Error> {
+        Self::validate_key(&key)?;
+        self.0.insert(key, value);
+        Ok(())
+    }
+}
+
+/// Taskwarrior's 0.8/0.9/1.0 ramp for annotation and tag counts
+fn count_factor(count: usize) -> f64 {
+    match count {
+        0 => 0.0,
+        1 => 0.8,
+        2 => 0.9,
+        _ => 1.0,
+    }
+}
+
+fn timestamp(task: &TCTaskData, name: &str) -> Option<DateTime<Utc>> {
+    task.get(name)
+        .and_then(|s| s.parse::<i64>().ok())
+        .and_then(|secs| DateTime::from_timestamp(secs, 0))
+}
+
+/// Map due dates from two weeks ahead to one week overdue onto 0.2..1.0
+fn due_factor(task: &TCTaskData, now: DateTime<Utc>) -> f64 {
+    let Some(due) = timestamp(task, "due") else {
+        return 0.0;
+    };
+    let days_overdue = (now - due).num_seconds() as f64 / 86400.0;
+    if days_overdue >= 7.0 {
+        1.0
+    } else if days_overdue >= -14.0 {
+        ((days_overdue + 14.0) * 0.8 / 21.0) + 0.2
+    } else {
+        0.2
+    }
+}
+
+fn age_factor(task: &TCTaskData, max_days: f64, now: DateTime<Utc>) -> f64 {
+    let Some(entry) = timestamp(task, "entry") else {
+        return 1.0;
+    };
+    // Taskwarrior counts whole days
+    let age = (now - entry).num_days() as f64;
+    if max_days == 0.0 || age > max_days {
+        1.0
+    } else {
+        age / max_days
+    }
+}
+
+/// Compute a task's urgency the way Taskwarrior does. `ctx.depmap` supplies
+/// the blocked and blocking states; without one the task is neither.
+pub fn urgency(task: &TCTaskData, coefficients: &Coefficients, ctx: &FilterContext) -> f64 {
+    let flag = |present: bool| if present { 1.0 } else { 0.0 };
+    let synthetic = |name: &str| TCTag::try_from(name).is_ok_and(|tag| has_tag(task, &tag, name, ctx));
+
+    let tag_count = task.properties().filter(|p| p.starts_with("tag_")).count();
+    let annotation_count = task.properties().filter(|p| p.starts_with("annotation_")).count();
+    let scheduled = timestamp(task, "scheduled").is_some_and(|s| s < ctx.now);
+
+    let mut value = 0.0;
+    value += flag(task.has("project")) * coefficients.get("urgency.project.coefficient");
+    value += flag(task.has("start")) * coefficients.get("urgency.active.coefficient");
+    value += flag(scheduled) * coefficients.get("urgency.scheduled.coefficient");
+    value += flag(synthetic("WAITING")) * coefficients.get("urgency.waiting.coefficient");
+    value += flag(synthetic("BLOCKED")) * coefficients.get("urgency.blocked.coefficient");
+    value += count_factor(annotation_count) * coefficients.get("urgency.annotations.coefficient");
+    value += count_factor(tag_count) * coefficients.get("urgency.tags.coefficient");
+    value += flag(synthetic("BLOCKING")) * coefficients.get("urgency.blocking.coefficient");
+    value += due_factor(task, ctx.now) * coefficients.get("urgency.due.coefficient");
+    value += age_factor(task, coefficients.get("urgency.age.max"), ctx.now)
+        * coefficients.get("urgency.age.coefficient");
+
+    for (key, coefficient) in &coefficients.0 {
+        let Some(name) = key.strip_suffix(".coefficient") else {
+            continue;
+        };
+        if let Some(tag) = name.strip_prefix("urgency.user.tag.") {
+            if let Ok(tc_tag) = TCTag::try_from(tag) {
+                value += flag(has_tag(task, &tc_tag, tag, ctx)) * coefficient;
+            }
+        } else if let Some(project) = name.strip_prefix("urgency.user.project.") {
+            // Subprojects count too, as in Taskwarrior
+            value += flag(task.get("project").is_some_and(|p| p.starts_with(project))) * coefficient;
+        } else if let Some(uda) = name.strip_prefix("urgency.uda.") {
+            // `urgency.uda.<name>.coefficient` applies whenever the UDA is set,
+            // `urgency.uda.<name>.<value>.coefficient` only for that value
+            let present = match uda.split_once('.') {
+                Some((uda, expected)) => task.get(uda) == Some(expected),
+                None => task.has(uda),
+            };
+            value += flag(present) * coefficient;
+        }
+    }
+
+    value
+}
+
+#[magnus::wrap(class = "Taskchampion::UrgencyConfig", free_immediately)]
+pub struct UrgencyConfig(ThreadBound<Coefficients>);
+
+impl UrgencyConfig {
+    fn new(args: &[Value]) -> Result<Self, Error> {
+        let args = scan_args::<(), (Option<Option<RHash>>,), (), (), (), ()>(args)?;
+        let (overrides,) = args.optional;
+
+        let mut coefficients = Coefficients::default();
+        if let Some(overrides) = overrides.flatten() {
+            overrides.foreach(|key: Value, value: f64| {
+                coefficients.set(key_to_string(key)?, value)?;
+                Ok(magnus::r_hash::ForEach::Continue)
+            })?;
+        }
+        Ok(UrgencyConfig(ThreadBound::new(coefficients)))
+    }
+
+    /// A copy of the coefficients that can be used without holding a borrow
+    pub fn coefficients(&self) -> Result<Coefficients, Error> {
+        Ok(self.0.get()?.clone())
+    }
+
+    fn get(&self, key: Value) -> Result<Option<f64>, Error> {
+        let key = key_to_string(key)?;
+        Ok(self.0.get()?.0.get(&key).copied())
+    }
+
+    fn set(&self, key: Value, value: f64) -> Result<f64, Error> {
+        self.0.get_mut()?.set(key_to_string(key)?, value)?;
+        Ok(value)
+    }
+
+    fn to_h(&self) -> Result<RHash, Error> {
+        let coefficients = self.0.get()?;
+        let hash = RHash::new();
+        for (key, value) in &coefficients.0 {
+            hash.aset(key.as_str(), *value)?;
+        }
+        Ok(hash)
+    }
+
+    fn inspect(&self) -> Result<String, Error> {
+        let coefficients = self.0.get()?;
+        Ok(format!("#<Taskchampion::UrgencyConfig: {} settings>", coefficients.0.len()))
+    }
+}
+
+/// Accept setting names as Strings or Symbols
+fn key_to_string(key: Value) -> Result<String, Error> {
+    if let Some(symbol) = Symbol::from_value(key) {
+        return Ok(symbol.name()?.into_owned());
+    }
+    RString::try_convert(key)?.to_string()
+}
+
+pub fn init(module: &RModule) -> Result<(), Error> {
+    let class = module.define_class("UrgencyConfig", class::object())?;
+
+    class.define_singleton_method("new", function!(UrgencyConfig::new, -1))?;
+    class.define_method("[]", method!(UrgencyConfig::get, 1))?;
+    class.define_method("[]=", method!(UrgencyConfig::set, 2))?;
+    class.define_method("to_h", method!(UrgencyConfig::to_h, 0))?;
+    class.define_method("inspect", method!(UrgencyConfig::inspect, 0))?;
+
+    Ok(())
+}
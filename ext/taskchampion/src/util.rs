@@ -1,7 +1,12 @@
-use magnus::{Error, Value, RString, RHash, RArray, IntoValue, prelude::*};
+use magnus::{
+    function, prelude::*, rb_sys::{protect, AsRawValue, FromRawValue}, Error, Integer, IntoValue, RArray,
+    RClass, RHash, RModule, RString, Ruby, Time, Value,
+};
 use taskchampion::Uuid;
 use chrono::{DateTime, Utc};
 use std::collections::HashMap;
+use std::ffi::c_int;
+use std::sync::atomic::{AtomicBool, Ordering};
 use crate::error::validation_error;
 
 /// Convert a string from Ruby into a Rust Uuid with enhanced validation
@@ -19,59 +24,110 @@ pub fn into_error(err: taskchampion::Error) -> Error {
     crate::error::map_taskchampion_error(err)
 }
 
-/// Convert Rust DateTime<Utc> to Ruby DateTime
+/// Whether timestamps are returned as `DateTime` rather than `Time`; see
+/// `Taskchampion.time_class=`
+static RETURN_DATETIME: AtomicBool = AtomicBool::new(false);
+
+pub fn init_time_class(module: &RModule) -> Result<(), Error> {
+    module.define_module_function("time_class", function!(time_class, 0))?;
+    module.define_module_function("time_class=", function!(set_time_class, 1))?;
+    Ok(())
+}
+
+fn time_class(ruby: &Ruby) -> Result<Value, Error> {
+    if RETURN_DATETIME.load(Ordering::Relaxed) {
+        ruby.class_object().const_get("DateTime")
+    } else {
+        Ok(ruby.class_time().as_value())
+    }
+}
+
+fn set_time_class(class: Value) -> Result<Value, Error> {
+    let name = RClass::from_value(class).map(|class| unsafe { class.name() }.into_owned());
+    match name.as_deref() {
+        Some("Time") => RETURN_DATETIME.store(false, Ordering::Relaxed),
+        Some("DateTime") => RETURN_DATETIME.store(true, Ordering::Relaxed),
+        _ => {
+            return Err(Error::new(
+                magnus::exception::arg_error(),
+                format!("time_class must be Time or DateTime, got {}", class.inspect()),
+            ))
+        }
+    }
+    Ok(class)
+}
+
+/// Convert Rust DateTime<Utc> to a UTC Ruby Time, or a DateTime if
+/// `Taskchampion.time_class` says so
 pub fn datetime_to_ruby(dt: DateTime<Utc>) -> Result<Value, Error> {
-    let ruby = magnus::Ruby::get().map_err(|e| Error::new(magnus::exception::runtime_error(), e.to_string()))?;
-    let datetime_class: Value = ruby.eval("require 'date'; DateTime")?;
+    let ts = rb_sys::timespec {
+        tv_sec: dt.timestamp() as _,
+        tv_nsec: dt.timestamp_subsec_nanos() as _,
+    };
+    // An offset of INT_MAX - 1 asks for a UTC Time rather than local time
+    let time = protect(|| unsafe { rb_sys::rb_time_timespec_new(&ts, c_int::MAX - 1) })?;
+    let time = unsafe { Value::from_raw(time) };
 
-    // Convert to string and parse in Ruby (simplest approach)
-    let iso_string = dt.to_rfc3339();
-    datetime_class.funcall("parse", (iso_string,))
+    if RETURN_DATETIME.load(Ordering::Relaxed) {
+        time.funcall("to_datetime", ())
+    } else {
+        Ok(time)
+    }
 }
 
-/// Convert Ruby DateTime/Time/String to Rust DateTime<Utc> with enhanced validation
+/// Convert a Ruby Time, DateTime, Date, Integer epoch or String to Rust
+/// DateTime<Utc>, keeping sub-second precision
 pub fn ruby_to_datetime(value: Value) -> Result<DateTime<Utc>, Error> {
     // If it's a string, parse it
-    if let Ok(s) = RString::try_convert(value) {
+    if let Some(s) = RString::from_value(value) {
         let s = unsafe { s.as_str()? };
-        DateTime::parse_from_rfc3339(s)
+        return DateTime::parse_from_rfc3339(s)
             .map(|dt| dt.with_timezone(&Utc))
             .or_else(|_| DateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S %z")
                 .map(|dt| dt.with_timezone(&Utc)))
             .map_err(|_| Error::new(
                 validation_error(),
                 format!("Invalid datetime format: '{}'. Expected ISO 8601 format (e.g., '2023-01-01T12:00:00Z') or '%Y-%m-%d %H:%M:%S %z'", s)
-            ))
+            ));
+    }
+
+    // Seconds since the Unix epoch
+    if let Some(seconds) = Integer::from_value(value) {
+        let seconds = seconds.to_i64()?;
+        return DateTime::from_timestamp(seconds, 0).ok_or_else(|| Error::new(
+            validation_error(),
+            format!("Timestamp {} is out of range", seconds)
+        ));
+    }
+
+    // DateTime and Date (at local midnight, like Date#to_time) become Times
+    let time = if Time::from_value(value).is_some() {
+        value
+    } else if value.respond_to("to_time", false)? {
+        value.funcall("to_time", ())?
     } else {
-        // Check if it's a Time object first (Time doesn't have iso8601 method)
-        let class = value.class();
-        let class_name = unsafe { class.name() };
-        let iso_string = if class_name == "Time" {
-            // For Time objects, use strftime to get ISO 8601-like format
-            value.funcall::<_, (&str,), String>("strftime", ("%Y-%m-%dT%H:%M:%S%z",))?
-        } else {
-            // For DateTime objects, use iso8601 method
-            match value.funcall::<_, (), String>("iso8601", ()) {
-                Ok(s) => s,
-                Err(_) => return Err(Error::new(
-                    validation_error(),
-                    format!("Cannot convert value to datetime. Expected Time, DateTime, or String, got: {}", class_name)
-                ))
-            }
-        };
-
-        DateTime::parse_from_rfc3339(&iso_string)
-            .map(|dt| dt.with_timezone(&Utc))
-            .or_else(|_| {
-                // Try parsing the Time strftime format (%z gives +HHMM instead of +HH:MM)
-                DateTime::parse_from_str(&iso_string, "%Y-%m-%dT%H:%M:%S%z")
-                    .map(|dt| dt.with_timezone(&Utc))
-            })
-            .map_err(|_| Error::new(
-                validation_error(),
-                format!("Invalid datetime from Ruby object: '{}'. Unable to parse as ISO 8601", iso_string)
-            ))
+        return Err(Error::new(
+            validation_error(),
+            format!("Cannot convert value to datetime. Expected Time, DateTime, Date, Integer, or String, got: {}", unsafe { value.classname() })
+        ));
+    };
+    if Time::from_value(time).is_none() {
+        return Err(Error::new(
+            validation_error(),
+            format!("{}#to_time did not return a Time", unsafe { value.classname() })
+        ));
     }
+
+    let nil = Ruby::get_with(value).qnil().as_raw();
+    let mut ts = rb_sys::timespec { tv_sec: 0, tv_nsec: 0 };
+    protect(|| unsafe {
+        ts = rb_sys::rb_time_timespec(time.as_raw());
+        nil
+    })?;
+    DateTime::from_timestamp(ts.tv_sec as i64, ts.tv_nsec as u32).ok_or_else(|| Error::new(
+        validation_error(),
+        format!("Time {} is out of range", ts.tv_sec)
+    ))
 }
 
 /// Convert Option<T> to Ruby value (nil for None)
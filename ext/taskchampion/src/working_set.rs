@@ -1,38 +1,53 @@
 use magnus::{
-    class, method, prelude::*, Error, IntoValue, RModule, Value,
+    class, gc::Marker, method, module, prelude::*, typed_data::Obj, value::Opaque, DataTypeFunctions, Error,
+    IntoValue, RArray, RModule, RString, Ruby, TypedData, Value,
 };
-use std::sync::Arc;
-use taskchampion::WorkingSet as TCWorkingSet;
+use taskchampion::{Uuid, WorkingSet as TCWorkingSet};
 
+use crate::replica::Replica;
 use crate::thread_check::ThreadBound;
+use crate::util::uuid2tc;
 
-#[magnus::wrap(class = "Taskchampion::WorkingSet", free_immediately)]
-pub struct WorkingSet(ThreadBound<Arc<TCWorkingSet>>);
+/// The replica's working set as it was when read, giving pending tasks the
+/// short IDs Taskwarrior shows. Tasks are looked up through the replica the
+/// working set came from.
+#[derive(TypedData)]
+#[magnus(class = "Taskchampion::WorkingSet", free_immediately, mark)]
+pub struct WorkingSet {
+    working_set: ThreadBound<TCWorkingSet>,
+    replica: Opaque<Obj<Replica>>,
+}
+
+impl DataTypeFunctions for WorkingSet {
+    fn mark(&self, marker: &Marker) {
+        marker.mark(self.replica);
+    }
+}
 
 impl WorkingSet {
-    pub fn from_tc_working_set(tc_working_set: Arc<TCWorkingSet>) -> Self {
-        WorkingSet(ThreadBound::new(tc_working_set))
+    pub fn new(tc_working_set: TCWorkingSet, replica: Obj<Replica>) -> Self {
+        WorkingSet {
+            working_set: ThreadBound::new(tc_working_set),
+            replica: replica.into(),
+        }
     }
 
     fn largest_index(&self) -> Result<usize, Error> {
-        let working_set = self.0.get()?;
+        let working_set = self.working_set.get()?;
         Ok(working_set.largest_index())
     }
 
-    fn by_index(&self, index: usize) -> Result<Value, Error> {
-        let working_set = self.0.get()?;
-        match working_set.by_index(index) {
-            Some(uuid) => {
-                // For now, return the UUID string - we'll need to solve the task lookup problem
-                // in a different way since WorkingSet doesn't have access to the replica
-                Ok(uuid.to_string().into_value())
-            }
+    fn by_index(ruby: &Ruby, rb_self: &Self, index: usize) -> Result<Value, Error> {
+        let uuid = rb_self.working_set.get()?.by_index(index);
+        match uuid {
+            Some(uuid) => ruby.get_inner(rb_self.replica).task(uuid.to_string()),
             None => Ok(().into_value()),
         }
     }
+
     fn by_uuid(&self, uuid: String) -> Result<Value, Error> {
-        let working_set = self.0.get()?;
-        let tc_uuid = crate::util::uuid2tc(&uuid)?;
+        let working_set = self.working_set.get()?;
+        let tc_uuid = uuid2tc(&uuid)?;
 
         match working_set.by_uuid(tc_uuid) {
             Some(index) => Ok(index.into_value()),
@@ -40,18 +55,71 @@ impl WorkingSet {
         }
     }
 
-    fn renumber(&self) -> Result<(), Error> {
-        let _working_set = self.0.get()?;
-        // Note: renumber requires &mut self in TaskChampion, but WorkingSet is immutable
-        // This is a limitation we'll need to work around or document
-        Err(Error::new(
-            magnus::exception::runtime_error(),
-            "WorkingSet renumber is not implemented due to mutability constraints",
-        ))
+    /// The index of a Task (or anything else with a `uuid`, such as a
+    /// TaskSnapshot) or UUID string, or nil if it isn't in the working set
+    fn index_of(&self, task: Value) -> Result<Option<usize>, Error> {
+        let uuid: String = match RString::from_value(task) {
+            Some(uuid) => uuid.to_string()?,
+            None => task.funcall("uuid", ())?,
+        };
+        let tc_uuid = uuid2tc(&uuid)?;
+        Ok(self.working_set.get()?.by_uuid(tc_uuid))
+    }
+
+    /// Index and task for each entry, in index order. Entries whose task no
+    /// longer exists are left out.
+    fn entries(ruby: &Ruby, rb_self: &Self) -> Result<Vec<(usize, Value)>, Error> {
+        // Copied out so the block can renumber while we iterate
+        let indexed: Vec<(usize, Uuid)> = rb_self.working_set.get()?.iter().collect();
+        let uuids: Vec<Uuid> = indexed.iter().map(|(_, uuid)| *uuid).collect();
+        let tasks = ruby.get_inner(rb_self.replica).tasks_by_uuid(&uuids)?;
+
+        Ok(indexed
+            .into_iter()
+            .zip(tasks)
+            .filter_map(|((index, _), task)| Some((index, task?.into_value())))
+            .collect())
+    }
+
+    fn each(ruby: &Ruby, rb_self: Obj<Self>) -> Result<Value, Error> {
+        if !ruby.block_given() {
+            return Ok(rb_self.enumeratorize("each", ()).into_value());
+        }
+        let block = ruby.block_proc()?;
+
+        for (index, task) in Self::entries(ruby, &rb_self)? {
+            block.call::<_, Value>((index, task))?;
+        }
+        Ok(rb_self.into_value())
+    }
+
+    fn to_a(ruby: &Ruby, rb_self: &Self) -> Result<RArray, Error> {
+        let entries = Self::entries(ruby, rb_self)?;
+        let array = RArray::with_capacity(entries.len());
+        for entry in entries {
+            array.push(entry)?;
+        }
+        Ok(array)
+    }
+
+    fn size(&self) -> Result<usize, Error> {
+        Ok(self.working_set.get()?.iter().count())
+    }
+
+    /// Renumber the replica's working set, closing the gaps left by
+    /// completed and deleted tasks, and refresh this one to match
+    fn renumber(ruby: &Ruby, rb_self: &Self) -> Result<(), Error> {
+        let renumbered = ruby.get_inner(rb_self.replica).load_working_set(true)?;
+        *rb_self.working_set.get_mut()? = renumbered;
+        Ok(())
+    }
+
+    fn replica(ruby: &Ruby, rb_self: &Self) -> Obj<Replica> {
+        ruby.get_inner(rb_self.replica)
     }
 
     fn inspect(&self) -> Result<String, Error> {
-        let working_set = self.0.get()?;
+        let working_set = self.working_set.get()?;
         Ok(format!(
             "#<Taskchampion::WorkingSet: largest_index={}>",
             working_set.largest_index()
@@ -61,11 +129,17 @@ impl WorkingSet {
 
 pub fn init(module: &RModule) -> Result<(), Error> {
     let class = module.define_class("WorkingSet", class::object())?;
+    class.include_module(module::enumerable())?;
 
     class.define_method("largest_index", method!(WorkingSet::largest_index, 0))?;
     class.define_method("by_index", method!(WorkingSet::by_index, 1))?;
     class.define_method("by_uuid", method!(WorkingSet::by_uuid, 1))?;
+    class.define_method("index_of", method!(WorkingSet::index_of, 1))?;
+    class.define_method("each", method!(WorkingSet::each, 0))?;
+    class.define_method("to_a", method!(WorkingSet::to_a, 0))?;
+    class.define_method("size", method!(WorkingSet::size, 0))?;
     class.define_method("renumber", method!(WorkingSet::renumber, 0))?;
+    class.define_method("replica", method!(WorkingSet::replica, 0))?;
     class.define_method("inspect", method!(WorkingSet::inspect, 0))?;
 
     Ok(())
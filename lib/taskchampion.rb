@@ -1,5 +1,7 @@
 # frozen_string_literal: true
 
+require "delegate"
+
 require_relative "taskchampion/version"
 require_relative "taskchampion/taskchampion" # This loads the Rust extension
 
@@ -8,36 +10,7 @@ module Taskchampion
   # Replica class is defined in Rust
   # Additional Ruby-level helpers can be added here
 
-  # Override WorkingSet to add task lookup functionality
-  class WorkingSet
-    # Store replica reference for task lookup
-    attr_accessor :replica
-
-    alias_method :_original_by_index, :by_index
-
-    def by_index(index)
-      uuid_string = _original_by_index(index)
-      return nil if uuid_string.nil? || uuid_string.to_s.empty?
-
-      # If we have a replica reference, look up the actual task
-      if @replica
-        @replica.task(uuid_string)
-      else
-        uuid_string
-      end
-    end
-  end
-
-  # Override the Replica class to set the replica reference
   class Replica
-    alias_method :_original_working_set, :working_set
-
-    def working_set
-      ws = _original_working_set
-      ws.replica = self
-      ws
-    end
-
     # Ruby-style convenience methods for undo functionality
     def task_operations(uuid)
       get_task_operations(uuid)
@@ -58,6 +31,93 @@ module Taskchampion
     end
   end
 
+  # Transaction is defined in Rust; tasks it hands out are wrapped so their
+  # mutating methods use the transaction's operations
+  class Transaction
+    alias_method :_original_task, :task
+    alias_method :_original_create_task, :create_task
+
+    def task(uuid)
+      task = _original_task(uuid)
+      task && TaskProxy.new(task, self)
+    end
+
+    def create_task(uuid)
+      TaskProxy.new(_original_create_task(uuid), self)
+    end
+
+    # Forwards to a Task, appending the transaction's operations to any call
+    # that leaves them out
+    #
+    # @example
+    #   replica.transaction do |tx|
+    #     tx.task(uuid).set_description("Updated")
+    #   end
+    class TaskProxy < SimpleDelegator
+      # Task methods that take an operations argument, and its position.
+      # Methods added to Task that take operations must be listed here.
+      OPERATIONS_POSITION = {
+        set_description: 1,
+        set_status: 1,
+        set_priority: 1,
+        add_tag: 1,
+        remove_tag: 1,
+        add_annotation: 1,
+        remove_annotation: 1,
+        add_annotation_with_timestamp: 2,
+        update_annotation: 2,
+        set_due: 1,
+        set_wait: 1,
+        clear_wait: 0,
+        set_entry: 1,
+        set_modified: 1,
+        set_value: 2,
+        set_timestamp: 2,
+        set_uda: 3,
+        delete_uda: 2,
+        start: 0,
+        stop: 0,
+        add_dependency: 1,
+        remove_dependency: 1,
+        set_recurrence: 1,
+        done: 0
+      }.freeze
+
+      def initialize(task, transaction)
+        super(task)
+        @transaction = transaction
+      end
+
+      def method_missing(name, *args, **kwargs, &block)
+        task = __getobj__
+        return super unless task.respond_to?(name)
+
+        # Rust methods expect real Tasks, e.g. for add_dependency
+        args = args.map { |arg| arg.is_a?(TaskProxy) ? arg.__getobj__ : arg }
+        position = OPERATIONS_POSITION[name]
+        args = with_operations(name, position, args) if position
+        task.public_send(name, *args, **kwargs, &block)
+      end
+
+      def respond_to_missing?(name, include_private = false)
+        __getobj__.respond_to?(name, include_private)
+      end
+
+      private
+
+      # Insert the transaction's operations, unless the caller passed
+      # operations themselves
+      def with_operations(name, position, args)
+        return args.dup.insert(position, @transaction.operations) if args.length == position
+        return args if args.length == position + 1 && args[position].is_a?(Operations)
+
+        raise ArgumentError,
+              "#{name} takes #{position} argument#{"s" unless position == 1} before its operations, " \
+              "got #{args.length}; the transaction supplies the operations"
+      end
+    end
+  end
+
   # Task convenience methods
   class Task
     # Update an existing annotation's description while preserving its timestamp
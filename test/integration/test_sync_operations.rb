@@ -198,6 +198,72 @@ class TestSyncOperations < Minitest::Test
     end
   end
 
+  def test_aws_sync_validation
+    assert_raises(ArgumentError) do
+      @replica.sync_to_aws({})
+    end
+
+    error = assert_raises(ArgumentError) do
+      @replica.sync_to_aws(region: "us-east-1", bucket: "my-bucket")
+    end
+    assert_match(/encryption_secret/, error.message)
+
+    # Keys must be given as a pair
+    error = assert_raises(ArgumentError) do
+      @replica.sync_to_aws(
+        region: "us-east-1",
+        bucket: "my-bucket",
+        access_key_id: "key",
+        encryption_secret: "test-secret"
+      )
+    end
+    assert_match(/secret_access_key/, error.message)
+  end
+
+  def test_aws_sync_rejects_endpoint
+    # The endpoint comes from AWS_ENDPOINT_URL, so silently ignoring this
+    # would sync to AWS itself
+    error = assert_raises(ArgumentError) do
+      @replica.sync_to_aws(
+        region: "us-east-1",
+        bucket: "my-bucket",
+        endpoint: "http://127.0.0.1:9000",
+        encryption_secret: "test-secret"
+      )
+    end
+    assert_match(/AWS_ENDPOINT_URL/, error.message)
+  end
+
+  # Runs against an S3-compatible server such as MinIO, e.g.
+  #   AWS_ENDPOINT_URL=http://127.0.0.1:9000 \
+  #   TASKCHAMPION_TEST_S3_BUCKET=taskchampion \
+  #   TASKCHAMPION_TEST_S3_ACCESS_KEY_ID=minioadmin \
+  #   TASKCHAMPION_TEST_S3_SECRET_ACCESS_KEY=minioadmin rake test
+  def test_aws_sync_round_trip
+    skip "AWS_ENDPOINT_URL not set" unless ENV["AWS_ENDPOINT_URL"]
+
+    options = {
+      region: ENV.fetch("TASKCHAMPION_TEST_S3_REGION", "us-east-1"),
+      bucket: ENV.fetch("TASKCHAMPION_TEST_S3_BUCKET"),
+      access_key_id: ENV.fetch("TASKCHAMPION_TEST_S3_ACCESS_KEY_ID"),
+      secret_access_key: ENV.fetch("TASKCHAMPION_TEST_S3_SECRET_ACCESS_KEY"),
+      encryption_secret: "test-secret"
+    }
+
+    uuid = SecureRandom.uuid
+    task = @replica.create_task(uuid, @operations)
+    task.set_description("Task via S3", @operations)
+    @replica.commit_operations(@operations)
+
+    result = @replica.sync_to_aws(**options)
+    assert result.versions_pushed >= 1
+
+    replica2 = Taskchampion::Replica.new_in_memory
+    result = replica2.sync_to_aws(**options)
+    assert_includes result.changed_task_uuids, uuid
+    assert_equal "Task via S3", replica2.task(uuid).description
+  end
+
   def test_multiple_sync_operations
     Dir.mktmpdir do |tmpdir|
       server_dir = File.join(tmpdir, "multi_sync")
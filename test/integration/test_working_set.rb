@@ -93,26 +93,72 @@ class TestWorkingSet < Minitest::Test
   end
 
   def test_working_set_renumber
-    # Create multiple tasks
-    10.times do |i|
+    uuids = 3.times.map do |i|
       uuid = SecureRandom.uuid
       task = @replica.create_task(uuid, @operations)
       task.set_description("Task #{i}", @operations)
       task.set_status(:pending, @operations)
+      uuid
     end
     @replica.commit_operations(@operations)
 
-    # Get working set
+    # A completed task keeps its index until the working set is rebuilt
+    ops = Taskchampion::Operations.new
+    @replica.task(uuids.first).set_status(:completed, ops)
+    @replica.commit_operations(ops)
+
     working_set = @replica.working_set
+    assert_equal 3, working_set.largest_index
+    refute_nil working_set.index_of(uuids.first)
+
+    working_set.renumber
 
-    # Renumber the working set (currently not supported due to mutability constraints)
-    assert_raises(RuntimeError) do
-      working_set.renumber
+    assert_equal 2, working_set.largest_index
+    assert_nil working_set.index_of(uuids.first)
+    assert_equal [1, 2], uuids.drop(1).map { |uuid| working_set.index_of(uuid) }.sort
+    # The replica's working set is renumbered too
+    assert_equal 2, @replica.working_set.largest_index
+  end
+
+  def test_working_set_each
+    uuids = 3.times.map do |i|
+      uuid = SecureRandom.uuid
+      task = @replica.create_task(uuid, @operations)
+      task.set_description("Task #{i}", @operations)
+      task.set_status(:pending, @operations)
+      uuid
     end
+    @replica.commit_operations(@operations)
+    working_set = @replica.working_set
 
-    # Working set should still be functional
-    largest = working_set.largest_index
-    assert largest > 0
+    seen = []
+    result = working_set.each { |index, task| seen << [index, task.uuid] }
+
+    assert_same working_set, result
+    assert_equal [1, 2, 3], seen.map(&:first)
+    assert_equal uuids.sort, seen.map(&:last).sort
+    seen.each { |index, uuid| assert_equal index, working_set.by_uuid(uuid) }
+
+    assert_instance_of Enumerator, working_set.each
+    assert_equal 3, working_set.size
+    assert_equal seen, working_set.to_a.map { |index, task| [index, task.uuid] }
+    assert_equal ["Task 0", "Task 1", "Task 2"], working_set.map { |_, task| task.description }.sort
+  end
+
+  def test_working_set_index_of
+    uuid = SecureRandom.uuid
+    task = @replica.create_task(uuid, @operations)
+    task.set_description("Find me", @operations)
+    task.set_status(:pending, @operations)
+    @replica.commit_operations(@operations)
+    working_set = @replica.working_set
+    index = working_set.by_uuid(uuid)
+
+    assert_equal index, working_set.index_of(@replica.task(uuid))
+    assert_equal index, working_set.index_of(uuid)
+    assert_equal index, working_set.index_of(@replica.task(uuid).to_snapshot)
+    assert_nil working_set.index_of(SecureRandom.uuid)
+    assert_same @replica, working_set.replica
   end
 
   def test_working_set_with_completed_tasks
@@ -179,6 +225,8 @@ class TestWorkingSet < Minitest::Test
 
     # Largest index should be 0 for empty set
     assert_equal 0, working_set.largest_index
+    assert_equal 0, working_set.size
+    assert_empty working_set.to_a
 
     # by_index should return nil
     assert_nil working_set.by_index(1)
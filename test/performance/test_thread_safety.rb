@@ -275,4 +275,29 @@ class TestThreadSafety < Minitest::Test
     # Verify tasks were created
     assert_equal 50, replica.task_uuids.length
   end
+
+  def test_sync_runs_alongside_other_threads
+    Dir.mktmpdir("taskchampion-gvl") do |dir|
+      server_dir = File.join(dir, "server")
+      counter = 0
+      ticker = Thread.new { loop { counter += 1; Thread.pass } }
+
+      # Each worker owns its replica, so only the GVL is shared between them
+      workers = 4.times.map do |i|
+        Thread.new do
+          replica = Taskchampion::Replica.new_on_disk(File.join(dir, "replica#{i}"), true, :read_write)
+          ops = Taskchampion::Operations.new
+          task = replica.create_task(SecureRandom.uuid, ops)
+          task.set_description("Worker #{i}", ops)
+          replica.commit_operations(ops)
+          replica.sync_to_local(File.join(server_dir, i.to_s), false)
+          replica.all_tasks.size
+        end
+      end
+
+      assert_equal [1, 1, 1, 1], workers.map(&:value)
+      ticker.kill
+      assert counter > 0
+    end
+  end
 end
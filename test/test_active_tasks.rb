@@ -0,0 +1,85 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+class TestActiveTasks < TaskchampionTest
+  def setup
+    super
+    @replica = Taskchampion::Replica.new_in_memory
+  end
+
+  def test_start_marks_task_active
+    ops = Taskchampion::Operations.new
+    task = @replica.create_task(SecureRandom.uuid, ops)
+    task.set_description("Write report", ops)
+    task.set_status(Taskchampion::PENDING, ops)
+    task.start(ops)
+    @replica.commit_operations(ops)
+
+    retrieved = @replica.task(task.uuid)
+    assert retrieved.active?
+    refute_nil retrieved.start_time
+    assert (retrieved.start_time.to_time - Time.now).abs < 5
+  end
+
+  def test_stop_clears_start_time
+    ops = Taskchampion::Operations.new
+    task = @replica.create_task(SecureRandom.uuid, ops)
+    task.set_description("Write report", ops)
+    task.set_status(Taskchampion::PENDING, ops)
+    task.start(ops)
+    @replica.commit_operations(ops)
+
+    ops = Taskchampion::Operations.new
+    task = @replica.task(task.uuid)
+    task.stop(ops)
+    @replica.commit_operations(ops)
+
+    retrieved = @replica.task(task.uuid)
+    refute retrieved.active?
+    assert_nil retrieved.start_time
+  end
+
+  def test_start_time_nil_for_new_task
+    ops = Taskchampion::Operations.new
+    task = @replica.create_task(SecureRandom.uuid, ops)
+    assert_nil task.start_time
+  end
+
+  def test_active_tasks_returns_only_started_pending_tasks
+    ops = Taskchampion::Operations.new
+    started = @replica.create_task(SecureRandom.uuid, ops)
+    started.set_description("In progress", ops)
+    started.set_status(Taskchampion::PENDING, ops)
+    started.start(ops)
+
+    idle = @replica.create_task(SecureRandom.uuid, ops)
+    idle.set_description("Not started", ops)
+    idle.set_status(Taskchampion::PENDING, ops)
+    @replica.commit_operations(ops)
+
+    active = @replica.active_tasks
+    assert_kind_of Array, active
+    assert_equal [started.uuid], active.map(&:uuid)
+  end
+
+  def test_active_tasks_skips_tasks_completed_while_started
+    ops = Taskchampion::Operations.new
+    task = @replica.create_task(SecureRandom.uuid, ops)
+    task.set_description("Finished without stopping", ops)
+    task.set_status(Taskchampion::PENDING, ops)
+    task.start(ops)
+    @replica.commit_operations(ops)
+
+    ops = Taskchampion::Operations.new
+    @replica.task(task.uuid).set_status(:completed, ops)
+    @replica.commit_operations(ops)
+
+    assert @replica.task(task.uuid).active?
+    assert_equal [], @replica.active_tasks
+  end
+
+  def test_active_tasks_empty_replica
+    assert_equal [], @replica.active_tasks
+  end
+end
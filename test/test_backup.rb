@@ -0,0 +1,74 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+class TestBackup < TaskchampionTest
+  def setup
+    super
+    @path = File.join(@temp_dir, "tasks")
+    @replica = Taskchampion::Replica.new_on_disk(@path, true, :read_write)
+    @uuid = add_task("Back me up")
+  end
+
+  def test_backup_and_restore
+    backup = File.join(@temp_dir, "backup.sqlite3")
+    @replica.backup_to(backup)
+
+    restored = Taskchampion::Replica.restore_from(backup, into: File.join(@temp_dir, "restored"))
+
+    assert_instance_of Taskchampion::Replica, restored
+    assert_equal "Back me up", restored.task(@uuid).description
+    assert_equal @replica.num_local_operations, restored.num_local_operations
+    assert_equal @replica.num_undo_points, restored.num_undo_points
+  end
+
+  def test_backup_is_point_in_time
+    backup = File.join(@temp_dir, "backup.sqlite3")
+    @replica.backup_to(backup)
+    later = add_task("After the backup")
+
+    restored = Taskchampion::Replica.restore_from(backup, into: File.join(@temp_dir, "restored"))
+
+    assert_nil restored.task(later)
+    refute_nil @replica.task(later)
+  end
+
+  def test_backup_refuses_to_overwrite
+    backup = File.join(@temp_dir, "backup.sqlite3")
+    File.write(backup, "precious")
+
+    assert_raises(Taskchampion::StorageError) { @replica.backup_to(backup) }
+    assert_equal "precious", File.read(backup)
+  end
+
+  def test_in_memory_replica_cannot_be_backed_up
+    replica = Taskchampion::Replica.new_in_memory
+
+    assert_raises(Taskchampion::ValidationError) do
+      replica.backup_to(File.join(@temp_dir, "backup.sqlite3"))
+    end
+  end
+
+  def test_restore_refuses_existing_database
+    backup = File.join(@temp_dir, "backup.sqlite3")
+    @replica.backup_to(backup)
+
+    assert_raises(Taskchampion::StorageError) { Taskchampion::Replica.restore_from(backup, into: @path) }
+  end
+
+  def test_restore_rejects_invalid_backup
+    junk = File.join(@temp_dir, "junk.sqlite3")
+    File.write(junk, "not a database")
+
+    assert_raises(Taskchampion::StorageError) do
+      Taskchampion::Replica.restore_from(junk, into: File.join(@temp_dir, "restored"))
+    end
+    assert_raises(Taskchampion::StorageError) do
+      Taskchampion::Replica.restore_from(File.join(@temp_dir, "missing"), into: File.join(@temp_dir, "restored"))
+    end
+  end
+
+  def test_restore_requires_into
+    assert_raises(ArgumentError) { Taskchampion::Replica.restore_from("backup.sqlite3", {}) }
+  end
+end
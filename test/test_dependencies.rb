@@ -0,0 +1,222 @@
+# frozen_string_literal: true
+
+require "test_helper"
+require "json"
+
+class TestDependencies < TaskchampionTest
+  def setup
+    super
+    @replica = Taskchampion::Replica.new_in_memory
+  end
+
+  def test_add_dependency_with_uuid
+    a = @replica.task(add_task("A"))
+    b = @replica.task(add_task("B"))
+
+    ops = Taskchampion::Operations.new
+    a.add_dependency(b.uuid, ops)
+    @replica.commit_operations(ops)
+
+    assert_equal [b.uuid], @replica.task(a.uuid).dependencies
+    assert @replica.task(a.uuid).blocked?
+    assert @replica.task(b.uuid).blocking?
+  end
+
+  def test_add_dependency_with_task
+    a = @replica.task(add_task("A"))
+    b = @replica.task(add_task("B"))
+
+    ops = Taskchampion::Operations.new
+    a.add_dependency(b, ops)
+    @replica.commit_operations(ops)
+
+    assert_equal [b.uuid], @replica.task(a.uuid).dependencies
+  end
+
+  def test_remove_dependency
+    a = @replica.task(add_task("A"))
+    b = @replica.task(add_task("B"))
+
+    ops = Taskchampion::Operations.new
+    a.add_dependency(b, ops)
+    @replica.commit_operations(ops)
+
+    ops = Taskchampion::Operations.new
+    @replica.task(a.uuid).remove_dependency(b.uuid, ops)
+    @replica.commit_operations(ops)
+
+    assert_empty @replica.task(a.uuid).dependencies
+  end
+
+  def test_self_dependency_rejected
+    a = @replica.task(add_task("A"))
+    ops = Taskchampion::Operations.new
+
+    error = assert_raises(Taskchampion::ValidationError) do
+      a.add_dependency(a, ops)
+    end
+    assert_match(/cycle/, error.message)
+    assert ops.empty?
+  end
+
+  def test_cycle_rejected_with_path
+    a = @replica.task(add_task("A"))
+    b = @replica.task(add_task("B"))
+    c = @replica.task(add_task("C"))
+
+    ops = Taskchampion::Operations.new
+    a.add_dependency(b, ops)
+    b.add_dependency(c, ops)
+    @replica.commit_operations(ops)
+
+    ops = Taskchampion::Operations.new
+    error = assert_raises(Taskchampion::ValidationError) do
+      @replica.task(c.uuid).add_dependency(a.uuid, ops)
+    end
+    assert_includes error.message, [c.uuid, a.uuid, b.uuid, c.uuid].join(" -> ")
+    assert ops.empty?
+  end
+
+  def test_cycle_rejected_on_commit_for_stale_task
+    a = @replica.task(add_task("A"))
+    b = @replica.task(add_task("B"))
+    stale_b = @replica.task(b.uuid)
+    depend(a, b)
+
+    ops = Taskchampion::Operations.new
+    stale_b.add_dependency(a, ops)
+    error = assert_raises(Taskchampion::ValidationError) { @replica.commit_operations(ops) }
+
+    assert_includes error.message, [b.uuid, a.uuid, b.uuid].join(" -> ")
+    assert_empty @replica.task(b.uuid).dependencies
+  end
+
+  def test_cycle_rejected_on_commit_within_one_batch
+    a = @replica.task(add_task("A"))
+    b = @replica.task(add_task("B"))
+
+    ops = Taskchampion::Operations.new
+    a.add_dependency(b, ops)
+    b.add_dependency(a, ops)
+    error = assert_raises(Taskchampion::ValidationError) { @replica.commit_operations(ops) }
+
+    assert_match(/would create a cycle/, error.message)
+    assert_empty @replica.task(a.uuid).dependencies
+    assert_empty @replica.task(b.uuid).dependencies
+  end
+
+  def test_cycle_rejected_on_import
+    a = add_task("A")
+    b = add_task("B") { |task, ops| task.add_dependency(a, ops) }
+    exported = JSON.parse(@replica.export_json).find { |task| task["uuid"] == a }
+
+    error = assert_raises(Taskchampion::ValidationError) do
+      @replica.import_json([exported.merge("depends" => [b])].to_json)
+    end
+
+    assert_includes error.message, [a, b, a].join(" -> ")
+    assert_empty @replica.task(a).dependencies
+  end
+
+  def test_invalid_dependency_argument
+    a = @replica.task(add_task("A"))
+    ops = Taskchampion::Operations.new
+
+    assert_raises(Taskchampion::ValidationError) { a.add_dependency("not-a-uuid", ops) }
+    assert_raises(Taskchampion::ValidationError) { a.add_dependency(42, ops) }
+  end
+
+  def depend(task, *dependencies)
+    ops = Taskchampion::Operations.new
+    dependencies.each { |dep| task.add_dependency(dep, ops) }
+    @replica.commit_operations(ops)
+  end
+
+  # a depends on b and d, both of which depend on c
+  def create_diamond
+    a, b, c, d = %w[A B C D].map { |description| @replica.task(add_task(description)) }
+    depend(a, b, d)
+    depend(b, c)
+    depend(d, c)
+    [a, b, c, d]
+  end
+
+  def test_topological_order
+    a, b, c, d = create_diamond
+    e = @replica.task(add_task("E"))
+    dep_map = @replica.dependency_map(true)
+
+    assert_equal [c.uuid, d.uuid, b.uuid, a.uuid], dep_map.topological_order([a.uuid, d.uuid, b.uuid, c.uuid])
+    # Indirect dependencies count even when the tasks between are left out
+    assert_equal [e.uuid, c.uuid, a.uuid], dep_map.topological_order([a.uuid, e.uuid, c.uuid])
+  end
+
+  def test_transitive_dependencies
+    a, b, c, d = create_diamond
+    dep_map = @replica.dependency_map(true)
+
+    assert_equal [b.uuid, d.uuid].sort + [c.uuid], dep_map.transitive_dependencies(a.uuid)
+    assert_equal [c.uuid], dep_map.transitive_dependencies(d.uuid)
+    assert_empty dep_map.transitive_dependencies(c.uuid)
+  end
+
+  def test_cycles
+    a, b, c, d = create_diamond
+    assert_empty @replica.dependency_map(true).cycles
+
+    # Commits and imports reject cycles, but a sync can still bring one in:
+    # another replica makes a new task depend on A while C comes to depend on it
+    server_dir = temp_path("server")
+    Dir.mkdir(server_dir)
+    other = Taskchampion::Replica.new_in_memory
+    e = add_task("E", replica: other) { |task, ops| task.add_dependency(a.uuid, ops) }
+    depend(c, e)
+    other.sync_to_local(server_dir, false)
+    @replica.sync_to_local(server_dir, false)
+    dep_map = @replica.dependency_map(true)
+
+    assert_equal [[a.uuid, b.uuid, c.uuid, d.uuid, e].sort], dep_map.cycles
+    error = assert_raises(Taskchampion::ValidationError) { dep_map.topological_order([a.uuid]) }
+    assert_match(/Dependency cycle/, error.message)
+  end
+
+  def test_critical_path
+    a, b, c, d = create_diamond
+    ops = Taskchampion::Operations.new
+    b.set_value("estimate", "PT2H", ops)
+    c.set_value("estimate", "1800", ops)
+    d.set_value("estimate", "1h", ops)
+    @replica.commit_operations(ops)
+
+    critical = @replica.dependency_map(true).critical_path(duration_uda: :estimate)
+
+    assert_equal [c.uuid, b.uuid, a.uuid], critical[:path]
+    assert_equal 9000, critical[:duration]
+  end
+
+  def test_critical_path_invalid_duration
+    a, = create_diamond
+    ops = Taskchampion::Operations.new
+    a.set_value("estimate", "a while", ops)
+    @replica.commit_operations(ops)
+
+    assert_raises(Taskchampion::ValidationError) do
+      @replica.dependency_map(true).critical_path(duration_uda: "estimate")
+    end
+  end
+
+  def test_to_dot
+    a, b = create_diamond
+    ops = Taskchampion::Operations.new
+    a.set_description('Ship "v2"', ops)
+    @replica.commit_operations(ops)
+    add_task("Unrelated")
+
+    dot = @replica.dependency_map(true).to_dot
+
+    assert dot.start_with?("digraph dependencies {")
+    assert_includes dot, %("#{a.uuid}" [label="Ship \\"v2\\""];)
+    assert_includes dot, %("#{a.uuid}" -> "#{b.uuid}";)
+    refute_includes dot, "Unrelated"
+  end
+end
@@ -0,0 +1,100 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+class TestEachTask < TaskchampionTest
+  def setup
+    super
+    @replica = Taskchampion::Replica.new_in_memory
+  end
+
+  def add_tasks(count, status)
+    ops = Taskchampion::Operations.new
+    uuids = Array.new(count) do |i|
+      task = @replica.create_task(SecureRandom.uuid, ops)
+      task.set_description("#{status} #{i}", ops)
+      task.set_status(status, ops)
+      task.uuid
+    end
+    @replica.commit_operations(ops)
+    uuids
+  end
+
+  def test_yields_every_task
+    pending = add_tasks(3, Taskchampion::PENDING)
+    completed = add_tasks(2, Taskchampion::COMPLETED)
+
+    yielded = []
+    result = @replica.each_task { |task| yielded << task }
+
+    assert_same @replica, result
+    assert_instance_of Taskchampion::Task, yielded.first
+    assert_equal (pending + completed).sort, yielded.map(&:uuid).sort
+  end
+
+  def test_status_filter
+    pending = add_tasks(2, Taskchampion::PENDING)
+    completed = add_tasks(3, Taskchampion::COMPLETED)
+    deleted = add_tasks(1, Taskchampion::DELETED)
+
+    assert_equal pending.sort, @replica.each_task(status: :pending).map(&:uuid).sort
+    assert_equal completed.sort, @replica.each_task(status: Taskchampion::Status.completed).map(&:uuid).sort
+    assert_equal deleted, @replica.each_task(status: Taskchampion::DELETED).map(&:uuid)
+  end
+
+  def test_pending_filter_skips_tasks_completed_since_the_working_set_was_built
+    uuid, other = add_tasks(2, Taskchampion::PENDING)
+    ops = Taskchampion::Operations.new
+    @replica.task(uuid).set_status(Taskchampion::COMPLETED, ops)
+    @replica.commit_operations(ops)
+
+    assert_equal [other], @replica.each_task(status: :pending).map(&:uuid)
+    assert_equal 1, @replica.count_tasks(status: :pending)
+  end
+
+  def test_returns_lazy_enumerator_without_block
+    add_tasks(3, Taskchampion::PENDING)
+
+    enum = @replica.each_task(status: :pending)
+
+    assert_kind_of Enumerator, enum
+    assert_equal 2, enum.lazy.map(&:description).first(2).size
+    assert_equal 3, enum.count
+  end
+
+  def test_reads_across_batches
+    uuids = add_tasks(1_200, Taskchampion::PENDING)
+
+    assert_equal uuids.sort, @replica.each_task.map(&:uuid).sort
+    assert_equal 1_200, @replica.count_tasks(status: :pending)
+  end
+
+  def test_block_can_modify_replica
+    add_tasks(3, Taskchampion::PENDING)
+
+    @replica.each_task(status: :pending) do |task|
+      ops = Taskchampion::Operations.new
+      task.set_status(Taskchampion::COMPLETED, ops)
+      @replica.commit_operations(ops)
+    end
+
+    assert_equal 0, @replica.count_tasks(status: :pending)
+    assert_equal 3, @replica.count_tasks(status: :completed)
+  end
+
+  def test_count_tasks
+    add_tasks(4, Taskchampion::PENDING)
+    add_tasks(2, Taskchampion::DELETED)
+
+    assert_equal 6, @replica.count_tasks
+    assert_equal 6, @replica.count_tasks(status: nil)
+    assert_equal 4, @replica.count_tasks(status: :pending)
+    assert_equal 2, @replica.count_tasks(status: :deleted)
+    assert_equal 0, @replica.count_tasks(status: :recurring)
+  end
+
+  def test_invalid_status
+    assert_raises(Taskchampion::ValidationError) { @replica.count_tasks(status: :waiting) }
+    assert_raises(Taskchampion::ValidationError) { @replica.each_task(status: "pending") }
+  end
+end
@@ -0,0 +1,108 @@
+# frozen_string_literal: true
+
+require "test_helper"
+require "socket"
+
+class TestErrorDetails < TaskchampionTest
+  def setup
+    super
+    @replica = Taskchampion::Replica.new_in_memory
+  end
+
+  def teardown
+    @server&.close
+    @server_thread&.kill
+    super
+  end
+
+  # Answer every HTTP request with `status`
+  def start_http_server(status)
+    @server = TCPServer.new("127.0.0.1", 0)
+    @server_thread = Thread.new do
+      loop do
+        client = @server.accept
+        begin
+          client.readpartial(4096)
+          client.write("HTTP/1.1 #{status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+        rescue IOError, SystemCallError
+          # The client gave up; wait for the next one
+        ensure
+          client.close
+        end
+      end
+    end
+    "http://127.0.0.1:#{@server.addr[1]}"
+  end
+
+  def sync_error(url)
+    assert_raises(Taskchampion::SyncError) do
+      @replica.sync_to_remote(url: url, client_id: SecureRandom.uuid, encryption_secret: "secret")
+    end
+  end
+
+  def test_server_error_status
+    url = start_http_server("503 Service Unavailable")
+
+    error = sync_error(url)
+
+    assert_equal :server, error.kind
+    assert_equal 503, error.http_status
+    assert error.url.start_with?(url)
+    assert error.retryable?
+  end
+
+  def test_client_error_status_is_not_retryable
+    url = start_http_server("403 Forbidden")
+
+    error = sync_error(url)
+
+    assert_equal 403, error.http_status
+    refute error.retryable?
+  end
+
+  def test_connection_failure
+    server = TCPServer.new("127.0.0.1", 0)
+    url = "http://127.0.0.1:#{server.addr[1]}"
+    server.close
+
+    error = sync_error(url)
+
+    assert_nil error.http_status
+    assert error.url.start_with?(url)
+    assert error.retryable?
+  end
+
+  def test_causes
+    error = sync_error(start_http_server("500 Internal Server Error"))
+
+    assert_equal "Failed to synchronize with server", error.causes.first
+    assert_match(/responded with 500/, error.causes.last)
+    error.causes.each { |cause| assert_includes error.message, cause }
+  end
+
+  def test_storage_error_details
+    path = File.join(@temp_dir, "not-a-directory")
+    File.write(path, "")
+
+    error = assert_raises(Taskchampion::StorageError) { @replica.sync_to_local(path, false) }
+
+    assert_equal :other, error.kind
+    refute_empty error.causes
+    refute error.retryable?
+  end
+
+  def test_errors_raised_by_the_extension
+    error = assert_raises(Taskchampion::ValidationError) { @replica.task("not-a-uuid") }
+
+    assert_equal :usage, error.kind
+    assert_equal [error.message], error.causes
+    refute error.retryable?
+  end
+
+  def test_errors_raised_from_ruby
+    assert_equal :config, Taskchampion::ConfigError.new("bad").kind
+    assert_equal :out_of_sync, Taskchampion::OutOfSyncError.new("stale").kind
+    assert_nil Taskchampion::SyncError.new("down").http_status
+    assert_equal ["plain"], Taskchampion::Error.new("plain").causes
+  end
+end
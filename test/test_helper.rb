@@ -8,6 +8,7 @@ require "mocha/minitest"
 require "tmpdir"
 require "fileutils"
 require "securerandom"
+require "date"
 
 module Minitest
   module Assertions
@@ -36,4 +37,24 @@ class TaskchampionTest < Minitest::Test
   def temp_path(filename = nil)
     filename ? File.join(@temp_dir, filename) : @temp_dir
   end
+
+  # Create a task and commit it to `replica`, returning its UUID. The block,
+  # if given, can make further changes before the commit.
+  def add_task(description, replica: @replica, status: Taskchampion::PENDING, project: nil, tags: [],
+               priority: nil, entry: nil, due: nil, wait: nil, annotation: nil)
+    ops = Taskchampion::Operations.new
+    task = replica.create_task(SecureRandom.uuid, ops)
+    task.set_description(description, ops)
+    task.set_status(status, ops)
+    task.set_value("project", project, ops) if project
+    task.set_priority(priority, ops) if priority
+    task.set_entry(entry, ops) if entry
+    task.set_due(due, ops) if due
+    task.set_wait(wait, ops) if wait
+    task.add_annotation(annotation, ops) if annotation
+    tags.each { |tag| task.add_tag(Taskchampion::Tag.new(tag), ops) }
+    yield task, ops if block_given?
+    replica.commit_operations(ops)
+    task.uuid
+  end
 end
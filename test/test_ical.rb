@@ -0,0 +1,186 @@
+# frozen_string_literal: true
+
+require "test_helper"
+require "stringio"
+
+class TestIcal < TaskchampionTest
+  def setup
+    super
+    @replica = Taskchampion::Replica.new_in_memory
+  end
+
+  def unfold(ics)
+    ics.gsub("\r\n ", "")
+  end
+
+  def calendar(*todos)
+    (["BEGIN:VCALENDAR", "VERSION:2.0"] + todos.flat_map { |todo| ["BEGIN:VTODO", *todo, "END:VTODO"] } +
+      ["END:VCALENDAR", ""]).join("\r\n")
+  end
+
+  def test_export_maps_task_properties
+    due = Time.utc(2026, 5, 1, 17, 0, 0)
+    blocker = add_task("Gather figures")
+    uuid = add_task("Write report; draft, then final") do |task, ops|
+      task.set_due(due, ops)
+      task.set_priority("H", ops)
+      task.add_tag(Taskchampion::Tag.new("work"), ops)
+      task.add_annotation_with_timestamp(Time.utc(2026, 4, 1), "Ask Sam", ops)
+      task.add_dependency(blocker, ops)
+    end
+
+    ics = unfold(@replica.export_ical)
+
+    assert ics.start_with?("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n")
+    assert ics.end_with?("END:VCALENDAR\r\n")
+    assert_includes ics, "UID:#{uuid}\r\n"
+    assert_includes ics, "SUMMARY:Write report\\; draft\\, then final\r\n"
+    assert_includes ics, "DUE:20260501T170000Z\r\n"
+    assert_includes ics, "STATUS:NEEDS-ACTION\r\n"
+    assert_includes ics, "PRIORITY:1\r\n"
+    assert_includes ics, "CATEGORIES:work\r\n"
+    assert_includes ics, "DESCRIPTION:Ask Sam\r\n"
+    assert_includes ics, "RELATED-TO;RELTYPE=DEPENDS-ON:#{blocker}\r\n"
+  end
+
+  def test_export_statuses_and_filter
+    add_task("Open")
+    add_task("Done", status: Taskchampion::COMPLETED) do |task, ops|
+      task.set_value("end", Time.utc(2026, 2, 3).to_i.to_s, ops)
+    end
+    add_task("Dropped", status: Taskchampion::DELETED)
+
+    ics = @replica.export_ical
+    assert_includes ics, "STATUS:COMPLETED\r\nCOMPLETED:20260203T000000Z\r\n"
+    assert_includes ics, "STATUS:CANCELLED\r\n"
+
+    pending = @replica.export_ical("status:pending")
+    assert_equal 1, pending.scan("BEGIN:VTODO").size
+    assert_includes pending, "SUMMARY:Open"
+  end
+
+  def test_export_folds_long_lines
+    add_task("word " * 40)
+
+    lines = @replica.export_ical.split("\r\n")
+
+    assert(lines.all? { |line| line.bytesize <= 75 })
+    assert_includes unfold(@replica.export_ical), "SUMMARY:#{"word " * 40}"
+  end
+
+  def test_round_trip_changes_nothing
+    add_task("Round trip") do |task, ops|
+      task.set_priority("M", ops)
+      task.add_tag(Taskchampion::Tag.new("home"), ops)
+    end
+
+    assert_equal({ created: 0, updated: 0, skipped: 1 }, @replica.import_ical(@replica.export_ical))
+  end
+
+  def test_import_creates_tasks
+    result = @replica.import_ical(StringIO.new(calendar([
+      "UID:todo-1@example.com",
+      "SUMMARY:Call the plumber\\, urgently",
+      "DUE;VALUE=DATE:20260610",
+      "PRIORITY:7",
+      "CATEGORIES:home,Odd Jobs",
+      "DESCRIPTION:Leak under sink\\nBring towels",
+      "BEGIN:VALARM",
+      "ACTION:DISPLAY",
+      "END:VALARM",
+    ])))
+
+    assert_equal({ created: 1, updated: 0, skipped: 0 }, result)
+    task = @replica.all_tasks.values.first
+    assert_equal "Call the plumber, urgently", task.description
+    assert_equal Time.utc(2026, 6, 10), task.due
+    assert_equal "L", task.priority
+    assert_equal Taskchampion::PENDING, task.status
+    assert task.has_tag?(Taskchampion::Tag.new("Odd_Jobs"))
+    assert_equal ["Bring towels", "Leak under sink"], task.annotations.map(&:description).sort
+    assert_includes @replica.export_ical, "UID:todo-1@example.com\r\n"
+  end
+
+  def test_import_updates_by_uid
+    uuid = add_task("Draft") { |task, ops| task.add_tag(Taskchampion::Tag.new("old"), ops) }
+    other = add_task("Other")
+
+    result = @replica.import_ical(calendar([
+      "UID:#{uuid}",
+      "SUMMARY:Final",
+      "STATUS:COMPLETED",
+      "COMPLETED:20260301T120000Z",
+      "CATEGORIES:new",
+      "RELATED-TO;RELTYPE=DEPENDS-ON:#{other}",
+    ]))
+
+    assert_equal({ created: 0, updated: 1, skipped: 0 }, result)
+    task = @replica.task(uuid)
+    assert_equal "Final", task.description
+    assert_equal Taskchampion::COMPLETED, task.status
+    assert_equal Time.utc(2026, 3, 1, 12).to_i.to_s, @replica.task_data(uuid).get("end")
+    assert_equal ["new"], task.tags.select(&:user?).map(&:to_s)
+    assert_equal [other], task.dependencies
+  end
+
+  def test_import_matches_non_uuid_uids_again
+    todo = ["UID:abc@client", "SUMMARY:First"]
+    @replica.import_ical(calendar(todo))
+
+    result = @replica.import_ical(calendar(["UID:abc@client", "SUMMARY:Second"]))
+
+    assert_equal({ created: 0, updated: 1, skipped: 0 }, result)
+    assert_equal ["Second"], @replica.all_tasks.values.map(&:description)
+  end
+
+  def test_import_resolves_dependencies_within_the_file
+    @replica.import_ical(calendar(
+      ["UID:child@client", "SUMMARY:Child", "RELATED-TO;RELTYPE=DEPENDS-ON:parent@client"],
+      ["UID:parent@client", "SUMMARY:Parent"]
+    ))
+
+    tasks = @replica.all_tasks.values.to_h { |task| [task.description, task] }
+    assert_equal [tasks["Parent"].uuid], tasks["Child"].dependencies
+  end
+
+  def test_import_rejects_dependency_cycles
+    error = assert_raises(Taskchampion::ValidationError) do
+      @replica.import_ical(calendar(
+        ["UID:egg@client", "SUMMARY:Egg", "RELATED-TO;RELTYPE=DEPENDS-ON:chicken@client"],
+        ["UID:chicken@client", "SUMMARY:Chicken", "RELATED-TO;RELTYPE=DEPENDS-ON:egg@client"]
+      ))
+    end
+    assert_match(/would create a cycle/, error.message)
+    assert_empty @replica.task_uuids
+  end
+
+  def test_import_resolves_tzid
+    @replica.import_ical(calendar(
+      ["UID:winter", "SUMMARY:Winter", "DUE;TZID=Europe/Berlin:20260301T090000"],
+      ["UID:summer", "SUMMARY:Summer", "DUE;TZID=\"America/New_York\":20260701T090000"],
+      ["UID:utc", "SUMMARY:UTC", "DUE;TZID=Europe/Berlin:20260301T090000Z"]
+    ))
+
+    tasks = @replica.all_tasks.values.to_h { |task| [task.description, task] }
+    assert_equal Time.utc(2026, 3, 1, 8), tasks["Winter"].due
+    assert_equal Time.utc(2026, 7, 1, 13), tasks["Summer"].due
+    assert_equal Time.utc(2026, 3, 1, 9), tasks["UTC"].due
+  end
+
+  def test_import_rejects_unknown_tzid
+    error = assert_raises(Taskchampion::ValidationError) do
+      @replica.import_ical(calendar(["UID:x", "DUE;TZID=W. Europe Standard Time:20260301T090000"]))
+    end
+    assert_match(/Unknown TZID 'W. Europe Standard Time'/, error.message)
+    assert_empty @replica.task_uuids
+  end
+
+  def test_invalid_input
+    assert_raises(Taskchampion::ValidationError) { @replica.import_ical("not a calendar") }
+    assert_raises(Taskchampion::ValidationError) { @replica.import_ical(calendar(["SUMMARY:No UID"])) }
+    assert_raises(Taskchampion::ValidationError) { @replica.import_ical(calendar(["UID:x", "DUE:tomorrow"])) }
+    assert_raises(Taskchampion::ValidationError) do
+      @replica.import_ical("BEGIN:VCALENDAR\r\nBEGIN:VTODO\r\nUID:x\r\nEND:VCALENDAR\r\n")
+    end
+  end
+end
@@ -0,0 +1,195 @@
+# frozen_string_literal: true
+
+require "test_helper"
+require "json"
+
+class TestOnCommit < TaskchampionTest
+  def setup
+    super
+    @replica = Taskchampion::Replica.new_in_memory
+    @notifications = []
+    @hook = @replica.on_commit { |changes| @notifications << changes }
+  end
+
+  def test_created_task
+    uuid = add_task("Buy milk")
+
+    assert_equal 1, @notifications.size
+    change = @notifications.first.first
+    assert_equal uuid, change[:uuid]
+    assert_equal :create, change[:kind]
+    assert_equal :commit, change[:source]
+    assert_equal [nil, "Buy milk"], change[:changes]["description"]
+    assert_equal [nil, "pending"], change[:changes]["status"]
+  end
+
+  def test_updated_task
+    uuid = add_task("Buy milk")
+    @notifications.clear
+
+    ops = Taskchampion::Operations.new
+    task = @replica.task(uuid)
+    task.set_description("Buy oat milk", ops)
+    task.set_priority("H", ops)
+    @replica.commit_operations(ops)
+
+    change = @notifications.first.first
+    assert_equal :update, change[:kind]
+    assert_equal ["Buy milk", "Buy oat milk"], change[:changes]["description"]
+    assert_equal [nil, "H"], change[:changes]["priority"]
+    refute change[:changes].key?("status")
+  end
+
+  def test_deleted_task
+    uuid = add_task("Buy milk")
+    @notifications.clear
+
+    ops = Taskchampion::Operations.new
+    ops.push(Taskchampion::Operation.delete(uuid, @replica.task_data(uuid).to_h))
+    @replica.commit_operations(ops)
+
+    change = @notifications.first.first
+    assert_equal :delete, change[:kind]
+    assert_equal ["Buy milk", nil], change[:changes]["description"]
+  end
+
+  def test_one_notification_per_commit
+    @replica.transaction do |tx|
+      tx.create_task(SecureRandom.uuid).set_description("First")
+      tx.create_task(SecureRandom.uuid).set_description("Second")
+    end
+
+    assert_equal 1, @notifications.size
+    assert_equal [[nil, "First"], [nil, "Second"]], @notifications.first.map { |c| c[:changes]["description"] }
+  end
+
+  def test_undo
+    uuid = SecureRandom.uuid
+    @replica.transaction { |tx| tx.create_task(uuid).set_description("Oops") }
+    @notifications.clear
+
+    assert @replica.undo!
+
+    change = @notifications.first.first
+    assert_equal uuid, change[:uuid]
+    assert_equal :delete, change[:kind]
+    assert_equal :undo, change[:source]
+    assert_equal ["Oops", nil], change[:changes]["description"]
+  end
+
+  def test_sync
+    server_dir = File.join(@temp_dir, "server")
+    other = Taskchampion::Replica.new_in_memory
+    uuid = add_task("From elsewhere", replica: other)
+    other.sync_to_local(server_dir, false)
+
+    @replica.sync_to_local(server_dir, false)
+
+    change = @notifications.first.first
+    assert_equal uuid, change[:uuid]
+    assert_equal :create, change[:kind]
+    assert_equal :sync, change[:source]
+    assert_equal [nil, "From elsewhere"], change[:changes]["description"]
+  end
+
+  def test_import_json
+    uuid = SecureRandom.uuid
+    @replica.import_json(JSON.generate([{ uuid: uuid, status: "pending", description: "Imported",
+                                          entry: "20260101T000000Z" }]))
+
+    change = @notifications.first.first
+    assert_equal uuid, change[:uuid]
+    assert_equal :create, change[:kind]
+    assert_equal :import, change[:source]
+    assert_equal [nil, "Imported"], change[:changes]["description"]
+  end
+
+  def test_import_ical
+    uuid = add_task("Draft")
+    @notifications.clear
+
+    @replica.import_ical(["BEGIN:VCALENDAR", "VERSION:2.0", "BEGIN:VTODO", "UID:#{uuid}", "SUMMARY:Final",
+                          "STATUS:NEEDS-ACTION", "END:VTODO", "END:VCALENDAR", ""].join("\r\n"))
+
+    change = @notifications.first.first
+    assert_equal uuid, change[:uuid]
+    assert_equal :update, change[:kind]
+    assert_equal :import, change[:source]
+    assert_equal ["Draft", "Final"], change[:changes]["description"]
+  end
+
+  def test_generated_recurring_instances
+    ops = Taskchampion::Operations.new
+    template = @replica.create_task(SecureRandom.uuid, ops)
+    template.set_description("Water plants", ops)
+    template.set_due(Time.utc(2026, 3, 1), ops)
+    template.set_recurrence("weekly", ops)
+    @replica.commit_operations(ops)
+    @notifications.clear
+
+    instances = @replica.generate_recurring_instances(horizon: Time.utc(2026, 3, 10))
+
+    changes = @notifications.first
+    assert_equal 1, @notifications.size
+    assert_equal instances.map(&:uuid).sort, changes.select { |c| c[:kind] == :create }.map { |c| c[:uuid] }.sort
+    assert(changes.all? { |c| c[:source] == :generate })
+    assert_includes changes.map { |c| c[:uuid] }, template.uuid
+  end
+
+  def test_expire_tasks
+    uuid = add_task("Long gone", status: Taskchampion::DELETED) do |task, ops|
+      task.set_modified(Time.now - (365 * 86_400), ops)
+    end
+    @notifications.clear
+
+    @replica.expire_tasks
+
+    change = @notifications.first.first
+    assert_equal uuid, change[:uuid]
+    assert_equal :delete, change[:kind]
+    assert_equal :expire, change[:source]
+  end
+
+  def test_not_called_without_changes
+    @replica.commit_operations(Taskchampion::Operations.new)
+    @replica.sync_to_local(File.join(@temp_dir, "server"), false)
+    @replica.import_json("[]")
+    @replica.generate_recurring_instances(horizon: Time.now)
+    @replica.expire_tasks
+
+    assert_empty @notifications
+  end
+
+  def test_hooks_can_use_the_replica
+    descriptions = []
+    @replica.on_commit do |changes|
+      descriptions.concat(changes.map { |c| @replica.task(c[:uuid])&.description })
+    end
+
+    add_task("Buy milk")
+
+    assert_equal ["Buy milk"], descriptions
+  end
+
+  def test_remove_on_commit
+    assert @replica.remove_on_commit(@hook)
+    refute @replica.remove_on_commit(@hook)
+
+    add_task("Buy milk")
+
+    assert_empty @notifications
+  end
+
+  def test_hook_errors_propagate_after_committing
+    @replica.on_commit { raise "listener failed" }
+
+    error = assert_raises(RuntimeError) { add_task("Buy milk") }
+
+    assert_equal "listener failed", error.message
+    assert_equal 1, @replica.task_uuids.size
+  end
+
+  def test_requires_block
+    assert_raises(ArgumentError) { @replica.on_commit }
+  end
+end
@@ -0,0 +1,105 @@
+# frozen_string_literal: true
+
+require "test_helper"
+require "json"
+require "stringio"
+
+class TestOperationsJson < TaskchampionTest
+  def setup
+    super
+    @uuid = SecureRandom.uuid
+    # Sub-second precision must survive the round trip
+    @timestamp = "2023-11-14T22:13:20.123456789Z"
+    @ops = Taskchampion::Operations.new
+    @ops << Taskchampion::Operation.undo_point
+    @ops << Taskchampion::Operation.create(@uuid)
+    @ops << Taskchampion::Operation.update(@uuid, "description", @timestamp, nil, "Write report")
+    @ops << Taskchampion::Operation.update(@uuid, "description", @timestamp, "Write report", "Ship report")
+    @ops << Taskchampion::Operation.delete(@uuid, { "description" => "Ship report" })
+  end
+
+  def assert_same_operations(expected, actual)
+    assert_equal expected.length, actual.length
+    expected.to_a.zip(actual.to_a).each do |a, b|
+      assert_equal a.to_json, b.to_json
+    end
+  end
+
+  def test_operation_to_json
+    json = JSON.parse(@ops[2].to_json)
+
+    assert_equal "update", json["type"]
+    assert_equal @uuid, json["uuid"]
+    assert_equal "description", json["property"]
+    assert_equal "2023-11-14T22:13:20.123456789Z", json["timestamp"]
+    assert_nil json["old_value"]
+    assert_equal "Write report", json["value"]
+  end
+
+  def test_operation_round_trip
+    @ops.each do |op|
+      copy = Taskchampion::Operation.from_json(op.to_json)
+      assert_equal op.operation_type, copy.operation_type
+      assert_equal op.to_json, copy.to_json
+    end
+  end
+
+  def test_operations_json_round_trip
+    copy = Taskchampion::Operations.from_json(@ops.to_json)
+
+    assert_same_operations @ops, copy
+    assert_equal "Write report", copy[3].old_value
+    assert_equal({ "description" => "Ship report" }, copy[4].old_task)
+  end
+
+  def test_works_with_json_generate
+    parsed = JSON.parse(JSON.generate({ "ops" => @ops }))
+
+    assert_equal 5, parsed["ops"].length
+  end
+
+  def test_ndjson_round_trip_through_string
+    ndjson = @ops.to_ndjson
+
+    assert_equal 5, ndjson.lines.length
+    assert_same_operations @ops, Taskchampion::Operations.load_ndjson(ndjson)
+  end
+
+  def test_ndjson_streams_through_io
+    io = StringIO.new
+    assert_equal 5, @ops.dump_ndjson(io)
+
+    io.rewind
+    assert_same_operations @ops, Taskchampion::Operations.load_ndjson(io)
+  end
+
+  def test_ndjson_skips_blank_lines
+    loaded = Taskchampion::Operations.load_ndjson("\n#{@ops[1].to_json}\n\n")
+
+    assert_equal 1, loaded.length
+  end
+
+  def test_invalid_input_raises_validation_error
+    assert_raises(Taskchampion::ValidationError) { Taskchampion::Operations.from_json("{}") }
+    assert_raises(Taskchampion::ValidationError) { Taskchampion::Operation.from_json('{"type":"zap"}') }
+
+    error = assert_raises(Taskchampion::ValidationError) do
+      Taskchampion::Operations.load_ndjson("#{@ops[1].to_json}\nnot json\n")
+    end
+    assert_match(/line 2/, error.message)
+  end
+
+  def test_replay_into_another_replica
+    source = Taskchampion::Replica.new_in_memory
+    ops = Taskchampion::Operations.new
+    task = source.create_task(@uuid, ops)
+    task.set_description("Replayed", ops)
+    task.set_status(Taskchampion::PENDING, ops)
+
+    target = Taskchampion::Replica.new_in_memory
+    target.commit_operations(Taskchampion::Operations.from_json(ops.to_json))
+
+    assert_equal "Replayed", target.task(@uuid).description
+    assert_equal task.modified.to_time.to_i, target.task(@uuid).modified.to_time.to_i
+  end
+end
@@ -0,0 +1,93 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+class TestQuery < TaskchampionTest
+  def setup
+    super
+    @replica = Taskchampion::Replica.new_in_memory
+  end
+
+  def descriptions(filter)
+    @replica.query(filter).map(&:description).sort
+  end
+
+  def test_empty_filter_returns_all_tasks
+    add_task("One")
+    add_task("Two", status: :completed)
+    assert_equal %w[One Two], descriptions("")
+  end
+
+  def test_attribute_and_tag_terms
+    add_task("Report", project: "work", tags: ["urgent"])
+    add_task("Slides", project: "work")
+    add_task("Groceries", project: "home", tags: ["urgent"])
+
+    assert_equal ["Report"], descriptions("project:work +urgent")
+    assert_equal ["Slides"], descriptions("project:work -urgent")
+  end
+
+  def test_project_matches_subprojects
+    add_task("Report", project: "work.reports")
+    add_task("Workshop", project: "workshop")
+
+    assert_equal ["Report"], descriptions("project:work")
+    assert_equal %w[Report Workshop], descriptions("project.startswith:work")
+  end
+
+  def test_status_filter
+    add_task("Open")
+    add_task("Done", status: :completed)
+
+    assert_equal ["Open"], descriptions("status:pending")
+    assert_equal ["Done"], descriptions("status:completed")
+    assert_equal ["Done"], descriptions("+COMPLETED")
+  end
+
+  def test_boolean_operators_and_parentheses
+    add_task("Report", project: "work", priority: "H")
+    add_task("Slides", project: "work", priority: "L")
+    add_task("Groceries", project: "home", priority: "H")
+    add_task("Taxes", project: "admin", priority: "H")
+
+    assert_equal %w[Groceries Report], descriptions("(project:work or project:home) and priority:H")
+    assert_equal %w[Groceries Taxes], descriptions("not project:work")
+    assert_equal %w[Report Taxes], descriptions("priority.above:M and not project:home")
+  end
+
+  def test_due_modifiers
+    add_task("Soon", due: Time.now - 3600)
+    add_task("Next year", due: Time.now + (400 * 86_400))
+    add_task("Someday")
+
+    assert_equal ["Soon"], descriptions("due.before:eom")
+    assert_equal ["Next year"], descriptions("due.after:eoy")
+    assert_equal ["Someday"], descriptions("due.none:")
+    assert_equal ["Next year", "Soon"], descriptions("due.any:")
+  end
+
+  def test_bare_words_search_description
+    add_task("Send invoice to ACME")
+    add_task("Call plumber")
+
+    assert_equal ["Send invoice to ACME"], descriptions("invoice")
+    assert_equal ["Send invoice to ACME"], descriptions("description.has:\"invoice to\"")
+  end
+
+  def test_returns_task_objects
+    uuid = add_task("Report", tags: ["urgent"])
+    result = @replica.query("+urgent")
+
+    assert_equal 1, result.length
+    assert_instance_of Taskchampion::Task, result.first
+    assert_equal uuid, result.first.uuid
+  end
+
+  def test_invalid_filters_raise_validation_error
+    ["(project:work", "project:work)", "and", "due.before:someday", "\"open", "+1abc"].each do |filter|
+      assert_raises(Taskchampion::ValidationError, "expected #{filter.inspect} to be rejected") do
+        @replica.query(filter)
+      end
+    end
+  end
+end
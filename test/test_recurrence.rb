@@ -0,0 +1,106 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+class TestRecurrence < TaskchampionTest
+  def setup
+    super
+    @replica = Taskchampion::Replica.new_in_memory
+    @due = Time.utc(2026, 1, 31, 9, 0, 0)
+  end
+
+  def create_template(rule, due: @due, **options)
+    ops = Taskchampion::Operations.new
+    task = @replica.create_task(SecureRandom.uuid, ops)
+    task.set_description("Pay rent", ops)
+    task.set_value("project", "home", ops)
+    task.set_due(due, ops) if due
+    task.set_recurrence(rule, ops, **options)
+    @replica.commit_operations(ops)
+    @replica.task(task.uuid)
+  end
+
+  def test_set_recurrence_makes_a_template
+    template = create_template("monthly")
+
+    assert_equal :recurring, template.status
+    assert_equal "monthly", template.value("recur")
+    assert_nil template.value("until")
+  end
+
+  def test_set_recurrence_with_until
+    template = create_template("weekly", until: Time.utc(2026, 3, 1))
+
+    assert_equal Time.utc(2026, 3, 1).to_i, template.get_timestamp("until").to_time.to_i
+  end
+
+  def test_set_recurrence_accepts_rrule
+    template = create_template("RRULE:FREQ=WEEKLY;INTERVAL=2;COUNT=3")
+
+    assert_equal "P2W", template.value("recur")
+    assert_equal (@due + 4 * 7 * 86400).to_i, template.get_timestamp("until").to_time.to_i
+  end
+
+  def test_set_recurrence_rejects_invalid_rules
+    assert_raises(Taskchampion::ValidationError) { create_template("fortnightly-ish") }
+    assert_raises(Taskchampion::ValidationError) { create_template("FREQ=WEEKLY;BYDAY=MO") }
+  end
+
+  def test_set_recurrence_requires_due
+    assert_raises(Taskchampion::ValidationError) { create_template("daily", due: nil) }
+  end
+
+  def test_generate_instances_up_to_horizon
+    template = create_template("monthly")
+
+    instances = @replica.generate_recurring_instances(horizon: Time.utc(2026, 4, 30, 23))
+
+    assert_equal 4, instances.length
+    assert instances.all?(&:pending?)
+    assert_equal ["0", "1", "2", "3"], instances.map { |t| t.value("imask") }.sort
+    assert instances.all? { |t| t.value("parent") == template.uuid }
+    assert instances.all? { |t| t.description == "Pay rent" && t.value("project") == "home" }
+
+    # Calendar months, clamped to the end of shorter months
+    dues = instances.sort_by { |t| t.value("imask") }.map { |t| t.due.to_time.utc.day }
+    assert_equal [31, 28, 31, 30], dues
+    assert_equal "----", @replica.task(template.uuid).value("mask")
+  end
+
+  def test_generate_instances_is_idempotent
+    create_template("P2W")
+    horizon = Time.utc(2026, 3, 1)
+
+    first = @replica.generate_recurring_instances(horizon: horizon)
+    second = @replica.generate_recurring_instances(horizon: horizon)
+
+    assert_equal 3, first.length
+    assert_empty second
+    assert_equal 1, @replica.generate_recurring_instances(horizon: horizon + 14 * 86400).length
+  end
+
+  def test_completed_instances_are_not_recreated
+    create_template("daily")
+    horizon = Time.utc(2026, 2, 1, 12)
+    instances = @replica.generate_recurring_instances(horizon: horizon)
+
+    ops = Taskchampion::Operations.new
+    instances.each { |t| t.done(ops) }
+    @replica.commit_operations(ops)
+    @replica.rebuild_working_set(true)
+
+    assert_empty @replica.generate_recurring_instances(horizon: horizon)
+  end
+
+  def test_generate_stops_at_until
+    create_template("daily", until: @due + 2 * 86400)
+
+    instances = @replica.generate_recurring_instances(horizon: @due + 30 * 86400)
+
+    assert_equal 3, instances.length
+  end
+
+  def test_generate_requires_horizon
+    assert_raises(ArgumentError) { @replica.generate_recurring_instances({}) }
+  end
+end
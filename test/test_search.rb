@@ -0,0 +1,169 @@
+# frozen_string_literal: true
+
+require "test_helper"
+require "json"
+
+class TestSearch < TaskchampionTest
+  def setup
+    super
+    @replica = Taskchampion::Replica.new_in_memory
+  end
+
+  def test_ranks_description_matches_first
+    in_annotation = add_task("Email the team", annotation: "mention the invoice")
+    in_description = add_task("Pay invoice")
+    add_task("Walk the dog")
+
+    results = @replica.search("invoice")
+
+    assert_equal [in_description, in_annotation], results.map(&:uuid)
+    assert_instance_of Taskchampion::Task, results.first
+  end
+
+  def test_repeated_terms_rank_higher
+    once = add_task("Invoice for March")
+    twice = add_task("Invoice: chase the unpaid invoice")
+
+    assert_equal [twice, once], @replica.search("invoice").map(&:uuid)
+  end
+
+  def test_case_folding_and_tokenisation
+    uuid = add_task("Review PR#42 (urgent!)")
+
+    assert_equal [uuid], @replica.search("REVIEW").map(&:uuid)
+    assert_equal [uuid], @replica.search("pr 42").map(&:uuid)
+    assert_equal [uuid], @replica.search("Urgent?").map(&:uuid)
+  end
+
+  def test_prefix_matching
+    uuid = add_task("Send invoices")
+
+    assert_equal [uuid], @replica.search("invoice").map(&:uuid)
+    assert_equal [uuid], @replica.search("inv").map(&:uuid)
+  end
+
+  def test_every_term_must_match
+    both = add_task("Pay the gas bill")
+    add_task("Pay rent")
+
+    assert_equal [both], @replica.search("pay bill").map(&:uuid)
+  end
+
+  def test_fields
+    in_project = add_task("Plan", project: "garden")
+    tagged = add_task("Buy seeds", tags: ["garden"])
+    add_task("Garden party", annotation: "bring chairs")
+
+    assert_equal 1, @replica.search("garden").size
+    assert_equal [in_project], @replica.search("garden", fields: [:project]).map(&:uuid)
+    assert_equal [tagged], @replica.search("garden", fields: ["tags"]).map(&:uuid)
+    assert_empty @replica.search("chairs", fields: [:description])
+  end
+
+  def test_limit
+    3.times { |i| add_task("Report #{i}") }
+
+    assert_equal 2, @replica.search("report", limit: 2).size
+    assert_equal 3, @replica.search("report", limit: nil).size
+  end
+
+  def test_empty_query
+    add_task("Anything")
+
+    assert_empty @replica.search("")
+    assert_empty @replica.search("  !! ")
+  end
+
+  def test_unknown_field
+    assert_raises(Taskchampion::ValidationError) do
+      @replica.search("x", fields: [:priority])
+    end
+  end
+
+  def test_index_follows_commits
+    first = add_task("Water the plants")
+    @replica.enable_search_index
+    assert @replica.search_index_enabled?
+
+    second = add_task("Water the lawn")
+    assert_equal [first, second].sort, @replica.search("water").map(&:uuid).sort
+
+    ops = Taskchampion::Operations.new
+    @replica.task(first).set_description("Feed the cat", ops)
+    @replica.commit_operations(ops)
+
+    assert_equal [second], @replica.search("water").map(&:uuid)
+    assert_equal [first], @replica.search("cat").map(&:uuid)
+  end
+
+  def test_index_follows_undo
+    @replica.enable_search_index
+    uuid = add_task("Temporary")
+    assert_equal [uuid], @replica.search("temporary").map(&:uuid)
+
+    assert @replica.commit_undo!(@replica.undo_operations)
+    assert_empty @replica.search("temporary")
+  end
+
+  def test_index_matches_full_scan
+    add_task("Invoice ACME", annotation: "second invoice reminder")
+    add_task("Invoice Globex")
+    add_task("Call the bank about the invoice")
+    unindexed = @replica.search("invoice").map(&:uuid)
+
+    @replica.enable_search_index
+    assert_equal unindexed, @replica.search("invoice").map(&:uuid)
+  end
+
+  def test_index_follows_import
+    @replica.enable_search_index
+    uuid = SecureRandom.uuid
+    @replica.import_json(JSON.generate([{ uuid: uuid, status: "pending", description: "Imported chore",
+                                          entry: "20240101T000000Z" }]))
+
+    assert_equal [uuid], @replica.search("chore").map(&:uuid)
+  end
+
+  def test_index_rebuilt_after_sync
+    server_dir = File.join(@temp_dir, "server")
+    other = Taskchampion::Replica.new_in_memory
+    ops = Taskchampion::Operations.new
+    task = other.create_task(SecureRandom.uuid, ops)
+    task.set_description("Synced errand", ops)
+    task.set_status(Taskchampion::PENDING, ops)
+    other.commit_operations(ops)
+    other.sync_to_local(server_dir, false)
+
+    @replica.enable_search_index
+    @replica.sync_to_local(server_dir, false)
+
+    assert_equal [task.uuid], @replica.search("errand").map(&:uuid)
+  end
+
+  def test_index_sees_changes_from_other_replica_objects
+    @replica = Taskchampion::Replica.new_on_disk(temp_path("tasks"), true, :read_write)
+    other = Taskchampion::Replica.new_on_disk(temp_path("tasks"), false, :read_write)
+    mine = add_task("Renew passport")
+    @replica.enable_search_index
+
+    theirs = add_task("Renew insurance", replica: other)
+    assert_equal [mine, theirs].sort, @replica.search("renew").map(&:uuid).sort
+
+    # The index follows this replica's own commits and still notices the other's
+    add_task("Renew library books")
+    ops = Taskchampion::Operations.new
+    other.task(mine).set_description("Post the passport form", ops)
+    other.commit_operations(ops)
+    assert_equal 2, @replica.search("renew").size
+    assert_equal [mine], @replica.search("form").map(&:uuid)
+  end
+
+  def test_disable_search_index
+    @replica.enable_search_index
+    @replica.disable_search_index
+    refute @replica.search_index_enabled?
+
+    uuid = add_task("Still searchable")
+    assert_equal [uuid], @replica.search("searchable").map(&:uuid)
+  end
+end
@@ -0,0 +1,104 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+class TestSharedReplica < TaskchampionTest
+  def setup
+    super
+    @shared = Taskchampion::SharedReplica.new_in_memory
+  end
+
+  def test_usable_from_other_threads
+    uuid = add_task("Created on main thread", replica: @shared)
+
+    description = Thread.new { @shared.task(uuid).description }.value
+
+    assert_equal "Created on main thread", description
+  end
+
+  def test_concurrent_writers
+    threads = 8.times.map do |i|
+      Thread.new { 5.times.map { |j| add_task("Task #{i}-#{j}", replica: @shared) } }
+    end
+    uuids = threads.flat_map(&:value)
+
+    assert_equal 40, @shared.task_uuids.length
+    assert_equal uuids.sort, @shared.task_uuids.sort
+    assert_equal 40, @shared.pending_tasks.length
+  end
+
+  def test_tasks_are_bound_to_calling_thread
+    uuid = add_task("Bound", replica: @shared)
+    task = @shared.task(uuid)
+
+    assert_raises(Taskchampion::ThreadError) { Thread.new { task.description }.join }
+  end
+
+  def test_snapshot_crosses_threads
+    uuid = add_task("Snapshot me", replica: @shared)
+    snapshot = @shared.snapshot(uuid)
+
+    result = Thread.new { [snapshot.uuid, snapshot.description, snapshot.status, snapshot.pending?] }.value
+
+    assert_equal [uuid, "Snapshot me", :pending, true], result
+  end
+
+  def test_snapshot_unknown_task
+    assert_nil @shared.snapshot(SecureRandom.uuid)
+  end
+
+  def test_snapshot_to_task_in_another_thread
+    uuid = add_task("Original", replica: @shared)
+    snapshot = @shared.snapshot(uuid)
+
+    Thread.new do
+      task = snapshot.to_task
+      ops = Taskchampion::Operations.new
+      task.set_description("Edited elsewhere", ops)
+      @shared.commit_operations(ops)
+    end.join
+
+    assert_equal "Edited elsewhere", @shared.task(uuid).description
+    assert_equal "Original", snapshot.description
+  end
+
+  def test_task_to_snapshot
+    replica = Taskchampion::Replica.new_in_memory
+    uuid = add_task("Plain replica", replica: replica)
+    ops = Taskchampion::Operations.new
+    task = replica.task(uuid)
+    task.add_tag(Taskchampion::Tag.new("home"), ops)
+    task.set_value("project", "house", ops)
+    replica.commit_operations(ops)
+
+    snapshot = replica.task(uuid).to_snapshot
+
+    assert_instance_of Taskchampion::TaskSnapshot, snapshot
+    assert snapshot.has_tag?(Taskchampion::Tag.new("home"))
+    assert_equal "house", snapshot.value("project")
+    assert_equal "house", snapshot.to_h["project"]
+    assert_equal snapshot, replica.task(uuid).to_snapshot
+  end
+
+  def test_sync_between_shared_replicas
+    server_dir = File.join(@temp_dir, "server")
+    uuid = add_task("Synced", replica: @shared)
+    other = Taskchampion::SharedReplica.new_in_memory
+
+    sent = Thread.new { @shared.sync_to_local(server_dir, false) }.value
+    received = other.sync_to_local(server_dir, false)
+
+    assert sent.operations_sent.positive?
+    assert_equal [uuid], received.changed_task_uuids
+    assert_equal "Synced", other.task(uuid).description
+  end
+
+  def test_on_disk
+    path = File.join(@temp_dir, "db")
+    shared = Taskchampion::SharedReplica.new_on_disk(path, true, :read_write)
+    uuid = add_task("Persisted", replica: shared)
+
+    reopened = Taskchampion::Replica.new_on_disk(path, false, :read_only)
+    assert_equal "Persisted", reopened.task(uuid).description
+  end
+end
@@ -0,0 +1,81 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+class TestSyncResult < TaskchampionTest
+  def setup
+    super
+    @server_dir = temp_path("server")
+    Dir.mkdir(@server_dir)
+    @replica = Taskchampion::Replica.new_in_memory
+  end
+
+  def test_sync_returns_sync_result
+    result = @replica.sync_to_local(@server_dir, false)
+
+    assert_instance_of Taskchampion::SyncResult, result
+    assert_equal 0, result.operations_sent
+    assert_equal [], result.changed_task_uuids
+    refute result.changed?
+  end
+
+  def test_counts_sent_operations_and_pushed_versions
+    add_task("Outgoing")
+    pending = @replica.num_local_operations
+
+    result = @replica.sync_to_local(@server_dir, false)
+
+    assert_equal pending, result.operations_sent
+    assert_equal 1, result.versions_pushed
+    assert_equal 0, result.versions_fetched
+    assert_equal 0, @replica.num_local_operations
+    # Local changes are already applied, so nothing changed during the sync
+    assert_equal [], result.changed_task_uuids
+    assert result.changed?
+  end
+
+  def test_reports_tasks_changed_by_incoming_operations
+    uuid = add_task("Incoming")
+    @replica.sync_to_local(@server_dir, false)
+
+    other = Taskchampion::Replica.new_in_memory
+    result = other.sync_to_local(@server_dir, false)
+
+    assert_equal 1, result.versions_fetched
+    assert_equal 0, result.operations_sent
+    assert_equal [uuid], result.changed_task_uuids
+
+    again = other.sync_to_local(@server_dir, false)
+    assert_equal 0, again.versions_fetched
+    assert_equal [], again.changed_task_uuids
+  end
+
+  def test_elapsed_and_snapshot
+    add_task("Timed")
+    result = @replica.sync_to_local(@server_dir, true)
+
+    assert_kind_of Float, result.elapsed
+    assert result.elapsed >= 0
+    refute result.snapshot_uploaded?
+  end
+
+  def test_to_h_and_inspect
+    add_task("Hash")
+    result = @replica.sync_to_local(@server_dir, false)
+    hash = result.to_h
+
+    assert_equal result.operations_sent, hash[:operations_sent]
+    assert_equal result.versions_fetched, hash[:versions_fetched]
+    assert_equal result.versions_pushed, hash[:versions_pushed]
+    assert_equal result.snapshot_uploaded?, hash[:snapshot_uploaded]
+    assert_equal result.changed_task_uuids, hash[:changed_task_uuids]
+    assert_kind_of Float, hash[:elapsed]
+    assert_match(/Taskchampion::SyncResult operations_sent=\d+/, result.inspect)
+  end
+
+  def test_usable_from_another_thread
+    result = @replica.sync_to_local(@server_dir, false)
+
+    assert_equal 0, Thread.new { result.operations_sent }.value
+  end
+end
@@ -92,7 +92,7 @@ class TestTask < TaskchampionTest
     # Verify the timestamp was stored and retrieved correctly
     retrieved_scheduled = retrieved_task.get_timestamp("scheduled")
     refute_nil retrieved_scheduled
-    assert_instance_of DateTime, retrieved_scheduled
+    assert_instance_of Time, retrieved_scheduled
 
     # Should be very close (within 1 second due to precision)
     time_diff = (retrieved_scheduled.to_time - scheduled_time.to_time).abs
@@ -145,14 +145,14 @@ class TestTask < TaskchampionTest
     replica.commit_operations(operations)
     retrieved_task = replica.task(uuid)
 
-    # All should be retrievable as DateTime objects
+    # All should be retrievable as Time objects
     due_custom = retrieved_task.get_timestamp("due_custom")
     wait_custom = retrieved_task.get_timestamp("wait_custom")
     end_custom = retrieved_task.get_timestamp("end_custom")
 
-    assert_instance_of DateTime, due_custom
-    assert_instance_of DateTime, wait_custom
-    assert_instance_of DateTime, end_custom
+    assert_instance_of Time, due_custom
+    assert_instance_of Time, wait_custom
+    assert_instance_of Time, end_custom
 
     # Check the ISO string was parsed correctly
     assert_equal 2024, end_custom.year
@@ -203,12 +203,12 @@ class TestTask < TaskchampionTest
     built_in_due = retrieved_task.due
     custom_due = retrieved_task.get_timestamp("custom_due")
 
-    assert_instance_of DateTime, built_in_due
-    assert_instance_of DateTime, custom_due
+    assert_instance_of Time, built_in_due
+    assert_instance_of Time, custom_due
 
     # Should also be able to get built-in date using get_timestamp
     due_via_get_timestamp = retrieved_task.get_timestamp("due")
-    assert_instance_of DateTime, due_via_get_timestamp
+    assert_instance_of Time, due_via_get_timestamp
 
     # Both methods should return the same value for built-in fields
     time_diff = (built_in_due.to_time - due_via_get_timestamp.to_time).abs
@@ -0,0 +1,98 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+class TestTaskHistory < TaskchampionTest
+  def setup
+    super
+    @replica = Taskchampion::Replica.new_in_memory
+    @uuid = SecureRandom.uuid
+    @created = Time.utc(2026, 3, 2, 9, 0, 0)
+    @edited = Time.utc(2026, 3, 5, 14, 30, 0)
+
+    # Build the log by hand so each change has a known timestamp
+    ops = Taskchampion::Operations.new
+    ops << Taskchampion::Operation.create(@uuid)
+    ops << update("description", @created, nil, "Draft report")
+    ops << update("status", @created, nil, "pending")
+    ops << update("description", @edited, "Draft report", "Final report")
+    ops << update("project", @edited, nil, "work")
+    @replica.commit_operations(ops)
+  end
+
+  def update(property, time, old_value, value)
+    Taskchampion::Operation.update(@uuid, property, time, old_value, value)
+  end
+
+  def test_task_history_groups_changes
+    history = @replica.task_history(@uuid)
+
+    assert_equal [:create, :update], history.map { |entry| entry[:kind] }
+    assert_equal @created.to_i, history[0][:timestamp].to_time.to_i
+    assert_equal @edited.to_i, history[1][:timestamp].to_time.to_i
+    assert_equal({ "description" => [nil, "Draft report"], "status" => [nil, "pending"] }, history[0][:changes])
+    assert_equal(
+      { "description" => ["Draft report", "Final report"], "project" => [nil, "work"] },
+      history[1][:changes]
+    )
+  end
+
+  def test_task_history_includes_full_properties
+    history = @replica.task_history(@uuid)
+
+    assert_equal({ "description" => "Draft report", "status" => "pending" }, history[0][:properties])
+    assert_equal "work", history[1][:properties]["project"]
+  end
+
+  def test_task_history_records_deletion
+    ops = Taskchampion::Operations.new
+    ops << Taskchampion::Operation.delete(@uuid, { "description" => "Final report" })
+    @replica.commit_operations(ops)
+
+    last = @replica.task_history(@uuid).last
+    assert_equal :delete, last[:kind]
+    assert_equal ["work", nil], last[:changes]["project"]
+    assert_empty last[:properties]
+  end
+
+  def test_task_history_for_unknown_task
+    assert_equal [], @replica.task_history(SecureRandom.uuid)
+  end
+
+  def test_task_at
+    before = @replica.task_at(@uuid, @created - 60)
+    draft = @replica.task_at(@uuid, Time.utc(2026, 3, 4))
+    final = @replica.task_at(@uuid, @edited)
+
+    assert_nil before
+    assert_instance_of Taskchampion::TaskData, draft
+    assert_equal @uuid, draft.uuid
+    assert_equal "Draft report", draft.get("description")
+    assert_nil draft.get("project")
+    assert_equal "Final report", final.get("description")
+    assert_equal "work", final.get("project")
+  end
+
+  def test_task_at_compares_sub_second_times
+    ops = Taskchampion::Operations.new
+    ops << update("project", @edited + 1.5, "work", "home")
+    @replica.commit_operations(ops)
+
+    assert_equal "work", @replica.task_at(@uuid, @edited + 1.25).get("project")
+    assert_equal "home", @replica.task_at(@uuid, @edited + 1.5).get("project")
+  end
+
+  def test_task_at_does_not_change_replica
+    @replica.task_at(@uuid, Time.utc(2026, 3, 4))
+
+    assert_equal "Final report", @replica.task(@uuid).description
+  end
+
+  def test_task_at_after_deletion
+    ops = Taskchampion::Operations.new
+    ops << Taskchampion::Operation.delete(@uuid, {})
+    @replica.commit_operations(ops)
+
+    assert_nil @replica.task_at(@uuid, Time.now)
+  end
+end
@@ -0,0 +1,266 @@
+# frozen_string_literal: true
+
+require "test_helper"
+require "rbconfig"
+require "json"
+
+class TestTaskwarriorHooks < TaskchampionTest
+  def setup
+    super
+    @hooks_dir = File.join(@temp_dir, "hooks")
+    FileUtils.mkdir_p(@hooks_dir)
+    @replica = Taskchampion::Replica.new_in_memory
+    @replica.enable_taskwarrior_hooks(@hooks_dir)
+  end
+
+  def write_hook(name, body, executable: true)
+    path = File.join(@hooks_dir, name)
+    File.write(path, "#!#{RbConfig.ruby}\nrequire \"json\"\n#{body}")
+    File.chmod(executable ? 0o755 : 0o644, path)
+    path
+  end
+
+  def modify_task(uuid, description)
+    ops = Taskchampion::Operations.new
+    @replica.task(uuid).set_description(description, ops)
+    @replica.commit_operations(ops)
+  end
+
+  def test_on_add_can_change_the_task
+    write_hook("on-add-tag", <<~RUBY)
+      task = JSON.parse($stdin.readline)
+      task["tags"] = ["inbox"]
+      task["description"] = task["description"].capitalize
+      puts task.to_json
+      puts "Tagged as inbox"
+    RUBY
+
+    uuid = add_task("buy milk")
+
+    task = @replica.task(uuid)
+    assert_equal "Buy milk", task.description
+    assert task.has_tag?(Taskchampion::Tag.new("inbox"))
+  end
+
+  def test_on_add_rejects
+    write_hook("on-add-reject", <<~RUBY)
+      $stdin.read
+      puts "Descriptions need a verb"
+      exit 1
+    RUBY
+
+    error = assert_raises(Taskchampion::HookError) { add_task("milk") }
+
+    assert_match(/on-add-reject rejected task .*: Descriptions need a verb/, error.message)
+    assert_equal :hook, error.kind
+    assert_empty @replica.task_uuids
+  end
+
+  def test_on_modify_receives_original_and_modified
+    write_hook("on-modify-log", <<~RUBY)
+      original = JSON.parse($stdin.readline)
+      modified = JSON.parse($stdin.readline)
+      modified["annotations"] = [{ "entry" => "20260101T000000Z",
+                                   "description" => "was \#{original["description"]}" }]
+      puts modified.to_json
+    RUBY
+    uuid = add_task("Draft report")
+
+    modify_task(uuid, "Final report")
+
+    task = @replica.task(uuid)
+    assert_equal "Final report", task.description
+    assert_equal ["was Draft report"], task.annotations.map(&:description)
+  end
+
+  def test_on_modify_rejects_without_committing
+    write_hook("on-modify-freeze", <<~RUBY)
+      $stdin.read
+      exit 2
+    RUBY
+    uuid = add_task("Frozen")
+
+    assert_raises(Taskchampion::HookError) { modify_task(uuid, "Thawed") }
+
+    assert_equal "Frozen", @replica.task(uuid).description
+  end
+
+  def test_hooks_run_in_name_order_and_chain
+    write_hook("on-add-b", <<~RUBY)
+      task = JSON.parse($stdin.readline)
+      task["description"] += " b"
+      puts task.to_json
+    RUBY
+    write_hook("on-add-a", <<~RUBY)
+      task = JSON.parse($stdin.readline)
+      task["description"] += " a"
+      puts task.to_json
+    RUBY
+
+    uuid = add_task("start")
+
+    assert_equal "start a b", @replica.task(uuid).description
+  end
+
+  def test_hooks_receive_arguments
+    args_file = File.join(@temp_dir, "args")
+    write_hook("on-add-args", <<~RUBY)
+      File.write(#{args_file.inspect}, ARGV.join(" "))
+      puts $stdin.readline
+    RUBY
+
+    add_task("anything")
+
+    assert_equal "api:2 command:add", File.read(args_file)
+  end
+
+  def test_non_executable_and_other_files_are_ignored
+    write_hook("on-add-disabled", "exit 1\n", executable: false)
+    write_hook("on-exit-report", "exit 1\n")
+
+    uuid = add_task("unhooked")
+
+    assert_equal "unhooked", @replica.task(uuid).description
+  end
+
+  def test_hook_must_print_the_task
+    write_hook("on-add-silent", "$stdin.read\n")
+
+    error = assert_raises(Taskchampion::HookError) { add_task("lost") }
+
+    assert_match(/must print exactly one JSON task/, error.message)
+  end
+
+  def test_hook_cannot_change_uuid
+    write_hook("on-add-swap", <<~RUBY)
+      task = JSON.parse($stdin.readline)
+      task["uuid"] = "#{SecureRandom.uuid}"
+      puts task.to_json
+    RUBY
+
+    assert_raises(Taskchampion::HookError) { add_task("swapped") }
+  end
+
+  def test_transactions_run_hooks
+    write_hook("on-add-reject", "$stdin.read\nexit 1\n")
+
+    assert_raises(Taskchampion::HookError) do
+      @replica.transaction { |tx| tx.create_task(SecureRandom.uuid).set_description("tx") }
+    end
+    assert_empty @replica.task_uuids
+  end
+
+  def test_cycles_are_rejected_before_hooks_run
+    blocker = add_task("Blocker")
+    # Loaded before the dependency below, so it can't see the cycle itself
+    stale_blocker = @replica.task(blocker)
+    blocked = add_task("Blocked") { |task, ops| task.add_dependency(blocker, ops) }
+    ran_file = File.join(@temp_dir, "ran")
+    write_hook("on-modify-touch", <<~RUBY)
+      File.write(#{ran_file.inspect}, "")
+      $stdin.readline
+      puts $stdin.readline
+    RUBY
+
+    ops = Taskchampion::Operations.new
+    stale_blocker.add_dependency(blocked, ops)
+    assert_raises(Taskchampion::ValidationError) { @replica.commit_operations(ops) }
+
+    refute File.exist?(ran_file)
+  end
+
+  def test_hooks_cannot_add_a_cycle
+    blocker = add_task("Blocker")
+    blocked = add_task("Blocked") { |task, ops| task.add_dependency(blocker, ops) }
+    write_hook("on-modify-depend", <<~RUBY)
+      $stdin.readline
+      task = JSON.parse($stdin.readline)
+      task["depends"] = [#{blocked.inspect}] if task["uuid"] == #{blocker.inspect}
+      puts task.to_json
+    RUBY
+
+    error = assert_raises(Taskchampion::ValidationError) { modify_task(blocker, "Blocker, revised") }
+
+    assert_includes error.message, [blocker, blocked, blocker].join(" -> ")
+    assert_equal "Blocker", @replica.task(blocker).description
+    assert_empty @replica.task(blocker).dependencies
+  end
+
+  def test_hooks_time_out
+    @replica.enable_taskwarrior_hooks(@hooks_dir, timeout: 0.5)
+    write_hook("on-add-stuck", "sleep 30\n")
+
+    started = Process.clock_gettime(Process::CLOCK_MONOTONIC)
+    error = assert_raises(Taskchampion::HookError) { add_task("stuck") }
+
+    assert_match(/on-add-stuck timed out after 0.5s/, error.message)
+    assert_operator Process.clock_gettime(Process::CLOCK_MONOTONIC) - started, :<, 10
+    assert_empty @replica.task_uuids
+  end
+
+  def test_invalid_timeout
+    assert_raises(Taskchampion::ValidationError) { @replica.enable_taskwarrior_hooks(@hooks_dir, timeout: 0) }
+    assert_raises(Taskchampion::ValidationError) { @replica.enable_taskwarrior_hooks(@hooks_dir, timeout: -1) }
+  end
+
+  def test_imports_run_hooks
+    write_hook("on-add-tag", <<~RUBY)
+      task = JSON.parse($stdin.readline)
+      task["tags"] = ["imported"]
+      puts task.to_json
+    RUBY
+    uuid = SecureRandom.uuid
+
+    @replica.import_json(JSON.generate([{ uuid: uuid, status: "pending", description: "From JSON",
+                                          entry: "20260101T000000Z" }]))
+
+    assert @replica.task(uuid).has_tag?(Taskchampion::Tag.new("imported"))
+  end
+
+  def test_generated_recurring_instances_run_hooks
+    write_hook("on-add-reject-instances", <<~RUBY)
+      task = JSON.parse($stdin.readline)
+      exit 1 if task["parent"]
+      puts task.to_json
+    RUBY
+    ops = Taskchampion::Operations.new
+    template = @replica.create_task(SecureRandom.uuid, ops)
+    template.set_description("Water plants", ops)
+    template.set_due(Time.utc(2026, 3, 1), ops)
+    template.set_recurrence("weekly", ops)
+    @replica.commit_operations(ops)
+
+    assert_raises(Taskchampion::HookError) do
+      @replica.generate_recurring_instances(horizon: Time.utc(2026, 3, 10))
+    end
+    assert_equal [template.uuid], @replica.task_uuids
+  end
+
+  def test_disable_and_enable
+    write_hook("on-add-reject", "$stdin.read\nexit 1\n")
+    assert_equal @hooks_dir, @replica.taskwarrior_hooks_dir
+
+    @replica.disable_taskwarrior_hooks
+    assert_nil @replica.taskwarrior_hooks_dir
+    add_task("allowed")
+
+    assert_equal 1, @replica.task_uuids.size
+  end
+
+  def test_default_directory_for_on_disk_replicas
+    taskdb = File.join(@temp_dir, "taskdb")
+    replica = Taskchampion::Replica.new_on_disk(taskdb, true, :read_write)
+    FileUtils.mkdir_p(File.join(taskdb, "hooks"))
+
+    replica.enable_taskwarrior_hooks
+
+    assert_equal File.join(taskdb, "hooks"), replica.taskwarrior_hooks_dir
+  end
+
+  def test_invalid_directory
+    assert_raises(Taskchampion::ValidationError) { Taskchampion::Replica.new_in_memory.enable_taskwarrior_hooks }
+    assert_raises(Taskchampion::ValidationError) do
+      @replica.enable_taskwarrior_hooks(File.join(@temp_dir, "missing"))
+    end
+  end
+end
@@ -0,0 +1,98 @@
+# frozen_string_literal: true
+
+require "test_helper"
+require "json"
+require "stringio"
+
+class TestTaskwarriorJson < TaskchampionTest
+  TW_EXPORT = <<~JSON
+    [{"id":1,"description":"Pay bills","entry":"20240101T120000Z","modified":"20240102T120000Z",
+      "status":"pending","uuid":"6d4d7a9a-8a0e-4c3e-9b1a-1d2c3b4a5f60","tags":["home","money"],
+      "annotations":[{"entry":"20240101T130000Z","description":"call bank"}],
+      "depends":["7e5e8b0b-9b1f-4d4f-8c2b-2e3d4c5b6a71"],"project":"home","priority":"H",
+      "due":"20240201T000000Z","estimate":"3h","urgency":12.3}]
+  JSON
+
+  def setup
+    super
+    @replica = Taskchampion::Replica.new_in_memory
+  end
+
+  def test_import_creates_tasks
+    result = @replica.import_json(TW_EXPORT)
+    assert_equal({ created: 1, updated: 0, skipped: 0 }, result)
+
+    task = @replica.task("6d4d7a9a-8a0e-4c3e-9b1a-1d2c3b4a5f60")
+    assert_equal "Pay bills", task.description
+    assert_equal "H", task.priority
+    assert_equal "home", task.value("project")
+    assert_equal "3h", task.value("estimate")
+    assert_equal %w[home money], task.tags.map(&:to_s).sort
+    assert_equal ["call bank"], task.annotations.map(&:description)
+    assert_equal ["7e5e8b0b-9b1f-4d4f-8c2b-2e3d4c5b6a71"], task.dependencies
+    assert_equal Time.utc(2024, 2, 1), task.due.to_time.utc
+  end
+
+  def test_import_accepts_io
+    result = @replica.import_json(StringIO.new(TW_EXPORT))
+    assert_equal 1, result[:created]
+  end
+
+  def test_reimport_is_skipped_and_changes_update
+    @replica.import_json(TW_EXPORT)
+    assert_equal({ created: 0, updated: 0, skipped: 1 }, @replica.import_json(TW_EXPORT))
+
+    changed = JSON.parse(TW_EXPORT)
+    changed.first["description"] = "Pay all bills"
+    changed.first.delete("estimate")
+    assert_equal({ created: 0, updated: 1, skipped: 0 }, @replica.import_json(JSON.generate(changed)))
+
+    task = @replica.task("6d4d7a9a-8a0e-4c3e-9b1a-1d2c3b4a5f60")
+    assert_equal "Pay all bills", task.description
+    assert_nil task.value("estimate")
+  end
+
+  def test_import_one_task_per_line
+    lines = "{\"description\":\"One\",\"status\":\"pending\"}\n{\"description\":\"Two\",\"status\":\"pending\"}\n"
+    assert_equal({ created: 2, updated: 0, skipped: 0 }, @replica.import_json(lines))
+    assert_equal %w[One Two], @replica.pending_tasks.map(&:description).sort
+  end
+
+  def test_export_format
+    @replica.import_json(TW_EXPORT)
+    exported = JSON.parse(@replica.export_json)
+
+    assert_equal 1, exported.length
+    task = exported.first
+    assert_equal "6d4d7a9a-8a0e-4c3e-9b1a-1d2c3b4a5f60", task["uuid"]
+    assert_equal "20240201T000000Z", task["due"]
+    assert_equal "20240101T120000Z", task["entry"]
+    assert_equal %w[home money], task["tags"].sort
+    assert_equal [{ "entry" => "20240101T130000Z", "description" => "call bank" }], task["annotations"]
+    assert_equal ["7e5e8b0b-9b1f-4d4f-8c2b-2e3d4c5b6a71"], task["depends"]
+    assert_equal "3h", task["estimate"]
+    assert_equal 1, task["id"]
+  end
+
+  def test_export_with_filter
+    @replica.import_json(TW_EXPORT)
+    @replica.import_json('{"description":"Other","status":"completed"}')
+
+    assert_equal ["Other"], JSON.parse(@replica.export_json("status:completed")).map { |t| t["description"] }
+    assert_equal 2, JSON.parse(@replica.export_json(nil)).length
+  end
+
+  def test_round_trip_between_replicas
+    @replica.import_json(TW_EXPORT)
+    other = Taskchampion::Replica.new_in_memory
+    other.import_json(@replica.export_json)
+
+    assert_equal JSON.parse(@replica.export_json), JSON.parse(other.export_json)
+  end
+
+  def test_invalid_input_raises_validation_error
+    assert_raises(Taskchampion::ValidationError) { @replica.import_json("[1, 2]") }
+    assert_raises(Taskchampion::ValidationError) { @replica.import_json("{not json") }
+    assert_raises(Taskchampion::ValidationError) { @replica.import_json('[{"uuid":"nope"}]') }
+  end
+end
@@ -0,0 +1,86 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+class TestTimeConversion < TaskchampionTest
+  def setup
+    super
+    @replica = Taskchampion::Replica.new_in_memory
+    @uuid = SecureRandom.uuid
+  end
+
+  def teardown
+    Taskchampion.time_class = Time
+    super
+  end
+
+  def task_with_due(due)
+    ops = Taskchampion::Operations.new
+    task = @replica.create_task(@uuid, ops)
+    task.set_due(due, ops)
+    @replica.commit_operations(ops)
+    @replica.task(@uuid)
+  end
+
+  def test_returns_utc_time
+    due = task_with_due(Time.at(1_700_000_000)).due
+
+    assert_instance_of Time, due
+    assert due.utc?
+    assert_equal 1_700_000_000, due.to_i
+  end
+
+  def test_keeps_nanoseconds
+    timestamp = Time.at(1_700_000_000, 123_456_789, :nsec)
+    op = Taskchampion::Operation.update(@uuid, "description", timestamp, nil, "x")
+
+    assert_equal timestamp, op.timestamp
+    assert_equal 123_456_789, op.timestamp.nsec
+  end
+
+  def test_accepts_integer_epoch
+    assert_equal Time.at(1_700_000_000), task_with_due(1_700_000_000).due
+  end
+
+  def test_accepts_date_at_local_midnight
+    date = Date.new(2025, 1, 15)
+
+    assert_equal date.to_time, task_with_due(date).due
+  end
+
+  def test_accepts_datetime_with_offset
+    datetime = DateTime.new(2025, 1, 15, 12, 30, 0, "+02:00")
+
+    assert_equal Time.utc(2025, 1, 15, 10, 30), task_with_due(datetime).due
+  end
+
+  def test_accepts_strings
+    assert_equal Time.utc(2024, 12, 31, 23, 59, 59), task_with_due("2024-12-31T23:59:59Z").due
+  end
+
+  def test_rejects_other_values
+    ops = Taskchampion::Operations.new
+    task = @replica.create_task(@uuid, ops)
+
+    assert_raises(Taskchampion::ValidationError) { task.set_due(Object.new, ops) }
+  end
+
+  def test_time_class_datetime
+    task = task_with_due(Time.utc(2025, 1, 15, 10, 30))
+
+    Taskchampion.time_class = DateTime
+    assert_equal DateTime, Taskchampion.time_class
+    assert_instance_of DateTime, task.due
+    assert_equal DateTime.new(2025, 1, 15, 10, 30), task.due
+
+    Taskchampion.time_class = Time
+    assert_equal Time, Taskchampion.time_class
+    assert_instance_of Time, task.due
+  end
+
+  def test_time_class_rejects_other_classes
+    assert_raises(ArgumentError) { Taskchampion.time_class = String }
+    assert_raises(ArgumentError) { Taskchampion.time_class = "Time" }
+    assert_equal Time, Taskchampion.time_class
+  end
+end
@@ -0,0 +1,138 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+class TestTransaction < TaskchampionTest
+  def setup
+    super
+    @replica = Taskchampion::Replica.new_in_memory
+    @uuid = SecureRandom.uuid
+  end
+
+  def test_transaction_commits_on_success
+    @replica.transaction do |tx|
+      task = tx.create_task(@uuid)
+      task.set_description("Write report")
+      task.set_status(Taskchampion::PENDING)
+      task.add_tag(Taskchampion::Tag.new("work"))
+    end
+
+    task = @replica.task(@uuid)
+    assert_equal "Write report", task.description
+    assert task.has_tag?(Taskchampion::Tag.new("work"))
+  end
+
+  def test_transaction_returns_block_value
+    result = @replica.transaction { |tx| tx.create_task(@uuid).uuid }
+
+    assert_equal @uuid, result
+  end
+
+  def test_transaction_adds_single_undo_point
+    before = @replica.num_undo_points
+    @replica.transaction do |tx|
+      tx.create_task(@uuid).set_description("First")
+      tx.create_task(SecureRandom.uuid).set_description("Second")
+    end
+
+    assert_equal before + 1, @replica.num_undo_points
+    assert @replica.undo!
+    assert_nil @replica.task(@uuid)
+  end
+
+  def test_transaction_discards_on_raise
+    error = assert_raises(RuntimeError) do
+      @replica.transaction do |tx|
+        tx.create_task(@uuid).set_description("Never saved")
+        raise "boom"
+      end
+    end
+
+    assert_equal "boom", error.message
+    assert_nil @replica.task(@uuid)
+    assert_equal 0, @replica.num_local_operations
+  end
+
+  def test_transaction_task_modifies_existing_task
+    @replica.transaction { |tx| tx.create_task(@uuid).set_description("Old") }
+
+    @replica.transaction do |tx|
+      task = tx.task(@uuid)
+      task.set_description("New")
+      task.set_value("project", "home")
+    end
+
+    task = @replica.task(@uuid)
+    assert_equal "New", task.description
+    assert_equal "home", task.value("project")
+  end
+
+  def test_transaction_task_returns_nil_for_unknown_uuid
+    @replica.transaction { |tx| assert_nil tx.task(SecureRandom.uuid) }
+  end
+
+  def test_proxy_accepts_explicit_operations
+    @replica.transaction do |tx|
+      tx.create_task(@uuid).set_description("Explicit", tx.operations)
+    end
+
+    assert_equal "Explicit", @replica.task(@uuid).description
+  end
+
+  def test_proxy_rejects_wrong_argument_counts
+    @replica.transaction do |tx|
+      task = tx.create_task(@uuid)
+
+      error = assert_raises(ArgumentError) { task.set_description }
+      assert_match(/set_description takes 1 argument before its operations, got 0/, error.message)
+      assert_raises(ArgumentError) { task.set_value("project") }
+      # Readers never get the operations, even when short of an argument
+      assert_raises(ArgumentError) { task.uda("ns") }
+    end
+  end
+
+  def test_proxy_lists_every_mutator
+    mutators = Taskchampion::Task.public_instance_methods(false).grep(/\A(set|add|remove|delete|clear|update)_/)
+
+    assert_empty mutators - Taskchampion::Transaction::TaskProxy::OPERATIONS_POSITION.keys
+  end
+
+  def test_proxy_passes_readers_through
+    @replica.transaction do |tx|
+      task = tx.create_task(@uuid)
+      task.set_description("Read me")
+
+      assert_equal "Read me", task.description
+      assert_equal @uuid, task.uuid
+      assert_respond_to task, :set_description
+    end
+  end
+
+  def test_proxy_tasks_as_dependencies
+    other = SecureRandom.uuid
+    @replica.transaction do |tx|
+      blocker = tx.create_task(other)
+      tx.create_task(@uuid).add_dependency(blocker)
+    end
+
+    assert_equal [other], @replica.task(@uuid).dependencies
+  end
+
+  def test_empty_transaction_commits_nothing
+    @replica.transaction { |tx| tx }
+
+    assert_equal 0, @replica.num_undo_points
+  end
+
+  def test_transaction_cannot_be_used_after_block
+    tx = @replica.transaction { |t| t }
+
+    refute tx.open?
+    assert_raises(Taskchampion::ValidationError) { tx.create_task(@uuid) }
+    assert_raises(Taskchampion::ValidationError) { tx.operations }
+  end
+
+  def test_transaction_requires_block
+    assert_raises(LocalJumpError) { @replica.transaction }
+  end
+end
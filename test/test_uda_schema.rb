@@ -0,0 +1,118 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+class TestUdaSchema < TaskchampionTest
+  def setup
+    super
+    @replica = Taskchampion::Replica.new_in_memory
+    @schema = Taskchampion::UdaSchema.new
+    @schema.define("app.estimate", :duration)
+              .define("app.severity", :enum, values: %w[low med high])
+              .define("app.points", :numeric)
+              .define("app.review", :date)
+              .define("app.note", :string)
+    @replica.uda_schema = @schema
+
+    ops = Taskchampion::Operations.new
+    @uuid = @replica.create_task(SecureRandom.uuid, ops).uuid
+    @replica.commit_operations(ops)
+  end
+
+  def set_uda(key, value)
+    ops = Taskchampion::Operations.new
+    @replica.task(@uuid).set_uda("app", key, value, ops)
+    @replica.commit_operations(ops)
+    @replica.task(@uuid)
+  end
+
+  def test_duration
+    assert_equal 5400, set_uda("estimate", "PT1H30M").uda("app", "estimate")
+    assert_equal 7200, set_uda("estimate", "2h").uda("app", "estimate")
+    assert_equal 90, set_uda("estimate", 90).uda("app", "estimate")
+    assert_equal "PT1M30S", @replica.task_data(@uuid).get("app.estimate")
+  end
+
+  def test_enum
+    assert_equal "med", set_uda("severity", :med).uda("app", "severity")
+
+    error = assert_raises(Taskchampion::ValidationError) { set_uda("severity", "urgent") }
+    assert_match(/one of low, med, high/, error.message)
+  end
+
+  def test_numeric
+    assert_equal 3, set_uda("points", 3).uda("app", "points")
+    assert_in_delta 2.5, set_uda("points", 2.5).uda("app", "points")
+    assert_equal 8, set_uda("points", " 8 ").uda("app", "points")
+    assert_raises(Taskchampion::ValidationError) { set_uda("points", "many") }
+  end
+
+  def test_date
+    time = Time.utc(2026, 3, 1, 12, 30)
+
+    review = set_uda("review", time).uda("app", "review")
+
+    assert_instance_of Time, review
+    assert_equal time, review
+    assert_equal time.to_i.to_s, @replica.task_data(@uuid).get("app.review")
+    assert_raises(Taskchampion::ValidationError) { set_uda("review", "someday") }
+  end
+
+  def test_string
+    assert_equal "call back", set_uda("note", "call back").uda("app", "note")
+    assert_raises(Taskchampion::ValidationError) { set_uda("note", 42) }
+  end
+
+  def test_undefined_udas_are_unchanged
+    assert_equal "anything", set_uda("other", "anything").uda("app", "other")
+    assert_raises(TypeError) { set_uda("other", 42) }
+  end
+
+  def test_stored_values_that_do_not_fit_are_returned_as_strings
+    ops = Taskchampion::Operations.new
+    @replica.task(@uuid).set_value("app.points", "lots", ops)
+    @replica.commit_operations(ops)
+
+    assert_equal "lots", @replica.task(@uuid).uda("app", "points")
+  end
+
+  def test_definitions_added_later_apply_to_loaded_tasks
+    task = set_uda("size", "12")
+    assert_equal "12", task.uda("app", "size")
+
+    @schema.define("app.size", :numeric)
+
+    assert_equal 12, task.uda("app", "size")
+  end
+
+  def test_snapshots_keep_the_schema
+    set_uda("estimate", "1h")
+    snapshot = @replica.task(@uuid).to_snapshot
+
+    assert_equal 3600, Thread.new { snapshot.to_task.uda("app", "estimate") }.value
+  end
+
+  def test_removing_the_schema
+    set_uda("estimate", "1h")
+    @replica.uda_schema = nil
+
+    assert_nil @replica.uda_schema
+    assert_equal "PT1H", @replica.task(@uuid).uda("app", "estimate")
+  end
+
+  def test_inspecting_definitions
+    assert_equal({ type: :enum, values: %w[low med high] }, @schema["app.severity"])
+    assert_equal({ type: :duration }, @schema[:"app.estimate"])
+    assert_nil @schema["app.missing"]
+    assert_equal %w[app.estimate app.note app.points app.review app.severity], @schema.names
+    assert_equal @schema.names, @replica.uda_schema.to_h.keys
+  end
+
+  def test_invalid_definitions
+    assert_raises(Taskchampion::ValidationError) { @schema.define("x", :color) }
+    assert_raises(Taskchampion::ValidationError) { @schema.define("x", :enum) }
+    assert_raises(Taskchampion::ValidationError) { @schema.define("x", :enum, values: []) }
+    assert_raises(Taskchampion::ValidationError) { @schema.define("x", :numeric, values: %w[1 2]) }
+    assert_raises(Taskchampion::ValidationError) { @schema.define(" ", :string) }
+  end
+end
@@ -0,0 +1,116 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+class TestUrgency < TaskchampionTest
+  def setup
+    super
+    @replica = Taskchampion::Replica.new_in_memory
+  end
+
+  def urgency(uuid, config = nil)
+    @replica.task(uuid).urgency(config)
+  end
+
+  def test_new_task_has_no_urgency
+    uuid = add_task("Plain", entry: Time.now)
+
+    assert_in_delta 0.0, urgency(uuid), 0.01
+  end
+
+  def test_priority_tags_and_project
+    uuid = add_task("Important", priority: "H", tags: ["next"], project: "work")
+
+    # priority.H 6.0 + tag "next" 15.0 + one tag 0.8 + project 1.0
+    assert_in_delta 22.8, urgency(uuid), 0.01
+  end
+
+  def test_due_proximity
+    overdue = add_task("Overdue", due: Time.now - 8 * 86400)
+    due_now = add_task("Due now", due: Time.now)
+    far_off = add_task("Far off", due: Time.now + 30 * 86400)
+
+    assert_in_delta 12.0, urgency(overdue), 0.01
+    assert_in_delta 12.0 * (14.0 * 0.8 / 21.0 + 0.2), urgency(due_now), 0.01
+    assert_in_delta 12.0 * 0.2, urgency(far_off), 0.01
+  end
+
+  def test_age
+    uuid = add_task("Old", entry: Time.now - 73 * 86400)
+
+    assert_in_delta 2.0 * 73 / 365.0, urgency(uuid), 0.01
+  end
+
+  def test_active_waiting_and_annotations
+    active = add_task("Active") { |t, ops| t.start(ops) }
+    waiting = add_task("Waiting", wait: Time.now + 86400)
+    annotated = add_task("Annotated") do |t, ops|
+      t.add_annotation_with_timestamp(Time.now - 10, "first", ops)
+      t.add_annotation_with_timestamp(Time.now - 5, "second", ops)
+    end
+
+    assert_in_delta 4.0, urgency(active), 0.01
+    assert_in_delta(-3.0, urgency(waiting), 0.01)
+    assert_in_delta 0.9, urgency(annotated), 0.01
+  end
+
+  def test_blocking_and_blocked
+    blocker = add_task("Blocker")
+    blocked = add_task("Blocked") { |t, ops| t.add_dependency(blocker, ops) }
+
+    assert_in_delta 8.0, urgency(blocker), 0.01
+    assert_in_delta(-5.0, urgency(blocked), 0.01)
+  end
+
+  def test_custom_coefficients
+    config = Taskchampion::UrgencyConfig.new(
+      "urgency.user.tag.next.coefficient" => 0,
+      "urgency.user.project.work.coefficient" => 2.5,
+      "urgency.uda.estimate.coefficient" => 1.5
+    )
+    uuid = add_task("Custom", tags: ["next"], project: "work.reports") do |t, ops|
+      t.set_value("estimate", "3h", ops)
+    end
+
+    # one tag 0.8 + project 1.0 + work project 2.5 + estimate UDA 1.5
+    assert_in_delta 5.8, urgency(uuid, config), 0.01
+  end
+
+  def test_urgency_config_accessors
+    config = Taskchampion::UrgencyConfig.new
+
+    assert_equal 12.0, config["urgency.due.coefficient"]
+    assert_equal 365.0, config[:"urgency.age.max"]
+    assert_nil config["urgency.user.tag.home.coefficient"]
+
+    config["urgency.due.coefficient"] = 20
+    assert_equal 20.0, config.to_h["urgency.due.coefficient"]
+  end
+
+  def test_urgency_config_rejects_unknown_settings
+    assert_raises(Taskchampion::ValidationError) do
+      Taskchampion::UrgencyConfig.new("urgency.bogus.coefficient" => 1.0)
+    end
+    assert_raises(Taskchampion::ValidationError) do
+      Taskchampion::UrgencyConfig.new["due"] = 1.0
+    end
+  end
+
+  def test_tasks_by_urgency
+    low = add_task("Low")
+    high = add_task("High", priority: "H")
+    medium = add_task("Medium", priority: "M")
+    add_task("Done", priority: "H") { |t, ops| t.done(ops) }
+
+    ranked = @replica.tasks_by_urgency
+    assert_equal [high, medium, low], ranked.map(&:uuid)
+  end
+
+  def test_tasks_by_urgency_with_config
+    high = add_task("High", priority: "H")
+    tagged = add_task("Tagged", tags: ["home"])
+
+    config = Taskchampion::UrgencyConfig.new("urgency.user.tag.home.coefficient" => 10.0)
+    assert_equal [tagged, high], @replica.tasks_by_urgency(config).map(&:uuid)
+  end
+end
@@ -0,0 +1,69 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+class TestWait < TaskchampionTest
+  def setup
+    super
+    @replica = Taskchampion::Replica.new_in_memory
+  end
+
+  def test_set_wait_snoozes_task
+    task = @replica.task(add_task("Snoozed", wait: Time.now + 3600))
+
+    assert task.waiting?
+    assert (task.wait.to_time - (Time.now + 3600)).abs < 2
+  end
+
+  def test_clear_wait
+    task = @replica.task(add_task("Snoozed", wait: Time.now + 3600))
+
+    ops = Taskchampion::Operations.new
+    task.clear_wait(ops)
+    @replica.commit_operations(ops)
+
+    retrieved = @replica.task(task.uuid)
+    refute retrieved.waiting?
+    assert_nil retrieved.wait
+  end
+
+  def test_set_wait_with_nil_clears
+    task = @replica.task(add_task("Snoozed", wait: Time.now + 3600))
+
+    ops = Taskchampion::Operations.new
+    task.set_wait(nil, ops)
+    @replica.commit_operations(ops)
+
+    assert_nil @replica.task(task.uuid).wait
+  end
+
+  def test_tasks_waking_between
+    now = Time.now
+    soon = add_task("Soon", wait: now + 600)
+    sooner = add_task("Sooner", wait: now + 60)
+    add_task("Later", wait: now + 86_400)
+    add_task("Not waiting")
+
+    waking = @replica.tasks_waking_between(now, now + 3600)
+    assert_equal [sooner, soon], waking.map(&:uuid)
+  end
+
+  def test_tasks_waking_between_skips_completed_tasks
+    now = Time.now
+    pending = add_task("Pending", wait: now + 60)
+    completed = add_task("Completed", wait: now + 120)
+    ops = Taskchampion::Operations.new
+    @replica.task(completed).set_status(Taskchampion::COMPLETED, ops)
+    @replica.commit_operations(ops)
+
+    waking = @replica.tasks_waking_between(now, now + 3600)
+    assert_equal [pending], waking.map(&:uuid)
+  end
+
+  def test_tasks_waking_between_rejects_inverted_window
+    now = Time.now
+    assert_raises(Taskchampion::ValidationError) do
+      @replica.tasks_waking_between(now, now - 60)
+    end
+  end
+end